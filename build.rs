@@ -0,0 +1,116 @@
+//! Compiles the GLSL shaders in `shaders/` to SPIR-V in `OUT_DIR`, with
+//! glslang, which the `glslang` crate builds from its C++ sources without
+//! cmake.
+//!
+//! `shader.vert` ends up as `$OUT_DIR/shader.vert.spv`, and so on. Shaders
+//! that check `#ifdef MULTIVIEW` are compiled a second time with it defined,
+//! `cube.vert` into `$OUT_DIR/cube_multiview.vert.spv`.
+//!
+//! The ray tracing stages, `.rgen`, `.rmiss` and `.rchit`, are only compiled
+//! with the `raytracing` feature, and to SPIR-V 1.4, which their pipelines
+//! take with `VK_KHR_spirv_1_4`.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use glslang::{
+    Compiler, CompilerOptions, ShaderInput, ShaderSource, ShaderStage, SpirvVersion, Target,
+    VulkanVersion,
+};
+
+const SHADER_DIR: &str = "shaders";
+
+fn shader_stage(extension: &str) -> Option<ShaderStage> {
+    match extension {
+        "vert" => Some(ShaderStage::Vertex),
+        "frag" => Some(ShaderStage::Fragment),
+        "comp" => Some(ShaderStage::Compute),
+        #[cfg(feature = "raytracing")]
+        "rgen" => Some(ShaderStage::RayGeneration),
+        #[cfg(feature = "raytracing")]
+        "rmiss" => Some(ShaderStage::Miss),
+        #[cfg(feature = "raytracing")]
+        "rchit" => Some(ShaderStage::ClosestHit),
+        _ => None,
+    }
+}
+
+/// Compiles a GLSL shader, with `MULTIVIEW` defined if `multiview`.
+fn compile_shader(
+    compiler: &Compiler,
+    path: &Path,
+    stage: ShaderStage,
+    multiview: bool,
+) -> Vec<u32> {
+    let source = fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {:?}: {}", path, error));
+    let path_str = path.to_string_lossy();
+    let source = ShaderSource::from(source);
+    // SPIR-V 1.0, so it runs on Vulkan 1.0, except for ray tracing
+    let target = match stage {
+        ShaderStage::RayGeneration | ShaderStage::Miss | ShaderStage::ClosestHit => {
+            Target::Vulkan {
+                version: VulkanVersion::Vulkan1_1,
+                spirv_version: SpirvVersion::SPIRV1_4,
+            }
+        }
+        _ => Target::Vulkan {
+            version: VulkanVersion::Vulkan1_0,
+            spirv_version: SpirvVersion::SPIRV1_0,
+        },
+    };
+    let options = CompilerOptions {
+        target,
+        ..Default::default()
+    };
+    let defines: &[(&str, Option<&str>)] = if multiview {
+        &[("MULTIVIEW", None)]
+    } else {
+        &[]
+    };
+    ShaderInput::new(&source, stage, &options, Some(defines), None)
+        .and_then(|input| compiler.create_shader(input))
+        .and_then(|shader| shader.compile())
+        .unwrap_or_else(|error| panic!("Failed to compile {}:\n{}", path_str, error))
+}
+
+/// Writes `$OUT_DIR/<file_name>.spv`.
+fn write_spv(out_dir: &str, file_name: &str, words: &[u32]) {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    fs::write(Path::new(out_dir).join(format!("{}.spv", file_name)), bytes)
+        .expect("Failed to write compiled shader");
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let compiler = Compiler::acquire().expect("Failed to initialize glslang");
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+
+    for entry in fs::read_dir(SHADER_DIR).expect("Failed to read shader directory") {
+        let path = entry.unwrap().path();
+        let stage = match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(shader_stage)
+        {
+            Some(stage) => stage,
+            None => continue,
+        };
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let words = compile_shader(compiler, &path, stage, false);
+        write_spv(&out_dir, &file_name, &words);
+        let source = fs::read_to_string(&path).unwrap();
+        if source.lines().any(|line| line.trim() == "#ifdef MULTIVIEW") {
+            let words = compile_shader(compiler, &path, stage, true);
+            let (stem, extension) = file_name.rsplit_once('.').unwrap();
+            write_spv(
+                &out_dir,
+                &format!("{}_multiview.{}", stem, extension),
+                &words,
+            );
+        }
+    }
+}
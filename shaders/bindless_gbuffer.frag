@@ -0,0 +1,34 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_nonuniform_qualifier : require
+
+// textured_gbuffer.frag, picking one of many textures rather than a layer
+// of one
+
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+layout(set = 1, binding = 0) uniform texture2D textures[];
+layout(set = 1, binding = 1) uniform sampler textureSampler;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    int textureIndex;
+} pushConstants;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 worldPosition;
+layout(location = 3) in vec2 uv;
+
+layout(location = 0) out vec4 outPosition;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outAlbedo;
+
+void main() {
+    vec3 albedo = fragColor;
+    if (pushConstants.textureIndex >= 0) {
+        albedo = texture(sampler2D(textures[pushConstants.textureIndex], textureSampler), uv).rgb;
+    }
+    outPosition = vec4(worldPosition, 1.0);
+    outNormal = vec4(normalize(cross(dFdy(worldPosition), dFdx(worldPosition))), 0.0);
+    outAlbedo = vec4(albedo * BRIGHTNESS, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// egui's font atlas, with the sampler bound on its own.
+layout(set = 0, binding = 0) uniform texture2D fontTexture;
+layout(set = 0, binding = 1) uniform sampler fontSampler;
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = fragColor * texture(sampler2D(fontTexture, fontSampler), fragUv);
+}
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Set when the pipeline is created, see VulkanApp::brightness
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 worldPosition;
+
+// The G-buffer, see deferred.rs
+layout(location = 0) out vec4 outPosition;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outAlbedo;
+
+void main() {
+    // An alpha of 0 is left where nothing was drawn
+    outPosition = vec4(worldPosition, 1.0);
+    // Flat normals from how the position changes across the screen. Y
+    // points down in framebuffer coordinates, so this order faces the camera
+    outNormal = vec4(normalize(cross(dFdy(worldPosition), dFdx(worldPosition))), 0.0);
+    outAlbedo = vec4(fragColor * BRIGHTNESS, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 worldPosition;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // How far the nearest line of the unit grid is, in pixels
+    vec2 unitsPerPixel = fwidth(worldPosition.xy);
+    vec2 distanceToLine = abs(fract(worldPosition.xy - 0.5) - 0.5) / unitsPerPixel;
+    if (min(distanceToLine.x, distanceToLine.y) > 1.0) {
+        discard;
+    }
+
+    vec3 color = vec3(0.5);
+    // The X axis in red, the Y axis in green
+    if (abs(worldPosition.y) < unitsPerPixel.y) {
+        color = vec3(0.8, 0.2, 0.2);
+    }
+    if (abs(worldPosition.x) < unitsPerPixel.x) {
+        color = vec3(0.2, 0.8, 0.2);
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,41 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+layout(location = 0) out vec3 worldPosition;
+
+// Half the width of the floor, which stays put while the model spins
+const float EXTENT = 10.0;
+// Just under the cube, which is 1 unit across
+const float HEIGHT = -0.5;
+
+vec2 corners[6] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, 1.0)
+);
+
+void main() {
+    worldPosition = vec3(corners[gl_VertexIndex] * EXTENT, HEIGHT);
+    gl_Position = ubo.proj * VIEW * vec4(worldPosition, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Maps imgui's coordinates, in logical pixels from the top left, to clip
+// space. See overlay::Transform.
+layout(push_constant) uniform Transform {
+    vec2 scale;
+    vec2 translate;
+} transform;
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec2 inUv;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+
+void main() {
+    fragUv = inUv;
+    // imgui's colors are sRGB, and the swapchain image encodes what we
+    // write as sRGB again
+    fragColor = vec4(pow(inColor.rgb, vec3(2.2)), inColor.a);
+    gl_Position = vec4(inPosition * transform.scale + transform.translate, 0.0, 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The G-buffer as input attachments
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput gPosition;
+layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput gNormal;
+layout(input_attachment_index = 2, set = 0, binding = 2) uniform subpassInput gAlbedo;
+
+layout(location = 0) out vec4 outColor;
+
+// Above the scene and a little off to the side, with Z pointing up
+const vec3 LIGHT_POSITION = vec3(2.0, -2.0, 3.0);
+const float AMBIENT = 0.2;
+
+void main() {
+    vec4 position = subpassLoad(gPosition);
+    if (position.a == 0.0) {
+        // Keep the clear color
+        discard;
+    }
+    vec3 normal = subpassLoad(gNormal).xyz;
+    vec4 albedo = subpassLoad(gAlbedo);
+    vec3 toLight = normalize(LIGHT_POSITION - position.xyz);
+    float diffuse = max(dot(normal, toLight), 0.0);
+    outColor = vec4(albedo.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), albedo.a);
+}
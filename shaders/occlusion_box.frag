@@ -0,0 +1,8 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Writes nothing, the occlusion query only counts the samples that pass
+// the depth test
+
+void main() {
+}
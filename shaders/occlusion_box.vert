@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The box around a scene object, which --occlusion-culling draws inside an
+// occlusion query to tell if any of the object could be seen, see
+// occlusion.rs. It's moved like textured.vert moves the object.
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+layout(set = 0, binding = 1) uniform ObjectUniforms {
+    mat4 model;
+} object;
+
+// The object's transform, times what fits the cube around its mesh
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+} pushConstants;
+
+vec3 corners[8] = vec3[](
+    vec3(-1.0, -1.0, -1.0),
+    vec3(1.0, -1.0, -1.0),
+    vec3(-1.0, 1.0, -1.0),
+    vec3(1.0, 1.0, -1.0),
+    vec3(-1.0, -1.0, 1.0),
+    vec3(1.0, -1.0, 1.0),
+    vec3(-1.0, 1.0, 1.0),
+    vec3(1.0, 1.0, 1.0)
+);
+
+// Culling is off, so the winding doesn't matter
+int indices[36] = int[](
+    0, 2, 1, 1, 2, 3,
+    4, 5, 6, 5, 7, 6,
+    0, 1, 4, 1, 5, 4,
+    2, 6, 3, 3, 6, 7,
+    0, 4, 2, 2, 4, 6,
+    1, 3, 5, 3, 7, 5
+);
+
+void main() {
+    vec3 position = corners[indices[gl_VertexIndex]];
+    mat4 model = ubo.model * object.model * pushConstants.model;
+    gl_Position = ubo.proj * ubo.view * model * vec4(position, 1.0);
+}
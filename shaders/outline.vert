@@ -0,0 +1,42 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+// cube.vert for the --outline, scaled up around the model's origin. Pushing
+// the vertices out along their normals would tear the cube's faces apart at
+// the edges, and leave the flat quad as it is.
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+layout(set = 0, binding = 1) uniform ObjectUniforms {
+    mat4 model;
+} object;
+
+// The renderer pushes the model, the app pushes the scale right after it
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    float scale;
+} pushConstants;
+
+// See mesh::Vertex
+layout(location = 0) in vec3 inPosition;
+
+void main() {
+    mat4 model = ubo.model * object.model * pushConstants.model;
+    gl_Position = ubo.proj * VIEW * model * vec4(inPosition * pushConstants.scale, 1.0);
+}
@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+// Straight from the storage buffer particles.comp writes
+layout(location = 0) in vec4 position;
+layout(location = 1) in vec4 velocity;
+
+layout(location = 0) out vec3 fragColor;
+
+// Fastest the fountain launches them, see particles.comp
+const float MAX_SPEED = 5.0;
+
+void main() {
+    // Particles stay where they are while the model spins
+    gl_Position = ubo.proj * VIEW * vec4(position.xyz, 1.0);
+    // Anything bigger needs the largePoints feature
+    gl_PointSize = 1.0;
+    // Bright on the way up, fading to red as they fall
+    float rising = clamp(velocity.z / MAX_SPEED * 0.5 + 0.5, 0.0, 1.0);
+    fragColor = mix(vec3(0.8, 0.1, 0.05), vec3(1.0, 0.9, 0.5), rising);
+}
@@ -0,0 +1,50 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The offscreen color image the scene was drawn into, the same size as the
+// swapchain, with a layer per eye with --multiview. The sampler is bound
+// on its own.
+layout(set = 0, binding = 0) uniform texture2DArray sceneColor;
+layout(set = 0, binding = 1) uniform sampler sceneSampler;
+
+// See offscreen::PostEffect
+const uint EFFECT_GRAYSCALE = 1;
+const uint EFFECT_VIGNETTE = 2;
+const uint EFFECT_GAMMA = 3;
+
+layout(push_constant) uniform PushConstants {
+    uint effect;
+    // How much of the effect there is, or the gamma to correct by
+    float strength;
+    // See offscreen::ToneMapping, 1.0 leaves the image as it is
+    float exposure;
+    float gamma;
+} post;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    ivec3 size = textureSize(sampler2DArray(sceneColor, sceneSampler), 0);
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    int layer = 0;
+    if (size.z > 1) {
+        // The eyes side by side, each squeezed into half the width. Their
+        // projection is stretched to make up for it.
+        int halfWidth = size.x / 2;
+        layer = pixel.x < halfWidth ? 0 : 1;
+        pixel.x = (pixel.x - layer * halfWidth) * 2;
+    }
+    vec4 color = texelFetch(sampler2DArray(sceneColor, sceneSampler), ivec3(pixel, layer), 0);
+    color.rgb *= post.exposure;
+    if (post.effect == EFFECT_GRAYSCALE) {
+        // Rec. 709 luma
+        float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));
+        color.rgb = mix(color.rgb, vec3(luma), post.strength);
+    } else if (post.effect == EFFECT_VIGNETTE) {
+        float fromCenter = distance(gl_FragCoord.xy / vec2(size.xy), vec2(0.5));
+        color.rgb *= 1.0 - post.strength * smoothstep(0.3, 0.75, fromCenter);
+    } else if (post.effect == EFFECT_GAMMA) {
+        color.rgb = pow(max(color.rgb, vec3(0.0)), vec3(1.0 / post.strength));
+    }
+    outColor = vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / post.gamma)), color.a);
+}
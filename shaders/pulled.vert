@@ -0,0 +1,63 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_EXT_buffer_reference : require
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+// This object's slice of the dynamic uniform buffer, see objects.rs
+layout(set = 0, binding = 1) uniform ObjectUniforms {
+    mat4 model;
+} object;
+
+// mesh::Vertex after mesh::Vertex, 11 floats each
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Vertices {
+    float data[];
+};
+
+// The renderer pushes the model and the address of the vertices
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    // Where outline.vert has its scale
+    float unused;
+    Vertices vertices;
+} pushConstants;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec3 worldPosition;
+layout(location = 2) out vec3 worldNormal;
+
+vec3 readVec3(int offset) {
+    int base = int(gl_VertexIndex) * 11 + offset;
+    Vertices vertices = pushConstants.vertices;
+    return vec3(vertices.data[base], vertices.data[base + 1], vertices.data[base + 2]);
+}
+
+void main() {
+    vec3 inPosition = readVec3(0);
+    vec3 inColor = readVec3(3);
+    vec3 inNormal = readVec3(6);
+
+    mat4 model = ubo.model * object.model * pushConstants.model;
+    vec4 world = model * vec4(inPosition, 1.0);
+    gl_Position = ubo.proj * VIEW * world;
+    worldPosition = world.xyz;
+    // Fine as long as the transforms don't scale unevenly
+    worldNormal = mat3(model) * inNormal;
+    fragColor = inColor;
+}
@@ -1,9 +1,49 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+// Set when the pipeline is created, see VulkanApp::brightness
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+// See lib.rs UniformBufferObject and Light
+struct Light {
+    vec4 position;
+    vec3 color;
+    float ambient;
+};
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 eyeViews[2];
+    vec4 eye;
+    Light light;
+} ubo;
+
 layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 worldPosition;
+layout(location = 2) in vec3 worldNormal;
+
 layout(location = 0) out vec4 outColor;
 
+const float SPECULAR = 0.5;
+const float SHININESS = 32.0;
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    vec3 toEye = normalize(ubo.eye.xyz - worldPosition);
+    vec3 normal = normalize(worldNormal);
+    // The triangles are seen from both sides, light whichever one faces us
+    if (dot(normal, toEye) < 0.0) {
+        normal = -normal;
+    }
+    Light light = ubo.light;
+    // w is 0 for a directional light
+    vec3 toLight = normalize(light.position.xyz - worldPosition * light.position.w);
+    float diffuse = max(dot(normal, toLight), 0.0);
+    vec3 reflected = reflect(-toLight, normal);
+    float specular = diffuse > 0.0
+        ? SPECULAR * pow(max(dot(reflected, toEye), 0.0), SHININESS)
+        : 0.0;
+    vec3 lit = fragColor * (light.ambient + (1.0 - light.ambient) * diffuse) + specular;
+    outColor = vec4(lit * light.color * BRIGHTNESS, 1.0);
 }
@@ -1,7 +1,40 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+// This object's slice of the dynamic uniform buffer, see objects.rs
+layout(set = 0, binding = 1) uniform ObjectUniforms {
+    mat4 model;
+} object;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+} pushConstants;
+
+// Per instance, see InstanceData
+layout(location = 0) in vec3 instanceOffset;
+layout(location = 1) in vec3 instanceColor;
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec3 worldPosition;
+layout(location = 2) out vec3 worldNormal;
 
 vec2 positions[3] = vec2[](
     vec2(0.0, -0.5),
@@ -16,6 +49,12 @@ vec3 colors[3] = vec3[](
 );
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
+    mat4 model = ubo.model * object.model * pushConstants.model;
+    vec4 world = model * vec4(vec3(positions[gl_VertexIndex], 0.0) + instanceOffset, 1.0);
+    gl_Position = ubo.proj * VIEW * world;
+    worldPosition = world.xyz;
+    // The triangles lie flat in the XY plane. Fine as long as the transforms
+    // don't scale unevenly.
+    worldNormal = mat3(model) * vec3(0.0, 0.0, 1.0);
+    fragColor = colors[gl_VertexIndex] * instanceColor;
 }
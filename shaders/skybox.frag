@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The sampler is bound on its own
+layout(set = 1, binding = 0) uniform textureCube skyboxTexture;
+layout(set = 1, binding = 1) uniform sampler skyboxSampler;
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Cubemaps are looked up with Y up, our world has Z up
+    outColor = texture(samplerCube(skyboxTexture, skyboxSampler), direction.xzy);
+}
@@ -0,0 +1,58 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// shader.frag, with the color from a layer of the texture array
+
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+struct Light {
+    vec4 position;
+    vec3 color;
+    float ambient;
+};
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    mat4 eyeViews[2];
+    vec4 eye;
+    Light light;
+} ubo;
+
+// The sampler is bound on its own
+layout(set = 1, binding = 0) uniform texture2DArray textures;
+layout(set = 1, binding = 1) uniform sampler textureSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 worldPosition;
+layout(location = 2) in vec3 worldNormal;
+layout(location = 3) in vec2 uv;
+layout(location = 4) flat in int textureLayer;
+
+layout(location = 0) out vec4 outColor;
+
+const float SPECULAR = 0.5;
+const float SHININESS = 32.0;
+
+void main() {
+    vec3 baseColor = fragColor;
+    // Negative for objects without a texture
+    if (textureLayer >= 0) {
+        baseColor = texture(sampler2DArray(textures, textureSampler), vec3(uv, float(textureLayer))).rgb;
+    }
+    vec3 toEye = normalize(ubo.eye.xyz - worldPosition);
+    vec3 normal = normalize(worldNormal);
+    if (dot(normal, toEye) < 0.0) {
+        normal = -normal;
+    }
+    Light light = ubo.light;
+    vec3 toLight = normalize(light.position.xyz - worldPosition * light.position.w);
+    float diffuse = max(dot(normal, toLight), 0.0);
+    vec3 reflected = reflect(-toLight, normal);
+    float specular = diffuse > 0.0
+        ? SPECULAR * pow(max(dot(reflected, toEye), 0.0), SHININESS)
+        : 0.0;
+    vec3 lit = baseColor * (light.ambient + (1.0 - light.ambient) * diffuse) + specular;
+    outColor = vec4(lit * light.color * BRIGHTNESS, 1.0);
+}
@@ -0,0 +1,58 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#ifdef MULTIVIEW
+#extension GL_EXT_multiview : enable
+#endif
+
+// cube.vert for scene objects, which also pass on where to sample their
+// texture, see texture_array.rs
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 model;
+    mat4 view;
+    mat4 proj;
+    // See lib.rs UniformBufferObject
+    mat4 eyeViews[2];
+} ubo;
+
+// Each eye's own view with --multiview, see build.rs
+#ifdef MULTIVIEW
+#define VIEW ubo.eyeViews[gl_ViewIndex]
+#else
+#define VIEW ubo.view
+#endif
+
+layout(set = 0, binding = 1) uniform ObjectUniforms {
+    mat4 model;
+} object;
+
+// See texture_array::TexturedPushConstants
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    int textureLayer;
+} pushConstants;
+
+// See mesh::Vertex
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec3 inNormal;
+layout(location = 3) in vec2 inUv;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec3 worldPosition;
+layout(location = 2) out vec3 worldNormal;
+layout(location = 3) out vec2 uv;
+layout(location = 4) flat out int textureLayer;
+
+void main() {
+    mat4 model = ubo.model * object.model * pushConstants.model;
+    vec4 world = model * vec4(inPosition, 1.0);
+    gl_Position = ubo.proj * VIEW * world;
+    // Only used when objects are drawn as points
+    gl_PointSize = 1.0;
+    worldPosition = world.xyz;
+    worldNormal = mat3(model) * inNormal;
+    fragColor = inColor;
+    uv = inUv;
+    textureLayer = pushConstants.textureLayer;
+}
@@ -0,0 +1,28 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// gbuffer.frag, with the albedo from a layer of the texture array
+
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+layout(set = 1, binding = 0) uniform texture2DArray textures;
+layout(set = 1, binding = 1) uniform sampler textureSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 worldPosition;
+layout(location = 3) in vec2 uv;
+layout(location = 4) flat in int textureLayer;
+
+layout(location = 0) out vec4 outPosition;
+layout(location = 1) out vec4 outNormal;
+layout(location = 2) out vec4 outAlbedo;
+
+void main() {
+    vec3 albedo = fragColor;
+    if (textureLayer >= 0) {
+        albedo = texture(sampler2DArray(textures, textureSampler), vec3(uv, float(textureLayer))).rgb;
+    }
+    outPosition = vec4(worldPosition, 1.0);
+    outNormal = vec4(normalize(cross(dFdy(worldPosition), dFdx(worldPosition))), 0.0);
+    outAlbedo = vec4(albedo * BRIGHTNESS, 1.0);
+}
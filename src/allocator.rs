@@ -0,0 +1,107 @@
+//! Device memory comes from gpu-allocator, which carves resources out of a
+//! few large blocks instead of allocating memory for each of them. Drivers
+//! only allow so many allocations (`maxMemoryAllocationCount`, as low as
+//! 4096), and every one of them is rounded up to a page or more.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ash::vk;
+use gpu_allocator::vulkan::{
+    Allocation, AllocationCreateDesc, AllocationScheme, Allocator, AllocatorCreateDesc,
+};
+
+pub(crate) use gpu_allocator::MemoryLocation;
+
+/// Shared by every resource that holds an allocation, since they have to
+/// hand it back when dropped.
+pub(crate) type SharedAllocator = Rc<RefCell<Allocator>>;
+
+/// Creates the allocator for `device`. It has to be dropped before the
+/// device is destroyed, which frees all of its memory blocks.
+/// `buffer_device_address` has all memory allocated so buffers in it can
+/// have an address, which needs the device feature of the same name.
+pub(crate) fn create_allocator(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &ash::Device,
+    buffer_device_address: bool,
+) -> SharedAllocator {
+    let allocator = Allocator::new(&AllocatorCreateDesc {
+        instance: instance.clone(),
+        device: device.clone(),
+        physical_device,
+        debug_settings: Default::default(),
+        buffer_device_address,
+        allocation_sizes: Default::default(),
+    })
+    .expect("Failed to create memory allocator!");
+    Rc::new(RefCell::new(allocator))
+}
+
+/// Sub-allocates memory meeting `requirements`. `linear` is true for buffers
+/// and linearly tiled images, which can't share a page with optimally tiled
+/// ones on some hardware.
+pub(crate) fn allocate(
+    allocator: &SharedAllocator,
+    name: &str,
+    requirements: vk::MemoryRequirements,
+    location: MemoryLocation,
+    linear: bool,
+) -> Allocation {
+    allocator
+        .borrow_mut()
+        .allocate(&AllocationCreateDesc {
+            name,
+            requirements,
+            location,
+            linear,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })
+        .unwrap_or_else(|error| panic!("Failed to allocate memory for {}: {}", name, error))
+}
+
+/// The memory block `allocation` lives in, to bind resources to at
+/// `allocation.offset()`.
+pub(crate) fn memory(allocation: &Allocation) -> vk::DeviceMemory {
+    unsafe { allocation.memory() }
+}
+
+/// The first memory type allowed by `type_bits` that has all of `properties`.
+/// Only needed for memory gpu-allocator never picks on its own, like lazily
+/// allocated memory.
+pub(crate) fn find_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    properties: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .find(|(index, memory_type)| {
+            type_bits & (1 << index) != 0 && memory_type.property_flags.contains(properties)
+        })
+        .map(|(index, _)| index as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_memory_type_respects_type_bits() {
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties {
+            memory_type_count: 3,
+            ..Default::default()
+        };
+        memory_properties.memory_types[0].property_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        memory_properties.memory_types[1].property_flags =
+            vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        memory_properties.memory_types[2].property_flags =
+            vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        let lazy = vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        assert_eq!(find_memory_type(&memory_properties, 0b111, lazy), Some(1));
+        assert_eq!(find_memory_type(&memory_properties, 0b101, lazy), Some(2));
+        assert_eq!(find_memory_type(&memory_properties, 0b001, lazy), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! `--benchmark` mode: renders a fixed number of frames and sums up how long
+//! they took, to compare changes by numbers rather than by eye.
+
+use std::time::{Duration, Instant};
+
+/// Collects the CPU time of each frame until `frame_budget` frames are in.
+pub(crate) struct Benchmark {
+    frame_budget: usize,
+    frame_times: Vec<Duration>,
+    /// When the first frame started.
+    started: Option<Instant>,
+    /// Wall time from the first frame's start to the last frame's end.
+    total_time: Duration,
+}
+
+/// Frame time statistics in milliseconds.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Summary {
+    pub(crate) frames: usize,
+    pub(crate) min_ms: f64,
+    pub(crate) max_ms: f64,
+    pub(crate) mean_ms: f64,
+    pub(crate) p95_ms: f64,
+    pub(crate) fps: f64,
+}
+
+impl Benchmark {
+    pub(crate) fn new(frame_budget: u32) -> Self {
+        Benchmark {
+            frame_budget: frame_budget as usize,
+            frame_times: Vec::with_capacity(frame_budget as usize),
+            started: None,
+            total_time: Duration::ZERO,
+        }
+    }
+
+    /// Records a presented frame that started at `frame_start`. Returns true
+    /// once the budget is used up, frames after that aren't counted.
+    pub(crate) fn record(&mut self, frame_start: Instant) -> bool {
+        if self.frame_times.len() < self.frame_budget {
+            let started = *self.started.get_or_insert(frame_start);
+            self.frame_times.push(frame_start.elapsed());
+            self.total_time = started.elapsed();
+        }
+        self.frame_times.len() >= self.frame_budget
+    }
+
+    /// Returns `None` if not a single frame was drawn.
+    pub(crate) fn summary(&self) -> Option<Summary> {
+        summarize(&self.frame_times, self.total_time)
+    }
+}
+
+fn summarize(frame_times: &[Duration], total_time: Duration) -> Option<Summary> {
+    if frame_times.is_empty() {
+        return None;
+    }
+    let mut frame_times_ms: Vec<f64> = frame_times
+        .iter()
+        .map(|frame_time| frame_time.as_secs_f64() * 1000.0)
+        .collect();
+    frame_times_ms.sort_by(f64::total_cmp);
+    let frames = frame_times_ms.len();
+    // Nearest rank, so it's always one of the measured frame times
+    let p95_index = (frames * 95).div_ceil(100) - 1;
+    Some(Summary {
+        frames,
+        min_ms: frame_times_ms[0],
+        max_ms: frame_times_ms[frames - 1],
+        mean_ms: frame_times_ms.iter().sum::<f64>() / frames as f64,
+        p95_ms: frame_times_ms[p95_index],
+        fps: frames as f64 / total_time.as_secs_f64(),
+    })
+}
+
+impl Summary {
+    pub(crate) fn print(&self) {
+        println!("Benchmark: {} frames, {:.1} FPS", self.frames, self.fps);
+        println!(
+            "Frame time: min {:.3} ms, max {:.3} ms, mean {:.3} ms, p95 {:.3} ms",
+            self.min_ms, self.max_ms, self.mean_ms, self.p95_ms
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_frames_have_no_summary() {
+        assert_eq!(summarize(&[], Duration::ZERO), None);
+    }
+
+    #[test]
+    fn summarizes_frame_times() {
+        // 1 to 20 ms, in no particular order
+        let frame_times: Vec<Duration> = (1..=20)
+            .rev()
+            .map(|ms| Duration::from_millis(ms as u64))
+            .collect();
+        let summary = summarize(&frame_times, Duration::from_secs(2)).unwrap();
+        assert_eq!(summary.frames, 20);
+        assert_eq!(summary.min_ms, 1.0);
+        assert_eq!(summary.max_ms, 20.0);
+        assert_eq!(summary.mean_ms, 10.5);
+        assert_eq!(summary.p95_ms, 19.0);
+        assert_eq!(summary.fps, 10.0);
+    }
+}
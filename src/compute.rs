@@ -0,0 +1,399 @@
+//! The `--compute` wave: a compute shader moves the triangle instances each
+//! frame, writing straight into the instance buffer the vertex shader reads.
+//!
+//! If the device has a compute family without graphics, the wave runs on
+//! that queue instead, where it can overlap with the previous frame's draws.
+
+use std::ffi::CString;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::allocator::{MemoryLocation, SharedAllocator};
+use crate::resources::{Buffer, Pipeline};
+use crate::{InstanceData, VulkanApp, MAX_FRAMES_IN_FLIGHT};
+
+/// Invocations per workgroup, `local_size_x` in wave.comp.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches the `push_constant` block in wave.comp.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct WavePushConstants {
+    /// Seconds of animation, see `ComputeWave::advance`.
+    time: f32,
+    count: u32,
+}
+
+/// The compute pipeline and the buffers it reads and writes.
+pub(crate) struct ComputeWave {
+    device: ash::Device,
+    /// Where the instances are when the wave is flat, read by the shader.
+    _resting_instances: Buffer,
+    /// One per frame in flight, so the wave never writes to instances a
+    /// frame that's still in flight draws.
+    instance_buffers: Vec<Buffer>,
+    instance_count: u32,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// One per instance buffer.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    pipeline_layout: vk::PipelineLayout,
+    pub(crate) pipeline: Pipeline,
+    /// Seconds the wave has been running, not counting pauses.
+    time: f32,
+}
+
+impl ComputeWave {
+    /// Animates `instances` into instance buffers of its own, which the
+    /// `queue_families` the wave and the draws run on can all access.
+    /// Panics if wave.comp can't be loaded.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        instances: &[InstanceData],
+        queue_families: &[u32],
+    ) -> Self {
+        let size = std::mem::size_of_val(instances) as vk::DeviceSize;
+        let resting_instances = VulkanApp::create_buffer(
+            device,
+            allocator,
+            "resting_instances",
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = resting_instances
+            .mapped_ptr()
+            .expect("Resting instance memory isn't host visible!");
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                instances.as_ptr(),
+                mapped.cast::<InstanceData>().as_ptr(),
+                instances.len(),
+            );
+        }
+        let instance_buffers: Vec<Buffer> = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                VulkanApp::create_shared_buffer(
+                    device,
+                    allocator,
+                    "wave_instances",
+                    size,
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                    MemoryLocation::GpuOnly,
+                    queue_families,
+                )
+            })
+            .collect();
+
+        let bindings = [0, 1].map(|binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            ..Default::default()
+        });
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create compute Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: (bindings.len() * MAX_FRAMES_IN_FLIGHT) as u32,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: MAX_FRAMES_IN_FLIGHT as u32,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create compute Descriptor Pool!")
+        };
+        let set_layouts = [descriptor_set_layout; MAX_FRAMES_IN_FLIGHT];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate compute Descriptor Sets!")
+        };
+        for (&descriptor_set, instance_buffer) in descriptor_sets.iter().zip(&instance_buffers) {
+            let buffer_infos = [resting_instances.buffer, instance_buffer.buffer].map(|buffer| {
+                vk::DescriptorBufferInfo {
+                    buffer,
+                    offset: 0,
+                    range: size,
+                }
+            });
+            let descriptor_writes = [0, 1].map(|binding| vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: binding,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                p_buffer_info: &buffer_infos[binding as usize],
+                ..Default::default()
+            });
+            unsafe {
+                device.update_descriptor_sets(&descriptor_writes, &[]);
+            }
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<WavePushConstants>() as u32,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create compute pipeline layout")
+        };
+
+        let pipeline = Self::create_pipeline(device, pipeline_cache, shader_dir, pipeline_layout)
+            .unwrap_or_else(|error| panic!("{}", error));
+
+        ComputeWave {
+            device: device.clone(),
+            _resting_instances: resting_instances,
+            instance_buffers,
+            instance_count: instances.len() as u32,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            pipeline_layout,
+            pipeline,
+            time: 0.0,
+        }
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        layout: vk::PipelineLayout,
+    ) -> Result<Pipeline, String> {
+        let code = VulkanApp::load_shader_code(shader_dir, "wave.comp")?;
+        let shader_module = VulkanApp::create_shader_module(device, &code)?;
+        let main_function_name = CString::new("main").unwrap();
+        let pipeline_infos = [vk::ComputePipelineCreateInfo {
+            stage: vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::COMPUTE,
+                module: shader_module,
+                p_name: main_function_name.as_ptr(),
+                ..Default::default()
+            },
+            layout,
+            ..Default::default()
+        }];
+        let result =
+            unsafe { device.create_compute_pipelines(pipeline_cache, &pipeline_infos, None) };
+        unsafe {
+            device.destroy_shader_module(shader_module, None);
+        }
+        result
+            .map(|pipelines| Pipeline::new(device, pipelines[0]))
+            .map_err(|(_, error)| format!("Failed to create compute Pipeline: {:?}", error))
+    }
+
+    /// The instances `frame` draws, once its dispatch is done.
+    pub(crate) fn instance_buffer(&self, frame: usize) -> vk::Buffer {
+        self.instance_buffers[frame].buffer
+    }
+
+    /// Records the dispatch that fills the instance buffer of `frame`. It's
+    /// up to the caller to make the draws wait for it.
+    /// Moves the wave `dt` seconds along.
+    pub(crate) fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub(crate) fn record(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let device = &self.device;
+        let push_constants = WavePushConstants {
+            time: self.time,
+            count: self.instance_count,
+        };
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[frame]],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const WavePushConstants as *const u8,
+                    std::mem::size_of::<WavePushConstants>(),
+                ),
+            );
+            device.cmd_dispatch(
+                command_buffer,
+                self.instance_count.div_ceil(WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+    }
+}
+
+impl Drop for ComputeWave {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+/// A queue of a family with compute but no graphics, and what it takes to
+/// submit the wave to it once per frame.
+pub(crate) struct AsyncCompute {
+    device: ash::Device,
+    pub(crate) family: u32,
+    pub(crate) queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    /// Signaled once a frame's dispatch is done, for its draws to wait on.
+    finished_semaphores: Vec<vk::Semaphore>,
+}
+
+impl AsyncCompute {
+    pub(crate) fn new(device: &ash::Device, family: u32, queue: vk::Queue) -> Self {
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: family,
+            ..Default::default()
+        };
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .expect("Failed to create compute Command Pool!")
+        };
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: MAX_FRAMES_IN_FLIGHT as u32,
+            ..Default::default()
+        };
+        let command_buffers = unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .expect("Failed to allocate compute Command Buffers!")
+        };
+        let finished_semaphores = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| unsafe {
+                device
+                    .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                    .expect("Failed to create Semaphore!")
+            })
+            .collect();
+        AsyncCompute {
+            device: device.clone(),
+            family,
+            queue,
+            command_pool,
+            command_buffers,
+            finished_semaphores,
+        }
+    }
+
+    /// Submits the dispatch of `frame`, returning the semaphore the frame's
+    /// draws have to wait on. Its command buffer is free to record again
+    /// once the frame is done, as the draws waited for it.
+    pub(crate) fn submit(
+        &self,
+        compute_wave: &ComputeWave,
+        frame: usize,
+    ) -> Result<vk::Semaphore, vk::Result> {
+        let command_buffer = self.command_buffers[frame];
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .expect("Failed to reset compute Command Buffer!");
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("Failed to begin recording compute Command Buffer!");
+        }
+        compute_wave.record(command_buffer, frame);
+        unsafe {
+            self.device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record compute Command Buffer!");
+        }
+
+        // The semaphore makes the writes visible to the draws that wait on
+        // it, and the instance buffers are shared by both families, so no
+        // barriers are needed
+        let command_buffers = [command_buffer];
+        let signal_semaphores = [self.finished_semaphores[frame]];
+        let submit_infos = [vk::SubmitInfo {
+            command_buffer_count: command_buffers.len() as u32,
+            p_command_buffers: command_buffers.as_ptr(),
+            signal_semaphore_count: signal_semaphores.len() as u32,
+            p_signal_semaphores: signal_semaphores.as_ptr(),
+            ..Default::default()
+        }];
+        unsafe {
+            self.device
+                .queue_submit(self.queue, &submit_infos, vk::Fence::null())?;
+        }
+        Ok(signal_semaphores[0])
+    }
+}
+
+impl Drop for AsyncCompute {
+    fn drop(&mut self) {
+        unsafe {
+            for &semaphore in &self.finished_semaphores {
+                self.device.destroy_semaphore(semaphore, None);
+            }
+            // Frees the command buffers along with it
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Optional settings from a `config.toml`, so experimenting with them doesn't
+//! need a recompile. Anything given on the command line takes precedence.
+
+use std::path::Path;
+
+use ash::vk;
+use serde::Deserialize;
+
+use crate::{CliArgs, GpuSelector};
+
+/// Where `main` looks for the config, relative to the working directory.
+pub const CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    Immediate,
+    Mailbox,
+    Fifo,
+    FifoRelaxed,
+}
+
+impl PresentMode {
+    pub fn to_vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+        }
+    }
+}
+
+/// Every key is optional, and missing ones keep the built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub present_mode: Option<PresentMode>,
+    /// Index of the device to render on, as listed by `--list-gpus`.
+    pub gpu: Option<usize>,
+    /// Renders on the first device whose name contains this. Ignored if
+    /// `gpu` or `gpu_id` is set.
+    pub gpu_name: Option<String>,
+    /// PCI device ID of the device to render on. Ignored if `gpu` is set.
+    pub gpu_id: Option<u32>,
+    pub msaa_samples: Option<u32>,
+    pub validation: Option<bool>,
+    /// How many times to recreate a lost device before giving up.
+    pub device_lost_retries: Option<u32>,
+}
+
+impl Config {
+    /// Reads the config at `path`. A missing file is fine and gives the
+    /// defaults, keys we don't know about are only warned about.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(error) => return Err(format!("Failed to read {:?}: {}", path, error)),
+        };
+        Self::parse(&source).map_err(|error| format!("Invalid config {:?}: {}", path, error))
+    }
+
+    fn parse(source: &str) -> Result<Config, String> {
+        let deserializer = toml::Deserializer::new(source);
+        let config: Config = serde_ignored::deserialize(deserializer, |key| {
+            eprintln!("Ignoring unknown config key: {}", key);
+        })
+        .map_err(|error| error.to_string())?;
+
+        if config.width == Some(0) || config.height == Some(0) {
+            return Err("width and height must be positive".to_string());
+        }
+        if let Some(msaa_samples) = config.msaa_samples {
+            if !msaa_samples.is_power_of_two() || msaa_samples > 64 {
+                return Err(format!(
+                    "msaa_samples must be 1, 2, 4, 8, 16, 32 or 64, got {}",
+                    msaa_samples
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Uses the config's values in `cli_args`. Call this before parsing the
+    /// command line, so that can override them.
+    pub fn apply(self, cli_args: &mut CliArgs) {
+        if let Some(width) = self.width {
+            cli_args.width = width;
+        }
+        if let Some(height) = self.height {
+            cli_args.height = height;
+        }
+        if let Some(present_mode) = self.present_mode {
+            cli_args.present_mode = present_mode.to_vk();
+        }
+        if let Some(index) = self.gpu {
+            cli_args.gpu = Some(GpuSelector::Index(index));
+        } else if let Some(device_id) = self.gpu_id {
+            cli_args.gpu = Some(GpuSelector::DeviceId(device_id));
+        } else if let Some(name) = self.gpu_name {
+            cli_args.gpu = Some(GpuSelector::Name(name));
+        }
+        if let Some(msaa_samples) = self.msaa_samples {
+            cli_args.msaa_samples = msaa_samples;
+        }
+        if let Some(validation) = self.validation {
+            cli_args.validation = validation;
+        }
+        if let Some(device_lost_retries) = self.device_lost_retries {
+            cli_args.device_lost_retries = device_lost_retries;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_keeps_defaults() {
+        let mut cli_args = CliArgs::default();
+        Config::parse("").unwrap().apply(&mut cli_args);
+        assert_eq!(cli_args.width, crate::WIDTH);
+        assert_eq!(cli_args.present_mode, vk::PresentModeKHR::MAILBOX);
+        assert!(cli_args.validation);
+    }
+
+    #[test]
+    fn config_overrides_defaults() {
+        let config = Config::parse(
+            "width = 1280\n\
+             present_mode = \"fifo\"\n\
+             gpu_name = \"Radeon\"\n\
+             validation = false\n",
+        )
+        .unwrap();
+        let mut cli_args = CliArgs::default();
+        config.apply(&mut cli_args);
+        assert_eq!(cli_args.width, 1280);
+        assert_eq!(cli_args.height, crate::HEIGHT);
+        assert_eq!(cli_args.present_mode, vk::PresentModeKHR::FIFO);
+        assert!(matches!(cli_args.gpu, Some(GpuSelector::Name(ref name)) if name == "Radeon"));
+        assert!(!cli_args.validation);
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let config = Config::parse("height = 720\nvsync = true\n").unwrap();
+        assert_eq!(config.height, Some(720));
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        assert!(Config::parse("present_mode = \"sometimes\"").is_err());
+        assert!(Config::parse("width = \"wide\"").is_err());
+        assert!(Config::parse("height = 0").is_err());
+        assert!(Config::parse("msaa_samples = 3").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! `--deferred` shading. The scene is drawn into a G-buffer in the first
+//! subpass, and lit in a second one that reads the G-buffer back as input
+//! attachments, one pixel at a time. Tile-based GPUs can keep the G-buffer
+//! on chip that way, it's never stored.
+
+use ash::vk;
+
+/// World space position, normal and albedo, in the order of the outputs of
+/// gbuffer.frag and the inputs of lighting.frag. The render pass has them
+/// after the color and depth attachments.
+pub(crate) const GBUFFER_FORMATS: [vk::Format; 3] = [
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R16G16B16A16_SFLOAT,
+    vk::Format::R8G8B8A8_UNORM,
+];
+
+/// Everything the lighting subpass draws with, besides its pipeline.
+pub(crate) struct Deferred {
+    device: ash::Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// The G-buffer attachments. All frames share them, like the depth
+    /// image.
+    descriptor_set: vk::DescriptorSet,
+    pub(crate) pipeline_layout: vk::PipelineLayout,
+}
+
+impl Deferred {
+    /// Reads from `gbuffer_views`, which have to be `GBUFFER_FORMATS`.
+    pub(crate) fn new(device: &ash::Device, gbuffer_views: &[vk::ImageView]) -> Self {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..GBUFFER_FORMATS.len() as u32)
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::INPUT_ATTACHMENT,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            })
+            .collect();
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create G-buffer Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: bindings.len() as u32,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create G-buffer Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate G-buffer Descriptor Set!")[0]
+        };
+
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create lighting pipeline layout")
+        };
+
+        let deferred = Deferred {
+            device: device.clone(),
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+        };
+        deferred.update(gbuffer_views);
+        deferred
+    }
+
+    /// Points the descriptor set at `gbuffer_views`, e.g. after the swapchain
+    /// created them again. The GPU can't be using the set at the time.
+    pub(crate) fn update(&self, gbuffer_views: &[vk::ImageView]) {
+        let image_infos: Vec<vk::DescriptorImageInfo> = gbuffer_views
+            .iter()
+            .map(|&image_view| vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .collect();
+        let descriptor_writes: Vec<vk::WriteDescriptorSet> = image_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, image_info)| vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: binding as u32,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::INPUT_ATTACHMENT,
+                p_image_info: image_info,
+                ..Default::default()
+            })
+            .collect();
+        unsafe {
+            self.device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+    }
+
+    /// Lights the whole screen with `pipeline`, in the second subpass.
+    pub(crate) fn record(&self, command_buffer: vk::CommandBuffer, pipeline: vk::Pipeline) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            // A triangle made up in fullscreen.vert
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Destroys what the GPU may still be using once it's done with it, instead
+//! of waiting for the whole device to go idle.
+
+use crate::MAX_FRAMES_IN_FLIGHT;
+
+type Deletion = Box<dyn FnOnce()>;
+
+pub(crate) struct DeletionQueue {
+    /// Per frame slot, run the next time `draw_frame` has waited for it.
+    pending: Vec<Vec<Deletion>>,
+}
+
+impl DeletionQueue {
+    pub(crate) fn new() -> Self {
+        DeletionQueue {
+            pending: (0..MAX_FRAMES_IN_FLIGHT).map(|_| vec![]).collect(),
+        }
+    }
+
+    /// Runs `deletion` once none of the frames submitted so far are in
+    /// flight. `current_frame` is the slot of the next frame to be drawn.
+    ///
+    /// Those frames are done once the slot before `current_frame`, which was
+    /// submitted last, is done. The other slots were waited for
+    /// on the way there.
+    pub(crate) fn push(&mut self, current_frame: usize, deletion: impl FnOnce() + 'static) {
+        let last_frame = (current_frame + MAX_FRAMES_IN_FLIGHT - 1) % MAX_FRAMES_IN_FLIGHT;
+        self.pending[last_frame].push(Box::new(deletion));
+    }
+
+    /// Runs what was waiting for `frame`, which has just been waited for.
+    pub(crate) fn flush(&mut self, frame: usize) {
+        for deletion in self.pending[frame].drain(..) {
+            deletion();
+        }
+    }
+
+    /// Runs everything. Only once the device is idle.
+    pub(crate) fn flush_all(&mut self) {
+        for frame in 0..self.pending.len() {
+            self.flush(frame);
+        }
+    }
+}
+
+impl Drop for DeletionQueue {
+    fn drop(&mut self) {
+        self.flush_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn deletions_wait_for_the_last_submitted_frame() {
+        let deleted = Rc::new(Cell::new(false));
+        let mut queue = DeletionQueue::new();
+        let current_frame = 0;
+        let flag = Rc::clone(&deleted);
+        queue.push(current_frame, move || flag.set(true));
+
+        // The next frame reuses slot 0, whose submission doesn't cover the frame
+        // submitted last
+        queue.flush(current_frame);
+        assert!(!deleted.get());
+        queue.flush((current_frame + 1) % MAX_FRAMES_IN_FLIGHT);
+        assert!(deleted.get());
+    }
+
+    #[test]
+    fn dropping_runs_pending_deletions() {
+        let deleted = Rc::new(Cell::new(false));
+        let mut queue = DeletionQueue::new();
+        let flag = Rc::clone(&deleted);
+        queue.push(1, move || flag.set(true));
+        drop(queue);
+        assert!(deleted.get());
+    }
+}
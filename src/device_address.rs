@@ -0,0 +1,34 @@
+//! Buffers that shaders read through their address in device memory, with
+//! `VK_KHR_buffer_device_address` (core in Vulkan 1.2), rather than
+//! through a descriptor or a vertex buffer binding. `--vertex-pulling`
+//! draws the demo's mesh like this, pushing the address of its vertices.
+//!
+//! Memory for these buffers has to be allocated with the `DEVICE_ADDRESS`
+//! flag, which gpu-allocator then sets on all of it.
+
+use ash::extensions::khr::BufferDeviceAddress as BufferDeviceAddressLoader;
+use ash::vk;
+
+/// What buffers have to be created with to have an address.
+pub(crate) const USAGE: vk::BufferUsageFlags = vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS_EXT;
+
+pub(crate) struct DeviceAddresses {
+    loader: BufferDeviceAddressLoader,
+}
+
+impl DeviceAddresses {
+    /// `loader` has to be for a device created with the extension and its
+    /// `buffer_device_address` feature enabled.
+    pub(crate) fn new(loader: BufferDeviceAddressLoader) -> Self {
+        DeviceAddresses { loader }
+    }
+
+    /// Where `buffer` starts, which has to have been created with `USAGE`.
+    pub(crate) fn of(&self, buffer: vk::Buffer) -> vk::DeviceAddress {
+        let info = vk::BufferDeviceAddressInfo {
+            buffer,
+            ..Default::default()
+        };
+        unsafe { self.loader.get_buffer_device_address(&info) }
+    }
+}
@@ -0,0 +1,176 @@
+//! `--dynamic-rendering`: drawing without a render pass or framebuffers,
+//! with `VK_KHR_dynamic_rendering` (core in Vulkan 1.3). The attachments go
+//! straight to `vkCmdBeginRenderingKHR` each frame, so nothing has to be
+//! created again along with the swapchain, and the layout transitions the
+//! render pass made are barriers around it instead.
+
+use ash::extensions::khr::DynamicRendering as DynamicRenderingLoader;
+use ash::vk;
+
+use crate::swapchain::Swapchain;
+
+pub(crate) struct DynamicRendering {
+    device: ash::Device,
+    loader: DynamicRenderingLoader,
+}
+
+impl DynamicRendering {
+    /// `loader` has to be for `device`, created with the extension and its
+    /// feature enabled.
+    pub(crate) fn new(device: &ash::Device, loader: DynamicRenderingLoader) -> Self {
+        DynamicRendering {
+            device: device.clone(),
+            loader,
+        }
+    }
+
+    /// Starts rendering into swapchain image `image_index` and the depth
+    /// image, and its stencil if it has any, clearing all of them. Everything
+    /// else that was in the image is thrown away. What's drawn comes from
+    /// secondary command buffers.
+    pub(crate) fn begin(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain: &Swapchain,
+        image_index: usize,
+        clear_color: [f32; 4],
+    ) {
+        let depth_image = swapchain.depth_image();
+        // Like the render pass dependency, the color layout transition waits
+        // for the stage draw_frame waits for the image in, and clearing the
+        // shared depth image waits for the previous frame's depth tests
+        let color_barrier = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: swapchain.images[image_index],
+            subresource_range: subresource_range(vk::ImageAspectFlags::COLOR),
+            ..Default::default()
+        };
+        let depth_barrier = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            new_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            image: depth_image.image,
+            subresource_range: subresource_range(swapchain.depth_aspect_mask()),
+            ..color_barrier
+        };
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[color_barrier, depth_barrier],
+            );
+        }
+
+        let color_attachments = [vk::RenderingAttachmentInfo {
+            image_view: swapchain.image_views[image_index],
+            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_color,
+                },
+            },
+            ..Default::default()
+        }];
+        // Depth and stencil are only needed while rendering, so they're not
+        // stored
+        let depth_attachment = vk::RenderingAttachmentInfo {
+            image_view: depth_image.view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+            ..Default::default()
+        };
+        let has_stencil = swapchain
+            .depth_aspect_mask()
+            .contains(vk::ImageAspectFlags::STENCIL);
+        let rendering_info = vk::RenderingInfo {
+            flags: vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: swapchain.extent,
+            },
+            layer_count: 1,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_attachment: &depth_attachment,
+            // The same view and clear value, for the stencil aspect
+            p_stencil_attachment: if has_stencil {
+                &depth_attachment
+            } else {
+                std::ptr::null()
+            },
+            ..Default::default()
+        };
+        unsafe {
+            self.loader
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    /// Ends what `begin` started, leaving the swapchain image ready to be
+    /// presented.
+    pub(crate) fn end(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain: &Swapchain,
+        image_index: usize,
+    ) {
+        let present_barrier = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: swapchain.images[image_index],
+            subresource_range: subresource_range(vk::ImageAspectFlags::COLOR),
+            ..Default::default()
+        };
+        unsafe {
+            self.loader.cmd_end_rendering(command_buffer);
+            // Present waits on the frame's semaphore, which covers all of
+            // it. Ending at COLOR_ATTACHMENT_OUTPUT lets a screenshot copy
+            // recorded after this wait for the transition.
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[present_barrier],
+            );
+        }
+    }
+}
+
+fn subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
@@ -0,0 +1,387 @@
+//! The `gui` feature: an egui window drawn over the finished frame, with
+//! the `overlay` renderer, like the `imgui` one.
+//!
+//! There's no egui-winit for the winit we're on, so `handle_event` turns
+//! the mouse and text input into egui events itself. egui lays out in
+//! points, which are the window's logical pixels.
+//!
+//! egui's only texture is its font atlas, which grows and changes as new
+//! glyphs are drawn. The overlay keeps a copy of it, and hands the whole of
+//! it over with `take_font_update` whenever it changed.
+
+use std::path::Path;
+
+use ash::vk;
+use egui::epaint::{Primitive, Vertex};
+use egui::{ImageData, TextureId, TexturesDelta};
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::allocator::SharedAllocator;
+use crate::overlay::{clip_to_scissor, OverlayPipelineInfo, OverlayRenderer, Transform};
+use crate::resources::Image;
+use crate::swapchain::Swapchain;
+
+/// The font atlas as egui last described it, in RGBA8 rows.
+#[derive(Default)]
+struct FontAtlas {
+    size: [usize; 2],
+    pixels: Vec<u8>,
+    /// Whether it changed since `take_font_update`.
+    changed: bool,
+}
+
+impl FontAtlas {
+    /// Applies egui's changes to the font atlas. Other textures are never
+    /// created, nothing we draw uses images.
+    fn apply(&mut self, textures_delta: TexturesDelta) {
+        for (id, delta) in textures_delta.set {
+            if id != TextureId::default() {
+                continue;
+            }
+            let ImageData::Color(image) = delta.image;
+            let pixels = image.pixels.iter().flat_map(|pixel| pixel.to_array());
+            match delta.pos {
+                None => {
+                    self.size = image.size;
+                    self.pixels = pixels.collect();
+                }
+                Some([x, y]) => {
+                    let [width, height] = image.size;
+                    let row_length = width * 4;
+                    let pixels: Vec<u8> = pixels.collect();
+                    for row in 0..height {
+                        let start = ((y + row) * self.size[0] + x) * 4;
+                        self.pixels[start..start + row_length]
+                            .copy_from_slice(&pixels[row * row_length..(row + 1) * row_length]);
+                    }
+                }
+            }
+            self.changed = true;
+        }
+    }
+}
+
+/// The egui context, and what its meshes are drawn with.
+pub(crate) struct EguiOverlay {
+    context: egui::Context,
+    /// The input since the last `prepare`.
+    input: egui::RawInput,
+    /// egui needs to know where clicks are, which winit only says when
+    /// the mouse moves.
+    pointer_position: egui::Pos2,
+    /// Scales egui's points to the swapchain's physical pixels.
+    hidpi_factor: f64,
+    font_atlas: FontAtlas,
+    /// `None` until the first `set_font_image`, there's nothing to draw
+    /// text with until then.
+    font_image: Option<Image>,
+    renderer: OverlayRenderer,
+}
+
+impl EguiOverlay {
+    /// Creates an egui context to draw on the images of `swapchain`. Fails
+    /// if the shaders in `shader_dir` are broken.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        swapchain: &Swapchain,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+    ) -> Result<Self, String> {
+        // egui's `Vertex`es, whose colors are premultiplied
+        let info = OverlayPipelineInfo {
+            name: "egui",
+            vertex_stride: std::mem::size_of::<Vertex>(),
+            attribute_offsets: [
+                std::mem::offset_of!(Vertex, pos),
+                std::mem::offset_of!(Vertex, uv),
+                std::mem::offset_of!(Vertex, color),
+            ],
+            blend: vk::PipelineColorBlendAttachmentState {
+                color_write_mask: vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+                blend_enable: vk::TRUE,
+                src_color_blend_factor: vk::BlendFactor::ONE,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_DST_ALPHA,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE,
+                alpha_blend_op: vk::BlendOp::ADD,
+            },
+            index_type: vk::IndexType::UINT32,
+        };
+        let renderer = OverlayRenderer::new(
+            device,
+            allocator,
+            swapchain,
+            pipeline_cache,
+            shader_dir,
+            &info,
+        )?;
+        Ok(EguiOverlay {
+            context: egui::Context::default(),
+            input: egui::RawInput::default(),
+            pointer_position: egui::Pos2::ZERO,
+            hidpi_factor: 1.0,
+            font_atlas: FontAtlas::default(),
+            font_image: None,
+            renderer,
+        })
+    }
+
+    /// Draws over the swapchain image, which it leaves ready to present.
+    pub(crate) fn render_pass(&self) -> vk::RenderPass {
+        self.renderer.render_pass()
+    }
+
+    /// Creates the framebuffers for the swapchain images, e.g. after the
+    /// swapchain created them again. The GPU can't be using them.
+    pub(crate) fn recreate(&mut self, swapchain: &Swapchain) {
+        self.renderer.recreate(swapchain);
+    }
+
+    /// The framebuffers, one per swapchain image, for debug names.
+    pub(crate) fn framebuffers(&self) -> &[vk::Framebuffer] {
+        self.renderer.framebuffers()
+    }
+
+    /// Passes the mouse and text input in `event` on to egui. Returns
+    /// whether egui takes the mouse button or wheel it's about, so the
+    /// camera leaves it alone. That's decided by the previous frame, it's
+    /// all egui knows yet.
+    pub(crate) fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match *event {
+            WindowEvent::CursorMoved {
+                position,
+                modifiers,
+                ..
+            } => {
+                self.pointer_position = egui::pos2(position.x as f32, position.y as f32);
+                self.input.modifiers = to_egui_modifiers(modifiers);
+                self.input
+                    .events
+                    .push(egui::Event::PointerMoved(self.pointer_position));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput {
+                state,
+                button,
+                modifiers,
+                ..
+            } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                self.input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer_position,
+                    button,
+                    pressed: state == ElementState::Pressed,
+                    modifiers: to_egui_modifiers(modifiers),
+                });
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel {
+                delta, modifiers, ..
+            } => {
+                let (unit, delta) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        (egui::MouseWheelUnit::Line, egui::vec2(x, y))
+                    }
+                    MouseScrollDelta::PixelDelta(position) => (
+                        egui::MouseWheelUnit::Point,
+                        egui::vec2(position.x as f32, position.y as f32),
+                    ),
+                };
+                self.input.events.push(egui::Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers: to_egui_modifiers(modifiers),
+                });
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::ReceivedCharacter(character) if !character.is_control() => {
+                self.input
+                    .events
+                    .push(egui::Event::Text(character.to_string()));
+                false
+            }
+            WindowEvent::Focused(focused) => {
+                self.input.focused = focused;
+                self.input.events.push(egui::Event::WindowFocused(focused));
+                false
+            }
+            WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                self.hidpi_factor = hidpi_factor;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the scale factor of the window the overlay is shown in.
+    pub(crate) fn set_hidpi_factor(&mut self, hidpi_factor: f64) {
+        self.hidpi_factor = hidpi_factor;
+    }
+
+    /// Runs egui `dt` seconds after the last frame, has `build` lay out the
+    /// windows, and uploads the result to frame slot `frame`, whose
+    /// previous frame has to be done.
+    pub(crate) fn prepare(
+        &mut self,
+        frame: usize,
+        extent: vk::Extent2D,
+        dt: f32,
+        build: impl FnMut(&egui::Context),
+    ) {
+        let pixels_per_point = self.hidpi_factor as f32;
+        let screen_size = [
+            extent.width as f32 / pixels_per_point,
+            extent.height as f32 / pixels_per_point,
+        ];
+        let mut input = std::mem::take(&mut self.input);
+        // Focus and modifiers last until winit says otherwise
+        self.input.focused = input.focused;
+        self.input.modifiers = input.modifiers;
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(screen_size[0], screen_size[1]),
+        ));
+        input
+            .viewports
+            .entry(input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+        input.predicted_dt = dt;
+        let output = self.context.run(input, build);
+        self.font_atlas.apply(output.textures_delta);
+        let primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+
+        let meshes: Vec<_> = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                // Nothing we draw uses callbacks or images
+                Primitive::Mesh(mesh) if mesh.texture_id == TextureId::default() => {
+                    Some((primitive.clip_rect, mesh))
+                }
+                _ => None,
+            })
+            .collect();
+        let vertex_count: usize = meshes.iter().map(|(_, mesh)| mesh.vertices.len()).sum();
+        let index_count: usize = meshes.iter().map(|(_, mesh)| mesh.indices.len()).sum();
+        let transform = Transform::new([0.0, 0.0], screen_size);
+        let Some(mut upload) =
+            self.renderer
+                .upload::<Vertex, u32>(frame, transform, vertex_count, index_count)
+        else {
+            return;
+        };
+        // All meshes go one after the other into the same buffers
+        let scale = [output.pixels_per_point; 2];
+        for (clip_rect, mesh) in meshes {
+            let (vertex_base, index_base) = upload.push_mesh(&mesh.vertices, &mesh.indices);
+            let clip_rect = [
+                clip_rect.min.x,
+                clip_rect.min.y,
+                clip_rect.max.x,
+                clip_rect.max.y,
+            ];
+            if let Some(scissor) = clip_to_scissor(clip_rect, [0.0, 0.0], scale, extent) {
+                upload.push_draw(scissor, mesh.indices.len(), index_base, vertex_base);
+            }
+        }
+    }
+
+    /// The whole font atlas, its width, height and RGBA8 pixels, if it
+    /// changed since the last call.
+    pub(crate) fn take_font_update(&mut self) -> Option<(u32, u32, Vec<u8>)> {
+        let atlas = &mut self.font_atlas;
+        if !atlas.changed {
+            return None;
+        }
+        atlas.changed = false;
+        Some((
+            atlas.size[0] as u32,
+            atlas.size[1] as u32,
+            atlas.pixels.clone(),
+        ))
+    }
+
+    /// Draws text with `font_image` from now on, in place of the previous
+    /// one. The GPU can't be using either.
+    pub(crate) fn set_font_image(&mut self, font_image: Image) {
+        self.renderer.set_image(font_image.view);
+        self.font_image = Some(font_image);
+    }
+
+    /// Draws what `prepare` uploaded to frame slot `frame` over swapchain
+    /// image `image_index`, in a render pass of its own.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        image_index: usize,
+        extent: vk::Extent2D,
+    ) {
+        if self.font_image.is_none() {
+            return;
+        }
+        self.renderer
+            .record(command_buffer, frame, image_index, extent);
+    }
+}
+
+fn to_egui_modifiers(modifiers: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: modifiers.alt,
+        ctrl: modifiers.ctrl,
+        shift: modifiers.shift,
+        mac_cmd: cfg!(target_os = "macos") && modifiers.logo,
+        command: if cfg!(target_os = "macos") {
+            modifiers.logo
+        } else {
+            modifiers.ctrl
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_atlas_patches_rows_in_place() {
+        let mut atlas = FontAtlas::default();
+        let full = egui::ColorImage::new([4, 2], vec![egui::Color32::BLACK; 8]);
+        let mut delta = TexturesDelta::default();
+        delta.set.push((
+            TextureId::default(),
+            egui::epaint::ImageDelta::full(full, egui::TextureOptions::LINEAR),
+        ));
+        atlas.apply(delta);
+        assert_eq!(atlas.size, [4, 2]);
+
+        let patch = egui::ColorImage::new([2, 1], vec![egui::Color32::WHITE; 2]);
+        let mut delta = TexturesDelta::default();
+        delta.set.push((
+            TextureId::default(),
+            egui::epaint::ImageDelta::partial([1, 1], patch, egui::TextureOptions::LINEAR),
+        ));
+        atlas.apply(delta);
+        // The second row, from the second pixel on
+        let row = &atlas.pixels[16..];
+        assert_eq!(&row[..4], &[0, 0, 0, 255]);
+        assert_eq!(&row[4..12], &[255; 8]);
+        assert_eq!(&row[12..], &[0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Optional device features. Each one is only enabled if the device has it,
+//! and whatever depends on a missing one turns itself off.
+
+use ash::vk;
+
+/// The optional `vk::PhysicalDeviceFeatures` the app knows how to use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestedFeatures {
+    pub sampler_anisotropy: bool,
+    /// Needed for the wireframe pipeline.
+    pub fill_mode_non_solid: bool,
+    pub wide_lines: bool,
+    /// Needed for `--sample-shading`.
+    pub sample_rate_shading: bool,
+}
+
+impl Default for RequestedFeatures {
+    /// Only what the app currently uses.
+    fn default() -> Self {
+        RequestedFeatures {
+            sampler_anisotropy: false,
+            fill_mode_non_solid: true,
+            wide_lines: true,
+            sample_rate_shading: true,
+        }
+    }
+}
+
+impl RequestedFeatures {
+    const NONE: RequestedFeatures = RequestedFeatures {
+        sampler_anisotropy: false,
+        fill_mode_non_solid: false,
+        wide_lines: false,
+        sample_rate_shading: false,
+    };
+
+    /// Splits the requested features into the ones `supported` has, which
+    /// are the ones to enable, and the ones it doesn't.
+    pub(crate) fn resolve(
+        self,
+        supported: &vk::PhysicalDeviceFeatures,
+    ) -> (RequestedFeatures, RequestedFeatures) {
+        let mut enabled = Self::NONE;
+        let mut denied = Self::NONE;
+        let split = |requested: bool, supported: vk::Bool32| -> (bool, bool) {
+            (
+                requested && supported == vk::TRUE,
+                requested && supported != vk::TRUE,
+            )
+        };
+        (enabled.sampler_anisotropy, denied.sampler_anisotropy) =
+            split(self.sampler_anisotropy, supported.sampler_anisotropy);
+        (enabled.fill_mode_non_solid, denied.fill_mode_non_solid) =
+            split(self.fill_mode_non_solid, supported.fill_mode_non_solid);
+        (enabled.wide_lines, denied.wide_lines) = split(self.wide_lines, supported.wide_lines);
+        (enabled.sample_rate_shading, denied.sample_rate_shading) =
+            split(self.sample_rate_shading, supported.sample_rate_shading);
+        (enabled, denied)
+    }
+
+    pub(crate) fn to_vk(self) -> vk::PhysicalDeviceFeatures {
+        let bool32 = |enabled: bool| if enabled { vk::TRUE } else { vk::FALSE };
+        vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: bool32(self.sampler_anisotropy),
+            fill_mode_non_solid: bool32(self.fill_mode_non_solid),
+            wide_lines: bool32(self.wide_lines),
+            sample_rate_shading: bool32(self.sample_rate_shading),
+            ..Default::default()
+        }
+    }
+
+    /// The names of the features that are set, as Vulkan spells them.
+    pub(crate) fn names(self) -> Vec<&'static str> {
+        [
+            (self.sampler_anisotropy, "samplerAnisotropy"),
+            (self.fill_mode_non_solid, "fillModeNonSolid"),
+            (self.wide_lines, "wideLines"),
+            (self.sample_rate_shading, "sampleRateShading"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|&(_, name)| name)
+        .collect()
+    }
+
+    pub(crate) fn is_empty(self) -> bool {
+        self == Self::NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_enables_only_supported_requests() {
+        let requested = RequestedFeatures {
+            sampler_anisotropy: true,
+            fill_mode_non_solid: true,
+            wide_lines: false,
+            sample_rate_shading: true,
+        };
+        let supported = vk::PhysicalDeviceFeatures {
+            fill_mode_non_solid: vk::TRUE,
+            wide_lines: vk::TRUE,
+            sample_rate_shading: vk::TRUE,
+            ..Default::default()
+        };
+        let (enabled, denied) = requested.resolve(&supported);
+        assert_eq!(enabled.names(), ["fillModeNonSolid", "sampleRateShading"]);
+        assert_eq!(denied.names(), ["samplerAnisotropy"]);
+        assert_eq!(enabled.to_vk().wide_lines, vk::FALSE);
+    }
+}
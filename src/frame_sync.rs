@@ -0,0 +1,214 @@
+//! Tells the CPU when the GPU is done with a frame's submission, so its
+//! command buffer and per-frame resources can be reused.
+//!
+//! With `VK_KHR_timeline_semaphore` (core in Vulkan 1.2) that's a single
+//! semaphore whose value counts up with every submission, and waiting for a
+//! frame is waiting for the value it signaled. Without it, each frame in
+//! flight has a fence that's reset before every submission.
+
+use ash::extensions::khr::TimelineSemaphore;
+use ash::vk;
+
+/// What to wait on for a submission to be done.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Submission {
+    Fence(vk::Fence),
+    /// The value the timeline semaphore reaches when it's done.
+    Timeline(u64),
+}
+
+enum Kind {
+    Fences(Vec<vk::Fence>),
+    Timeline {
+        loader: TimelineSemaphore,
+        semaphore: vk::Semaphore,
+        /// What the most recent submission signals.
+        last_value: u64,
+        /// What each frame slot's most recent submission signals. Zero, which
+        /// the semaphore starts at, if it didn't submit anything yet.
+        frame_values: Vec<u64>,
+    },
+}
+
+pub(crate) struct FrameSync {
+    device: ash::Device,
+    kind: Kind,
+}
+
+impl FrameSync {
+    /// Uses a timeline semaphore if `timeline` is loaded, which needs the
+    /// device to be created with its extension and feature enabled.
+    pub(crate) fn new(
+        device: &ash::Device,
+        timeline: Option<TimelineSemaphore>,
+        frame_count: usize,
+    ) -> FrameSync {
+        let kind = match timeline {
+            Some(loader) => {
+                let type_create_info = vk::SemaphoreTypeCreateInfo {
+                    semaphore_type: vk::SemaphoreType::TIMELINE,
+                    initial_value: 0,
+                    ..Default::default()
+                };
+                let create_info = vk::SemaphoreCreateInfo {
+                    p_next: &type_create_info as *const _ as *const _,
+                    ..Default::default()
+                };
+                let semaphore = unsafe {
+                    device
+                        .create_semaphore(&create_info, None)
+                        .expect("Failed to create timeline Semaphore!")
+                };
+                Kind::Timeline {
+                    loader,
+                    semaphore,
+                    last_value: 0,
+                    frame_values: vec![0; frame_count],
+                }
+            }
+            None => {
+                let fence_create_info = vk::FenceCreateInfo {
+                    // Start signaled so the first wait in draw_frame doesn't block forever
+                    flags: vk::FenceCreateFlags::SIGNALED,
+                    ..Default::default()
+                };
+                let fences = (0..frame_count)
+                    .map(|_| unsafe {
+                        device
+                            .create_fence(&fence_create_info, None)
+                            .expect("Failed to create Fence!")
+                    })
+                    .collect();
+                Kind::Fences(fences)
+            }
+        };
+        FrameSync {
+            device: device.clone(),
+            kind,
+        }
+    }
+
+    pub(crate) fn is_timeline(&self) -> bool {
+        matches!(self.kind, Kind::Timeline { .. })
+    }
+
+    /// The most recent submission of frame slot `frame`.
+    pub(crate) fn last_submission(&self, frame: usize) -> Submission {
+        match &self.kind {
+            Kind::Fences(fences) => Submission::Fence(fences[frame]),
+            Kind::Timeline { frame_values, .. } => Submission::Timeline(frame_values[frame]),
+        }
+    }
+
+    /// Blocks until `submission` is done.
+    pub(crate) fn wait(&self, submission: Submission) -> Result<(), vk::Result> {
+        match (&self.kind, submission) {
+            (_, Submission::Fence(fence)) => unsafe {
+                self.device.wait_for_fences(&[fence], true, u64::MAX)
+            },
+            (
+                Kind::Timeline {
+                    loader, semaphore, ..
+                },
+                Submission::Timeline(value),
+            ) => {
+                let semaphores = [*semaphore];
+                let values = [value];
+                let wait_info = vk::SemaphoreWaitInfo {
+                    semaphore_count: semaphores.len() as u32,
+                    p_semaphores: semaphores.as_ptr(),
+                    p_values: values.as_ptr(),
+                    ..Default::default()
+                };
+                unsafe { loader.wait_semaphores(&wait_info, u64::MAX) }
+            }
+            (Kind::Fences(_), Submission::Timeline(_)) => {
+                unreachable!("Timeline submission without a timeline semaphore")
+            }
+        }
+    }
+
+    /// The next submission of frame slot `frame`, which has to be done
+    /// already. Only call this once it's certain to be submitted, as its
+    /// fence is reset.
+    pub(crate) fn next_submission(&mut self, frame: usize) -> Submission {
+        match &mut self.kind {
+            Kind::Fences(fences) => {
+                unsafe {
+                    self.device
+                        .reset_fences(&[fences[frame]])
+                        .expect("Failed to reset Fence!");
+                }
+                Submission::Fence(fences[frame])
+            }
+            Kind::Timeline {
+                last_value,
+                frame_values,
+                ..
+            } => {
+                *last_value += 1;
+                frame_values[frame] = *last_value;
+                Submission::Timeline(*last_value)
+            }
+        }
+    }
+
+    /// Submits `submit_info` to `queue`, marking `submission` done when it
+    /// finishes. A timeline semaphore is signaled on top of the semaphores
+    /// `submit_info` signals already.
+    pub(crate) fn submit(
+        &self,
+        queue: vk::Queue,
+        submission: Submission,
+        mut submit_info: vk::SubmitInfo,
+    ) -> Result<(), vk::Result> {
+        match (&self.kind, submission) {
+            (_, Submission::Fence(fence)) => unsafe {
+                self.device.queue_submit(queue, &[submit_info], fence)
+            },
+            (Kind::Timeline { semaphore, .. }, Submission::Timeline(value)) => {
+                let signal_count = submit_info.signal_semaphore_count as usize;
+                let mut signal_semaphores: Vec<vk::Semaphore> = (0..signal_count)
+                    .map(|i| unsafe { *submit_info.p_signal_semaphores.add(i) })
+                    .collect();
+                signal_semaphores.push(*semaphore);
+                // Values of binary semaphores are ignored
+                let mut signal_values = vec![0; signal_count];
+                signal_values.push(value);
+                let timeline_submit_info = vk::TimelineSemaphoreSubmitInfo {
+                    p_next: submit_info.p_next,
+                    signal_semaphore_value_count: signal_values.len() as u32,
+                    p_signal_semaphore_values: signal_values.as_ptr(),
+                    ..Default::default()
+                };
+                submit_info.p_next = &timeline_submit_info as *const _ as *const _;
+                submit_info.signal_semaphore_count = signal_semaphores.len() as u32;
+                submit_info.p_signal_semaphores = signal_semaphores.as_ptr();
+                unsafe {
+                    self.device
+                        .queue_submit(queue, &[submit_info], vk::Fence::null())
+                }
+            }
+            (Kind::Fences(_), Submission::Timeline(_)) => {
+                unreachable!("Timeline submission without a timeline semaphore")
+            }
+        }
+    }
+}
+
+impl Drop for FrameSync {
+    fn drop(&mut self) {
+        unsafe {
+            match &self.kind {
+                Kind::Fences(fences) => {
+                    for &fence in fences {
+                        self.device.destroy_fence(fence, None);
+                    }
+                }
+                Kind::Timeline { semaphore, .. } => {
+                    self.device.destroy_semaphore(*semaphore, None);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Frustum culling: the scene objects the camera can't see are left out of
+//! the draws on the CPU, before anything is recorded for them.
+
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::mesh::Vertex;
+
+/// A sphere around everything in a mesh, in the mesh's own space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BoundingSphere {
+    pub(crate) center: Vec3,
+    pub(crate) radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on the box around `vertices`, which is a little bigger than
+    /// the smallest sphere but quick to find. Empty meshes get a point.
+    pub(crate) fn around(vertices: &[Vertex]) -> Self {
+        if vertices.is_empty() {
+            return BoundingSphere {
+                center: Vec3::ZERO,
+                radius: 0.0,
+            };
+        }
+        let positions = vertices.iter().map(|vertex| Vec3::from(vertex.position));
+        let (min, max) = positions.clone().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), position| (min.min(position), max.max(position)),
+        );
+        let center = (min + max) / 2.0;
+        let radius = positions
+            .map(|position| position.distance(center))
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// The sphere around the mesh once it's moved by `transform`. Scaling
+    /// that isn't the same along every axis grows it by the largest one.
+    pub(crate) fn transformed(&self, transform: Mat4) -> Self {
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// The six planes around what a view and projection show, facing inwards.
+pub(crate) struct Frustum {
+    /// `xyz` is the plane's unit normal and `w` its distance from the
+    /// origin along it.
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from the rows of `view_proj`, for Vulkan's clip
+    /// space where depth goes from 0 to 1.
+    pub(crate) fn from_view_proj(view_proj: Mat4) -> Self {
+        let row = |index| view_proj.row(index);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| plane / plane.truncate().length());
+        Frustum { planes }
+    }
+
+    /// Whether any of `sphere` is inside, in the space the frustum was made
+    /// for. Spheres just outside a corner still count as inside.
+    pub(crate) fn intersects(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+}
+
+/// How many of the scene objects were drawn last frame and how many were
+/// outside the frustum, or hidden behind others with `--occlusion-culling`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct CullStats {
+    pub(crate) drawn: usize,
+    pub(crate) culled: usize,
+    pub(crate) occluded: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> Vertex {
+        Vertex {
+            position,
+            color: [1.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            uv: [0.0; 2],
+        }
+    }
+
+    fn frustum() -> Frustum {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let mut proj = Mat4::perspective_rh(45.0_f32.to_radians(), 1.0, 0.1, 10.0);
+        proj.y_axis.y *= -1.0;
+        Frustum::from_view_proj(proj * view)
+    }
+
+    fn sphere(center: Vec3, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    #[test]
+    fn bounding_sphere_covers_vertices() {
+        let vertices = [
+            vertex([-1.0, 0.0, 0.0]),
+            vertex([3.0, 0.0, 0.0]),
+            vertex([1.0, 2.0, 0.0]),
+        ];
+        let bounds = BoundingSphere::around(&vertices);
+        assert_eq!(bounds.center, Vec3::new(1.0, 1.0, 0.0));
+        for vertex in vertices {
+            assert!(Vec3::from(vertex.position).distance(bounds.center) <= bounds.radius + 1e-5);
+        }
+    }
+
+    #[test]
+    fn bounding_sphere_scales_with_largest_axis() {
+        let bounds = sphere(Vec3::X, 1.0).transformed(
+            Mat4::from_translation(Vec3::Y) * Mat4::from_scale(Vec3::new(1.0, 3.0, 2.0)),
+        );
+        assert_eq!(bounds, sphere(Vec3::new(1.0, 1.0, 0.0), 3.0));
+    }
+
+    #[test]
+    fn frustum_keeps_what_the_camera_sees() {
+        let frustum = frustum();
+        assert!(frustum.intersects(&sphere(Vec3::ZERO, 0.5)));
+        // Off to the side, but reaching into view
+        assert!(frustum.intersects(&sphere(Vec3::new(3.0, 0.0, 0.0), 1.5)));
+    }
+
+    #[test]
+    fn frustum_culls_what_the_camera_does_not_see() {
+        let frustum = frustum();
+        // Beside, behind and past the far plane
+        assert!(!frustum.intersects(&sphere(Vec3::new(5.0, 0.0, 0.0), 0.5)));
+        assert!(!frustum.intersects(&sphere(Vec3::new(0.0, 0.0, 6.0), 0.5)));
+        assert!(!frustum.intersects(&sphere(Vec3::new(0.0, 0.0, -6.0), 0.5)));
+    }
+}
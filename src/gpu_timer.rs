@@ -0,0 +1,113 @@
+//! Measures how long the GPU spends on each frame with timestamp queries.
+
+use std::cell::Cell;
+
+use ash::vk;
+
+/// Two timestamps per frame in flight, written at the start and the end of
+/// the frame's command buffer.
+pub(crate) struct GpuTimer {
+    device: ash::Device,
+    query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    /// Timestamps only have this many meaningful low bits.
+    timestamp_mask: u64,
+    /// Whether a frame slot's queries were written and can be read back.
+    written: Vec<Cell<bool>>,
+}
+
+impl GpuTimer {
+    /// Returns `None` if queues of `queue_family` can't write timestamps.
+    pub(crate) fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family: u32,
+        frame_count: usize,
+    ) -> Option<GpuTimer> {
+        let queue_families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let valid_bits = queue_families[queue_family as usize].timestamp_valid_bits;
+        if valid_bits == 0 {
+            return None;
+        }
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+
+        let create_info = vk::QueryPoolCreateInfo {
+            query_type: vk::QueryType::TIMESTAMP,
+            query_count: 2 * frame_count as u32,
+            ..Default::default()
+        };
+        let query_pool = unsafe {
+            device
+                .create_query_pool(&create_info, None)
+                .expect("Failed to create Query Pool!")
+        };
+        Some(GpuTimer {
+            device: device.clone(),
+            query_pool,
+            timestamp_period: f64::from(properties.limits.timestamp_period),
+            timestamp_mask: u64::MAX >> (64 - valid_bits),
+            written: vec![Cell::new(false); frame_count],
+        })
+    }
+
+    /// Records the frame's start timestamp. Has to go before the render
+    /// pass, since queries can't be reset inside one.
+    pub(crate) fn write_start(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let first_query = 2 * frame as u32;
+        unsafe {
+            self.device
+                .cmd_reset_query_pool(command_buffer, self.query_pool, first_query, 2);
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.query_pool,
+                first_query,
+            );
+        }
+    }
+
+    pub(crate) fn write_end(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        unsafe {
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pool,
+                2 * frame as u32 + 1,
+            );
+        }
+        self.written[frame].set(true);
+    }
+
+    /// Returns how long the last submission for `frame` took on the GPU, in
+    /// milliseconds. Only call this once the frame is done.
+    pub(crate) fn read_ms(&self, frame: usize) -> Option<f64> {
+        if !self.written[frame].get() {
+            return None;
+        }
+        let mut timestamps = [0u64; 2];
+        unsafe {
+            self.device
+                .get_query_pool_results(
+                    self.query_pool,
+                    2 * frame as u32,
+                    2,
+                    &mut timestamps,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+                .ok()?;
+        }
+        let ticks = timestamps[1].wrapping_sub(timestamps[0]) & self.timestamp_mask;
+        Some(ticks as f64 * self.timestamp_period / 1_000_000.0)
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_query_pool(self.query_pool, None);
+        }
+    }
+}
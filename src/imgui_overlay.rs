@@ -0,0 +1,224 @@
+//! The `imgui` feature: a Dear ImGui window drawn over the finished frame,
+//! with the `overlay` renderer.
+//!
+//! There's no winit backend for the winit we're on, so `handle_event` feeds
+//! imgui the mouse and text input itself. imgui draws in logical pixels,
+//! which are scaled to the swapchain's physical ones.
+
+use std::path::Path;
+
+use ash::vk;
+use imgui::{BackendFlags, DrawCmd, DrawIdx, DrawVert};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::allocator::SharedAllocator;
+use crate::overlay::{clip_to_scissor, OverlayPipelineInfo, OverlayRenderer, Transform};
+use crate::resources::Image;
+use crate::swapchain::Swapchain;
+
+/// The imgui context, and what its draw data is drawn with.
+pub(crate) struct ImguiOverlay {
+    context: imgui::Context,
+    /// Scales imgui's logical pixels to the swapchain's physical ones.
+    hidpi_factor: f64,
+    /// Only kept for the renderer's descriptor set to point at.
+    _font_image: Image,
+    renderer: OverlayRenderer,
+}
+
+impl ImguiOverlay {
+    /// Takes over `context`, whose font atlas is uploaded in `font_image`,
+    /// to draw it on the images of `swapchain`. Fails if the shaders in
+    /// `shader_dir` are broken.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        swapchain: &Swapchain,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        mut context: imgui::Context,
+        font_image: Image,
+    ) -> Result<Self, String> {
+        // Draws with a large vertex count use the offset, to get by with
+        // 16 bit indices
+        context
+            .io_mut()
+            .backend_flags
+            .insert(BackendFlags::RENDERER_HAS_VTX_OFFSET);
+        // Alpha blended `DrawVert`s
+        let info = OverlayPipelineInfo {
+            name: "imgui",
+            vertex_stride: std::mem::size_of::<DrawVert>(),
+            attribute_offsets: [
+                std::mem::offset_of!(DrawVert, pos),
+                std::mem::offset_of!(DrawVert, uv),
+                std::mem::offset_of!(DrawVert, col),
+            ],
+            blend: vk::PipelineColorBlendAttachmentState {
+                color_write_mask: vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+                blend_enable: vk::TRUE,
+                src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                alpha_blend_op: vk::BlendOp::ADD,
+            },
+            index_type: vk::IndexType::UINT16,
+        };
+        let renderer = OverlayRenderer::new(
+            device,
+            allocator,
+            swapchain,
+            pipeline_cache,
+            shader_dir,
+            &info,
+        )?;
+        renderer.set_image(font_image.view);
+        Ok(ImguiOverlay {
+            context,
+            hidpi_factor: 1.0,
+            _font_image: font_image,
+            renderer,
+        })
+    }
+
+    /// Draws over the swapchain image, which it leaves ready to present.
+    pub(crate) fn render_pass(&self) -> vk::RenderPass {
+        self.renderer.render_pass()
+    }
+
+    /// Creates the framebuffers for the swapchain images, e.g. after the
+    /// swapchain created them again. The GPU can't be using them.
+    pub(crate) fn recreate(&mut self, swapchain: &Swapchain) {
+        self.renderer.recreate(swapchain);
+    }
+
+    /// The framebuffers, one per swapchain image, for debug names.
+    pub(crate) fn framebuffers(&self) -> &[vk::Framebuffer] {
+        self.renderer.framebuffers()
+    }
+
+    /// Passes the mouse and text input in `event` on to imgui. Returns
+    /// whether imgui takes the mouse button or wheel it's about, so the
+    /// camera leaves it alone. That's decided by the previous frame, it's
+    /// all imgui knows yet.
+    pub(crate) fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let io = self.context.io_mut();
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => {
+                io.add_mouse_pos_event([position.x as f32, position.y as f32]);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => imgui::MouseButton::Left,
+                    MouseButton::Right => imgui::MouseButton::Right,
+                    MouseButton::Middle => imgui::MouseButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                io.add_mouse_button_event(button, state == ElementState::Pressed);
+                io.want_capture_mouse
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let wheel = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => [x, y],
+                    // Roughly one line's worth of pixels per step, like the
+                    // camera's zoom
+                    MouseScrollDelta::PixelDelta(position) => {
+                        [position.x as f32 / 20.0, position.y as f32 / 20.0]
+                    }
+                };
+                io.add_mouse_wheel_event(wheel);
+                io.want_capture_mouse
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                io.add_input_character(character);
+                false
+            }
+            WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                self.hidpi_factor = hidpi_factor;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the scale factor of the window the overlay is shown in.
+    pub(crate) fn set_hidpi_factor(&mut self, hidpi_factor: f64) {
+        self.hidpi_factor = hidpi_factor;
+    }
+
+    /// Starts an imgui frame `dt` seconds after the last one, has `build`
+    /// lay out the windows, and uploads the result to frame slot `frame`,
+    /// whose previous frame has to be done.
+    pub(crate) fn prepare(
+        &mut self,
+        frame: usize,
+        extent: vk::Extent2D,
+        dt: f32,
+        build: impl FnOnce(&imgui::Ui),
+    ) {
+        let io = self.context.io_mut();
+        io.display_size = [
+            (f64::from(extent.width) / self.hidpi_factor) as f32,
+            (f64::from(extent.height) / self.hidpi_factor) as f32,
+        ];
+        io.display_framebuffer_scale = [self.hidpi_factor as f32; 2];
+        // imgui asserts time moves on
+        io.delta_time = dt.max(f32::EPSILON);
+        build(self.context.new_frame());
+        let draw_data = self.context.render();
+
+        let transform = Transform::new(draw_data.display_pos, draw_data.display_size);
+        let Some(mut upload) = self.renderer.upload::<DrawVert, DrawIdx>(
+            frame,
+            transform,
+            draw_data.total_vtx_count as usize,
+            draw_data.total_idx_count as usize,
+        ) else {
+            return;
+        };
+        // All draw lists go one after the other into the same buffers
+        for draw_list in draw_data.draw_lists() {
+            let (vertex_base, index_base) =
+                upload.push_mesh(draw_list.vtx_buffer(), draw_list.idx_buffer());
+            for command in draw_list.commands() {
+                // Nothing we draw uses callbacks, and the render state is
+                // the same for every draw
+                let DrawCmd::Elements { count, cmd_params } = command else {
+                    continue;
+                };
+                if let Some(scissor) = clip_to_scissor(
+                    cmd_params.clip_rect,
+                    draw_data.display_pos,
+                    draw_data.framebuffer_scale,
+                    extent,
+                ) {
+                    upload.push_draw(
+                        scissor,
+                        count,
+                        index_base + cmd_params.idx_offset,
+                        vertex_base + cmd_params.vtx_offset,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Draws what `prepare` uploaded to frame slot `frame` over swapchain
+    /// image `image_index`, in a render pass of its own.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        image_index: usize,
+        extent: vk::Extent2D,
+    ) {
+        self.renderer
+            .record(command_buffer, frame, image_index, extent);
+    }
+}
@@ -0,0 +1,6801 @@
+//! A Vulkan renderer following vulkan-tutorial.com, built on ash and winit.
+//!
+//! `main.rs` is only a thin binary around [`VulkanApp`], so the setup code
+//! can be reused by other binaries and tests.
+
+// Both draw their debug window over the swapchain image, and both want the
+// mouse before the camera
+#[cfg(all(feature = "gui", feature = "imgui"))]
+compile_error!("The `gui` and `imgui` features are mutually exclusive, enable only one of them");
+
+use std::collections::HashSet;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use ash::extensions::ext::DebugUtils;
+#[cfg(unix)]
+use ash::extensions::khr::XlibSurface;
+use ash::extensions::khr::{
+    self, BufferDeviceAddress, DynamicRendering as DynamicRenderingLoader, Surface,
+    TimelineSemaphore,
+};
+use ash::vk;
+
+use glam::{Mat4, Vec3};
+
+use winit::dpi::{LogicalPosition, LogicalSize};
+use winit::event::{
+    ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+use winit::event_loop::{ControlFlow, EventLoop};
+#[cfg(unix)]
+use winit::platform::unix::{WindowBuilderExtUnix, WindowExtUnix, XWindowType};
+use winit::window::{Fullscreen, Window, WindowBuilder};
+
+mod allocator;
+mod benchmark;
+mod compute;
+mod config;
+mod deferred;
+mod deletion_queue;
+mod device_address;
+mod dynamic_rendering;
+#[cfg(feature = "gui")]
+mod egui_overlay;
+mod features;
+mod frame_sync;
+mod frustum;
+mod gpu_timer;
+#[cfg(feature = "imgui")]
+mod imgui_overlay;
+mod mesh;
+mod objects;
+mod occlusion;
+mod offscreen;
+#[cfg(any(feature = "gui", feature = "imgui"))]
+mod overlay;
+mod particles;
+mod pipelines;
+mod properties2;
+#[cfg(feature = "raytracing")]
+mod raytracing;
+mod renderer;
+mod resources;
+mod scene;
+mod secondary_commands;
+mod skybox;
+mod swapchain;
+mod texture;
+mod texture_array;
+
+use allocator::{MemoryLocation, SharedAllocator};
+use benchmark::Benchmark;
+use compute::{AsyncCompute, ComputeWave};
+pub use config::{Config, PresentMode, CONFIG_PATH};
+use deferred::{Deferred, GBUFFER_FORMATS};
+use deletion_queue::DeletionQueue;
+use device_address::DeviceAddresses;
+use dynamic_rendering::DynamicRendering;
+#[cfg(feature = "gui")]
+use egui_overlay::EguiOverlay;
+pub use features::RequestedFeatures;
+use frame_sync::{FrameSync, Submission};
+use frustum::Frustum;
+use gpu_timer::GpuTimer;
+#[cfg(feature = "imgui")]
+use imgui_overlay::ImguiOverlay;
+use mesh::{Mesh, QUAD_INDICES, QUAD_VERTICES};
+pub use mesh::{Vertex, CUBE_INDICES, CUBE_VERTICES};
+use objects::Objects;
+use occlusion::OcclusionQueries;
+pub use offscreen::PostEffect;
+use offscreen::{Offscreen, ToneMapping, OFFSCREEN_FORMAT};
+use particles::{Particle, Particles};
+use pipelines::{Material, PipelineKey, PipelineManager, RenderTarget};
+pub use pipelines::{Stencil, Topology};
+use properties2::Properties2;
+#[cfg(feature = "raytracing")]
+use raytracing::{RayTracer, TracedMesh};
+pub use renderer::{
+    CubeRenderer, FrameContext, MeshBuffers, QuadRenderer, Renderer, TriangleRenderer,
+};
+use resources::{Buffer, Image, ImageMemory};
+pub use scene::ObjectId;
+use scene::{ObjectBindings, ObjectDraw, ObjectPipelines, RenderObject, Scene};
+use secondary_commands::SecondaryCommands;
+use skybox::{CubemapFaces, Skybox};
+use swapchain::{SwapChainSupportDetails, Swapchain};
+pub use texture_array::TextureId;
+use texture_array::{Storage, TextureArray};
+
+pub const WINDOW_TITLE: &str = "Fcking Vulkan";
+pub const WIDTH: u32 = 800;
+pub const HEIGHT: u32 = 600;
+const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// The highest Vulkan version we know how to use. We settle for less if the
+/// loader doesn't support it.
+const REQUESTED_API_VERSION: u32 = vk::make_api_version(0, 1, 3, 0);
+const PIPELINE_CACHE_PATH: &str = "pipeline_cache.bin";
+// Order in which the vsync toggle key cycles through present modes
+const PRESENT_MODE_CYCLE: [vk::PresentModeKHR; 4] = [
+    vk::PresentModeKHR::FIFO,
+    vk::PresentModeKHR::FIFO_RELAXED,
+    vk::PresentModeKHR::MAILBOX,
+    vk::PresentModeKHR::IMMEDIATE,
+];
+// Values the brightness key cycles through
+const BRIGHTNESS_CYCLE: [f32; 3] = [1.0, 0.6, 0.3];
+/// What `--raytracing` enables on top of buffer device addresses and
+/// descriptor indexing, which other options enable as well.
+const RAY_TRACING_EXTENSIONS: [fn() -> &'static CStr; 5] = [
+    khr::AccelerationStructure::name,
+    khr::RayTracingPipeline::name,
+    khr::DeferredHostOperations::name,
+    // What ray tracing shaders are written in, and what that builds on
+    vk::KhrSpirv14Fn::name,
+    vk::KhrShaderFloatControlsFn::name,
+];
+/// The views `--multiview` renders, left eye first.
+const EYE_COUNT: u32 = 2;
+/// How far apart the `--multiview` eyes are, in world units. A bit more
+/// than people's, next to a model a unit across.
+const EYE_SEPARATION: f32 = 0.1;
+
+// Compiled from shaders/ by build.rs and baked into the binary, so it can be
+// run from any working directory.
+const EMBEDDED_SHADERS: [(&str, &[u8]); 37] = [
+    (
+        "shader.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shader.vert.spv")),
+    ),
+    (
+        "cube.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/cube.vert.spv")),
+    ),
+    (
+        "shader.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shader.frag.spv")),
+    ),
+    (
+        "grid.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/grid.vert.spv")),
+    ),
+    (
+        "grid.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/grid.frag.spv")),
+    ),
+    (
+        "skybox.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/skybox.vert.spv")),
+    ),
+    (
+        "skybox.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/skybox.frag.spv")),
+    ),
+    (
+        "wave.comp",
+        include_bytes!(concat!(env!("OUT_DIR"), "/wave.comp.spv")),
+    ),
+    (
+        "gbuffer.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/gbuffer.frag.spv")),
+    ),
+    (
+        "fullscreen.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/fullscreen.vert.spv")),
+    ),
+    (
+        "lighting.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/lighting.frag.spv")),
+    ),
+    (
+        "textured.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/textured.vert.spv")),
+    ),
+    (
+        "textured.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/textured.frag.spv")),
+    ),
+    (
+        "textured_gbuffer.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/textured_gbuffer.frag.spv")),
+    ),
+    (
+        "bindless.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/bindless.frag.spv")),
+    ),
+    (
+        "bindless_gbuffer.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/bindless_gbuffer.frag.spv")),
+    ),
+    (
+        "particles.comp",
+        include_bytes!(concat!(env!("OUT_DIR"), "/particles.comp.spv")),
+    ),
+    (
+        "particle.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/particle.vert.spv")),
+    ),
+    (
+        "particle.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/particle.frag.spv")),
+    ),
+    (
+        "outline.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/outline.vert.spv")),
+    ),
+    (
+        "outline.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/outline.frag.spv")),
+    ),
+    (
+        "present.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/present.frag.spv")),
+    ),
+    (
+        "egui.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/egui.vert.spv")),
+    ),
+    (
+        "egui.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/egui.frag.spv")),
+    ),
+    (
+        "imgui.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/imgui.vert.spv")),
+    ),
+    (
+        "imgui.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/imgui.frag.spv")),
+    ),
+    (
+        "shader_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shader_multiview.vert.spv")),
+    ),
+    (
+        "cube_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/cube_multiview.vert.spv")),
+    ),
+    (
+        "grid_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/grid_multiview.vert.spv")),
+    ),
+    (
+        "skybox_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/skybox_multiview.vert.spv")),
+    ),
+    (
+        "occlusion_box.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/occlusion_box.vert.spv")),
+    ),
+    (
+        "occlusion_box.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/occlusion_box.frag.spv")),
+    ),
+    (
+        "textured_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/textured_multiview.vert.spv")),
+    ),
+    (
+        "particle_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/particle_multiview.vert.spv")),
+    ),
+    (
+        "outline_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/outline_multiview.vert.spv")),
+    ),
+    (
+        "pulled.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/pulled.vert.spv")),
+    ),
+    (
+        "pulled_multiview.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/pulled_multiview.vert.spv")),
+    ),
+];
+// Compiled by build.rs only with the `raytracing` feature
+#[cfg(feature = "raytracing")]
+const EMBEDDED_RAYTRACING_SHADERS: [(&str, &[u8]); 3] = [
+    (
+        "raytrace.rgen",
+        include_bytes!(concat!(env!("OUT_DIR"), "/raytrace.rgen.spv")),
+    ),
+    (
+        "raytrace.rmiss",
+        include_bytes!(concat!(env!("OUT_DIR"), "/raytrace.rmiss.spv")),
+    ),
+    (
+        "raytrace.rchit",
+        include_bytes!(concat!(env!("OUT_DIR"), "/raytrace.rchit.spv")),
+    ),
+];
+#[cfg(not(feature = "raytracing"))]
+const EMBEDDED_RAYTRACING_SHADERS: [(&str, &[u8]); 0] = [];
+
+pub const USAGE: &str = "Usage: vulkan-tutorial-rs [options]
+    --gpu <index>            Render on the device with this index
+    --gpu-name <substring>   Render on the first device whose name contains this
+    --gpu-id <id>            Render on the device with this PCI device ID, like
+                             0x1f82, as listed by --list-gpus
+    --list-gpus              Print all Vulkan devices and exit
+    --shader-dir <dir>       Load compiled .spv shaders from <dir> instead of the
+                             ones embedded in the binary
+    --clear-color <r,g,b>    Background color, each component in 0.0..=1.0
+    --width <pixels>         Initial window width (default 800)
+    --height <pixels>        Initial window height (default 600)
+    --x11-dialog             Open as an X11 dialog, which tiling window managers
+                             like i3 float by default
+    --validation-extra       Also run the best practices and synchronization
+                             checks of the validation layer
+    --strict-validation      Abort on the first validation error
+    --benchmark <frames>     Render this many frames with vsync off, then exit
+                             and print frame time statistics
+    --frames <count>         Exit after presenting this many frames, with the
+                             usual settings and cleanup
+    --instances <count>      Draw this many triangles in a grid with instancing
+    --demo <name>            What to draw: triangle (default), quad or cube
+    --grid                   Draw a grid on the floor under the model
+    --alpha-blending         Start with alpha blending on, toggled with F4
+    --skybox <dir>           Draw a skybox from the cubemap faces posx, negx,
+                             posy, negy, posz and negz in <dir>, with Y up.
+                             Each is a .png, or a .dds in BC1, BC3 or BC7
+    --compute                Move the triangles in a wave with a compute shader
+    --deferred               Light the scene with deferred shading
+    --dynamic-rendering      Render without a render pass where the device
+                             supports VK_KHR_dynamic_rendering
+    --threads <count>        Record the scene objects on this many threads
+                             (default 1)
+    --particles <count>      Draw a fountain of this many particles, moved by
+                             a compute shader
+    --outline                Outline the quad or the cube using the stencil
+                             buffer
+    --offscreen              Render the scene into a texture, which is then
+                             drawn onto the window, with the exposure set by
+                             +/- and the gamma by [/]
+    --post <effect>          Post-process the scene with none, grayscale,
+                             vignette or gamma, cycled with F5. Implies
+                             --offscreen
+    --multiview              Render a left and a right eye in one pass with
+                             VK_KHR_multiview, shown side by side. Implies
+                             --offscreen
+    --swapchain-images <count>
+                             Ask for this many swapchain images, e.g. 2 for
+                             double or 3 for triple buffering, within what
+                             the surface allows
+    --uncapped               Present without vsync, in IMMEDIATE or else
+                             MAILBOX mode, and keep drawing out of focus, to
+                             measure the most frames per second we can do
+    --msaa <samples>         Multisample the scene with 1 (default), 2, 4 or 8
+                             samples per pixel, or the most the device
+                             supports below that, cycled with F6
+    --sample-shading         Shade the samples of --msaa separately, smoothing
+                             the inside of surfaces too, toggled with F7
+    --occlusion-culling      Skip drawing scene objects hidden behind others,
+                             found with occlusion queries
+    --vertex-pulling         Read the vertices of the quad or the cube through
+                             their buffer device address instead of a vertex
+                             buffer, where the device supports it
+    --raytracing             Trace the quad or the cube with
+                             VK_KHR_ray_tracing_pipeline instead of
+                             rasterizing it, where the device supports it.
+                             Needs the raytracing feature";
+
+/// The built-in scenes `--demo` picks from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Demo {
+    /// Triangles whose vertices are hardcoded in shader.vert.
+    Triangle,
+    /// A square with per-vertex colors, drawn from an index buffer.
+    Quad,
+    /// A spinning cube with per-vertex colors, drawn from an index buffer.
+    Cube,
+}
+
+impl Demo {
+    fn vertex_shader(self) -> &'static str {
+        match self {
+            Demo::Triangle => "shader.vert",
+            Demo::Quad | Demo::Cube => "cube.vert",
+        }
+    }
+
+    fn renderer(self) -> Box<dyn Renderer> {
+        match self {
+            Demo::Triangle => Box::new(TriangleRenderer),
+            Demo::Quad => Box::new(QuadRenderer),
+            Demo::Cube => Box::new(CubeRenderer),
+        }
+    }
+
+    /// The vertices and indices of the demo's mesh, `None` for the
+    /// triangle that lives in shader.vert.
+    fn mesh(self) -> Option<(&'static [Vertex], &'static [u32])> {
+        match self {
+            Demo::Triangle => None,
+            Demo::Quad => Some((&QUAD_VERTICES, &QUAD_INDICES)),
+            Demo::Cube => Some((&CUBE_VERTICES, &CUBE_INDICES)),
+        }
+    }
+}
+
+/// Which physical device to render on.
+#[derive(Clone)]
+pub enum GpuSelector {
+    Index(usize),
+    Name(String),
+    /// The `device_id` of its properties, usually its PCI device ID.
+    DeviceId(u32),
+}
+
+/// Settings for the app, usually parsed from the command line on top of
+/// [`Config`].
+#[derive(Clone)]
+pub struct CliArgs {
+    pub gpu: Option<GpuSelector>,
+    pub list_gpus: bool,
+    pub shader_dir: Option<PathBuf>,
+    pub clear_color: [f32; 4],
+    pub width: u32,
+    pub height: u32,
+    pub x11_dialog: bool,
+    pub validation_extra: bool,
+    pub strict_validation: bool,
+    /// Falls back to FIFO if the surface doesn't support it.
+    pub present_mode: vk::PresentModeKHR,
+    /// Falls back to the most samples below it the device supports.
+    pub msaa_samples: u32,
+    /// Whether to enable the validation layer at all.
+    pub validation: bool,
+    /// How many times to recreate the device after it was lost, before
+    /// giving up.
+    pub device_lost_retries: u32,
+    /// Number of frames to render in benchmark mode.
+    pub benchmark_frames: Option<u32>,
+    /// How many frames to present before exiting.
+    pub frames: Option<u32>,
+    /// Optional device features to enable where supported.
+    pub features: RequestedFeatures,
+    /// How many triangles to draw.
+    pub instances: u32,
+    /// Directory with the six cubemap faces of the skybox.
+    pub skybox: Option<PathBuf>,
+    pub demo: Demo,
+    pub grid: bool,
+    pub alpha_blending: bool,
+    /// Animates the triangle instances with wave.comp.
+    pub compute: bool,
+    pub deferred: bool,
+    /// Renders with `VK_KHR_dynamic_rendering` instead of a render pass.
+    pub dynamic_rendering: bool,
+    /// How many threads record the scene objects, this one included.
+    pub threads: usize,
+    /// How many particles particles.comp moves, if any.
+    pub particles: Option<u32>,
+    pub outline: bool,
+    /// Renders into a sampled image, drawn to the swapchain afterwards.
+    pub offscreen: bool,
+    /// What the offscreen image goes through on its way to the swapchain.
+    pub post: PostEffect,
+    /// Renders a view per eye into the layers of the offscreen image.
+    pub multiview: bool,
+    /// How many swapchain images to ask for, one more than the surface's
+    /// minimum if `None`.
+    pub swapchain_images: Option<u32>,
+    /// Presents as fast as possible and keeps drawing out of focus, to
+    /// measure throughput.
+    pub uncapped: bool,
+    /// Shades the samples of `msaa_samples` separately, where the device has
+    /// the `sample_rate_shading` feature.
+    pub sample_shading: bool,
+    /// Leaves out scene objects that occlusion queries found hidden.
+    pub occlusion_culling: bool,
+    /// Draws the demo's mesh with pulled.vert, which reads its vertices
+    /// through their address.
+    pub vertex_pulling: bool,
+    /// Traces the demo's mesh instead of drawing it. Only honored when built
+    /// with the `raytracing` feature.
+    pub raytracing: bool,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        CliArgs {
+            gpu: None,
+            list_gpus: false,
+            shader_dir: None,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            width: WIDTH,
+            height: HEIGHT,
+            x11_dialog: false,
+            validation_extra: false,
+            strict_validation: false,
+            present_mode: vk::PresentModeKHR::MAILBOX,
+            msaa_samples: 1,
+            validation: true,
+            device_lost_retries: 3,
+            benchmark_frames: None,
+            frames: None,
+            features: RequestedFeatures::default(),
+            instances: 1,
+            skybox: None,
+            demo: Demo::Triangle,
+            grid: false,
+            alpha_blending: false,
+            compute: false,
+            deferred: false,
+            dynamic_rendering: false,
+            threads: 1,
+            particles: None,
+            outline: false,
+            offscreen: false,
+            post: PostEffect::None,
+            multiview: false,
+            swapchain_images: None,
+            uncapped: false,
+            sample_shading: false,
+            occlusion_culling: false,
+            vertex_pulling: false,
+            raytracing: false,
+        }
+    }
+}
+
+impl CliArgs {
+    /// Parses arguments, not including the program name, as described in
+    /// [`USAGE`].
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut cli_args = CliArgs::default();
+        cli_args.parse_onto(args)?;
+        Ok(cli_args)
+    }
+
+    /// Like [`CliArgs::parse`], but only overrides the settings the
+    /// arguments mention, e.g. after [`Config::apply`].
+    pub fn parse_onto<I: Iterator<Item = String>>(&mut self, mut args: I) -> Result<(), String> {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--gpu" => {
+                    let value = args.next().ok_or("--gpu expects a device index")?;
+                    let index = value
+                        .parse()
+                        .map_err(|_| format!("Invalid GPU index: {}", value))?;
+                    self.gpu = Some(GpuSelector::Index(index));
+                }
+                "--gpu-name" => {
+                    let name = args.next().ok_or("--gpu-name expects a name substring")?;
+                    self.gpu = Some(GpuSelector::Name(name));
+                }
+                "--gpu-id" => {
+                    let value = args.next().ok_or("--gpu-id expects a device ID")?;
+                    self.gpu = Some(GpuSelector::DeviceId(Self::parse_device_id(&value)?));
+                }
+                "--list-gpus" => self.list_gpus = true,
+                "--shader-dir" => {
+                    let dir = args.next().ok_or("--shader-dir expects a directory")?;
+                    self.shader_dir = Some(PathBuf::from(dir));
+                }
+                "--clear-color" => {
+                    let color = args.next().ok_or("--clear-color expects r,g,b")?;
+                    self.clear_color = Self::parse_color(&color)?;
+                }
+                "--width" => {
+                    let width = args.next().ok_or("--width expects a size in pixels")?;
+                    self.width = Self::parse_dimension(&width)?;
+                }
+                "--height" => {
+                    let height = args.next().ok_or("--height expects a size in pixels")?;
+                    self.height = Self::parse_dimension(&height)?;
+                }
+                "--x11-dialog" => self.x11_dialog = true,
+                "--validation-extra" => self.validation_extra = true,
+                "--strict-validation" => self.strict_validation = true,
+                "--benchmark" => {
+                    let frames = args.next().ok_or("--benchmark expects a frame count")?;
+                    match frames.parse() {
+                        Ok(0) | Err(_) => {
+                            return Err(format!("Invalid benchmark frame count: {}", frames))
+                        }
+                        Ok(frames) => self.benchmark_frames = Some(frames),
+                    }
+                }
+                "--frames" => {
+                    let count = args.next().ok_or("--frames expects a count")?;
+                    match count.parse() {
+                        Ok(0) | Err(_) => return Err(format!("Invalid frame count: {}", count)),
+                        Ok(count) => self.frames = Some(count),
+                    }
+                }
+                "--instances" => {
+                    let count = args.next().ok_or("--instances expects a count")?;
+                    match count.parse() {
+                        Ok(0) | Err(_) => return Err(format!("Invalid instance count: {}", count)),
+                        Ok(count) => self.instances = count,
+                    }
+                }
+                "--grid" => self.grid = true,
+                "--alpha-blending" => self.alpha_blending = true,
+                "--compute" => self.compute = true,
+                "--deferred" => self.deferred = true,
+                "--outline" => self.outline = true,
+                "--offscreen" => self.offscreen = true,
+                "--multiview" => {
+                    self.multiview = true;
+                    // The eyes are layers of the offscreen image
+                    self.offscreen = true;
+                }
+                "--swapchain-images" => {
+                    let count = args.next().ok_or("--swapchain-images expects a count")?;
+                    match count.parse() {
+                        Ok(0) | Err(_) => {
+                            return Err(format!("Invalid swapchain image count: {}", count))
+                        }
+                        Ok(count) => self.swapchain_images = Some(count),
+                    }
+                }
+                "--dynamic-rendering" => self.dynamic_rendering = true,
+                "--msaa" => {
+                    let samples = args.next().ok_or("--msaa expects a sample count")?;
+                    match samples.parse() {
+                        Ok(samples @ (1 | 2 | 4 | 8)) => self.msaa_samples = samples,
+                        _ => return Err(format!("Invalid MSAA sample count: {}", samples)),
+                    }
+                }
+                "--uncapped" => self.uncapped = true,
+                "--sample-shading" => self.sample_shading = true,
+                "--occlusion-culling" => self.occlusion_culling = true,
+                "--vertex-pulling" => self.vertex_pulling = true,
+                "--raytracing" => self.raytracing = true,
+                "--threads" => {
+                    let count = args.next().ok_or("--threads expects a count")?;
+                    match count.parse() {
+                        Ok(0) | Err(_) => return Err(format!("Invalid thread count: {}", count)),
+                        Ok(count) => self.threads = count,
+                    }
+                }
+                "--particles" => {
+                    let count = args.next().ok_or("--particles expects a count")?;
+                    match count.parse() {
+                        Ok(0) | Err(_) => return Err(format!("Invalid particle count: {}", count)),
+                        Ok(count) => self.particles = Some(count),
+                    }
+                }
+                "--skybox" => {
+                    let dir = args.next().ok_or("--skybox expects a directory")?;
+                    self.skybox = Some(PathBuf::from(dir));
+                }
+                "--post" => {
+                    let name = args
+                        .next()
+                        .ok_or("--post expects none, grayscale, vignette or gamma")?;
+                    self.post = match name.as_str() {
+                        "none" => PostEffect::None,
+                        "grayscale" => PostEffect::Grayscale,
+                        "vignette" => PostEffect::Vignette,
+                        "gamma" => PostEffect::Gamma,
+                        _ => return Err(format!("Unknown post effect: {}", name)),
+                    };
+                    // Effects are applied on the way from the offscreen image
+                    self.offscreen = true;
+                }
+                "--demo" => {
+                    let name = args.next().ok_or("--demo expects triangle, quad or cube")?;
+                    self.demo = match name.as_str() {
+                        "triangle" => Demo::Triangle,
+                        "quad" => Demo::Quad,
+                        "cube" => Demo::Cube,
+                        _ => return Err(format!("Unknown demo: {}", name)),
+                    };
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_dimension(dimension: &str) -> Result<u32, String> {
+        match dimension.parse() {
+            Ok(0) | Err(_) => Err(format!(
+                "Invalid window size: {}, expected a positive number of pixels",
+                dimension
+            )),
+            Ok(dimension) => Ok(dimension),
+        }
+    }
+
+    /// A device ID in hex with a `0x` prefix, the way they're usually
+    /// written, or in decimal.
+    fn parse_device_id(device_id: &str) -> Result<u32, String> {
+        match device_id.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => device_id.parse(),
+        }
+        .map_err(|_| format!("Invalid GPU device ID: {}", device_id))
+    }
+
+    fn parse_color(color: &str) -> Result<[f32; 4], String> {
+        let components = color
+            .split(',')
+            .map(|component| component.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Invalid color: {}", color))?;
+        match components.as_slice() {
+            &[r, g, b] if components.iter().all(|c| (0.0..=1.0).contains(c)) => Ok([r, g, b, 1.0]),
+            _ => Err(format!(
+                "Invalid color: {}, expected r,g,b in 0.0..=1.0",
+                color
+            )),
+        }
+    }
+}
+
+#[cfg(unix)]
+unsafe fn create_surface(
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    window: &Window,
+) -> Result<vk::SurfaceKHR, vk::Result> {
+    let x11_display = window.xlib_display().unwrap();
+    let x11_window = window.xlib_window().unwrap();
+    let x11_create_info = vk::XlibSurfaceCreateInfoKHR {
+        window: x11_window as vk::Window,
+        dpy: x11_display as *mut vk::Display,
+        ..Default::default()
+    };
+    let xlib_surface_loader = XlibSurface::new(entry, instance);
+    xlib_surface_loader.create_xlib_surface(&x11_create_info, None)
+}
+
+/// Only Xlib surfaces are implemented, so elsewhere there's nothing to
+/// present to.
+#[cfg(not(unix))]
+unsafe fn create_surface(
+    _entry: &ash::Entry,
+    _instance: &ash::Instance,
+    _window: &Window,
+) -> Result<vk::SurfaceKHR, vk::Result> {
+    Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT)
+}
+
+fn version_to_string(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+fn device_name(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> String {
+    let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    vk_to_string(&device_properties.device_name)
+}
+
+fn vk_to_string(raw_array: &[c_char]) -> String {
+    let raw_string = unsafe { CStr::from_ptr(raw_array.as_ptr()) };
+    raw_string
+        .to_str()
+        .expect("Failed to convert raw string.")
+        .to_owned()
+}
+
+/// Copies SPIR-V bytes into properly aligned words. Byte buffers, like the
+/// ones from `include_bytes!` or `fs::read`, have no alignment guarantees, so
+/// they can't be handed to Vulkan as `*const u32` directly.
+fn spv_to_words(bytes: &[u8]) -> std::io::Result<Vec<u32>> {
+    ash::util::read_spv(&mut std::io::Cursor::new(bytes))
+}
+
+/// Opens the Vulkan loader library. ash looks it up at runtime rather than
+/// linking to it, so a missing loader only shows up here.
+#[cfg(not(feature = "linked"))]
+fn load_entry() -> Result<ash::Entry, String> {
+    unsafe { ash::Entry::load() }.map_err(|error| {
+        format!(
+            "Vulkan loader not found ({}), is a Vulkan driver installed?",
+            error
+        )
+    })
+}
+
+/// The Vulkan loader the binary was linked to, with the `linked` feature.
+#[cfg(feature = "linked")]
+fn load_entry() -> Result<ash::Entry, String> {
+    Ok(ash::Entry::linked())
+}
+
+/// The driver reported `VK_ERROR_DEVICE_LOST`, after a GPU hang or reset.
+/// Nothing created from the device can be used anymore.
+#[derive(Debug)]
+pub struct DeviceLost;
+
+/// Turns a lost device into [`DeviceLost`] so the caller can recover from
+/// it, and panics with `message` on any other error.
+fn device_lost_or_panic(error: vk::Result, message: &str) -> DeviceLost {
+    match error {
+        vk::Result::ERROR_DEVICE_LOST => DeviceLost,
+        error => panic!("{} {:?}", message, error),
+    }
+}
+
+fn required_extension_names() -> Vec<*const c_char> {
+    // Why is Surface not enough?
+    let mut names = vec![Surface::name().as_ptr()];
+    #[cfg(unix)]
+    names.push(XlibSurface::name().as_ptr());
+    names
+}
+
+const REQUIRED_VALIDATION_LAYERS: [&str; 1] = ["VK_LAYER_KHRONOS_validation"];
+/// Whether to attach names and labels to Vulkan objects for validation
+/// messages and GPU captures. Release builds skip the extra calls.
+const ENABLE_DEBUG_NAMES: bool = cfg!(debug_assertions);
+/// Whether to print the chosen device's memory heaps and types at startup.
+const PRINT_MEMORY_PROPERTIES: bool = cfg!(debug_assertions);
+const DEVICE_EXTENSIONS: [&str; 1] = ["VK_KHR_swapchain"];
+
+fn enabled_validation_layer_names(validation: bool) -> Vec<CString> {
+    if !validation {
+        return vec![];
+    }
+    REQUIRED_VALIDATION_LAYERS
+        .iter()
+        .map(|&layer_name| CString::new(layer_name).unwrap())
+        .collect()
+}
+
+/// Counts the errors the validation layer reported. It's a static rather
+/// than the messenger's user data, so messages from instance creation and
+/// teardown are counted too.
+static VALIDATION_ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Set with `--strict-validation`.
+static PANIC_ON_VALIDATION_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Set once Ctrl+C was pressed, see `VulkanApp::run`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "system" fn vulkan_debug_utils_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _p_user_data: *mut c_void,
+) -> vk::Bool32 {
+    let severity = match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[Verbose]",
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "[Warning]",
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "[Error]",
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "[Info]",
+        _ => "[Unknown]",
+    };
+    let types = match message_type {
+        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
+        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
+        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
+        _ => "[Unknown]",
+    };
+    let message = CStr::from_ptr((*p_callback_data).p_message);
+    println!("[Debug]{}{}{:?}", severity, types, message);
+    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+        VALIDATION_ERROR_COUNT.fetch_add(1, Ordering::SeqCst);
+        if PANIC_ON_VALIDATION_ERROR.load(Ordering::SeqCst) {
+            // We can't unwind out of a Vulkan callback, so this aborts right
+            // at the offending call, which is what a debugger wants anyway
+            panic!("Validation error: {:?}", message);
+        }
+    }
+
+    vk::FALSE
+}
+
+struct QueueFamilyIndices {
+    graphics_family: Option<u32>,
+    present_family: Option<u32>,
+    /// A family that can do transfers but not graphics. Those usually map to
+    /// the GPU's DMA engines, so uploads don't compete with rendering.
+    transfer_family: Option<u32>,
+    /// A family that can run compute shaders but not graphics, so compute
+    /// work can overlap with rendering. Falls back to the graphics family,
+    /// where compute work runs in between.
+    compute_family: Option<u32>,
+}
+
+impl QueueFamilyIndices {
+    pub fn new() -> Self {
+        Self {
+            graphics_family: None,
+            present_family: None,
+            transfer_family: None,
+            compute_family: None,
+        }
+    }
+
+    /// The graphics, present and transfer families out of `queue_families`,
+    /// given which of them can present to the surface.
+    fn pick(queue_families: &[vk::QueueFamilyProperties], present_support: &[bool]) -> Self {
+        let mut indices = Self::new();
+        let supports_graphics = |queue_family: &vk::QueueFamilyProperties| {
+            queue_family.queue_count > 0
+                && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        };
+        // One family for both where there is one, so the swapchain images
+        // don't have to be shared between queues
+        if let Some(index) = queue_families
+            .iter()
+            .zip(present_support)
+            .position(|(queue_family, &present)| supports_graphics(queue_family) && present)
+        {
+            indices.graphics_family = Some(index as u32);
+            indices.present_family = Some(index as u32);
+        }
+
+        for (index, queue_family) in queue_families.iter().enumerate() {
+            // Keep looking for a dedicated transfer family, they tend to come
+            // after the graphics one
+            if indices.is_complete() && indices.transfer_family.is_some() {
+                break;
+            }
+            if queue_family.queue_count == 0 {
+                continue;
+            }
+            if indices.graphics_family.is_none() && supports_graphics(queue_family) {
+                indices.graphics_family = Some(index as u32);
+            }
+            if indices.present_family.is_none() && present_support[index] {
+                indices.present_family = Some(index as u32);
+            }
+            if indices.transfer_family.is_none()
+                && queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                && !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            {
+                indices.transfer_family = Some(index as u32);
+            }
+        }
+        indices
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.graphics_family.is_some() && self.present_family.is_some()
+    }
+    /// The family uploads go through. Graphics queues always support
+    /// transfers, so that's what we use without a dedicated one.
+    pub fn upload_family(&self) -> Option<u32> {
+        self.transfer_family.or(self.graphics_family)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SurfaceStuff {
+    surface_loader: Surface,
+    surface: vk::SurfaceKHR,
+}
+
+/// Orbits around the origin, with Z pointing up.
+#[derive(Clone, Copy)]
+struct Camera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl Camera {
+    const ROTATE_SPEED: f32 = 0.005;
+    const ZOOM_STEP: f32 = 0.9;
+    // Stay just short of straight up or down, where look_at has no
+    // well-defined "up" and the view flips over.
+    const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+    const MIN_DISTANCE: f32 = 0.5;
+    // Keep the origin in front of the far plane
+    const MAX_DISTANCE: f32 = 8.0;
+
+    fn new() -> Self {
+        // The same spot the camera used to be fixed at, (2, 2, 2)
+        Camera {
+            yaw: std::f32::consts::FRAC_PI_4,
+            pitch: (1.0 / 3.0_f32.sqrt()).asin(),
+            distance: 12.0_f32.sqrt(),
+        }
+    }
+
+    /// Orbits by a mouse drag of `dx`/`dy` pixels.
+    fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * Self::ROTATE_SPEED;
+        self.pitch =
+            (self.pitch + dy * Self::ROTATE_SPEED).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+
+    /// Moves closer for positive `steps`, further away for negative ones.
+    fn zoom(&mut self, steps: f32) {
+        self.distance = (self.distance * Self::ZOOM_STEP.powf(steps))
+            .clamp(Self::MIN_DISTANCE, Self::MAX_DISTANCE);
+    }
+
+    fn eye(&self) -> Vec3 {
+        self.distance
+            * Vec3::new(
+                self.pitch.cos() * self.yaw.cos(),
+                self.pitch.cos() * self.yaw.sin(),
+                self.pitch.sin(),
+            )
+    }
+
+    fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye(), Vec3::ZERO, Vec3::Z)
+    }
+
+    /// The views of a left and a right eye `separation` apart, either side
+    /// of `view_matrix`. Both look the same way, like people's do.
+    fn eye_view_matrices(&self, separation: f32) -> [Mat4; 2] {
+        let view = self.view_matrix();
+        // The left eye sees everything shifted to the right
+        let shift = |x: f32| Mat4::from_translation(Vec3::new(x, 0.0, 0.0)) * view;
+        [shift(separation / 2.0), shift(-separation / 2.0)]
+    }
+}
+
+/// Averages frame times over a short window, so the numbers in the title
+/// are readable instead of flickering every frame.
+struct FpsCounter {
+    last_report: Instant,
+    frame_count: u32,
+}
+
+impl FpsCounter {
+    const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+    fn new() -> Self {
+        Self {
+            last_report: Instant::now(),
+            frame_count: 0,
+        }
+    }
+
+    /// Records a frame. Returns the average FPS and frame time in
+    /// milliseconds once per `REPORT_INTERVAL`.
+    fn tick(&mut self) -> Option<(f64, f64)> {
+        let now = Instant::now();
+        self.frame_count += 1;
+
+        let elapsed = now.duration_since(self.last_report);
+        if elapsed < Self::REPORT_INTERVAL {
+            return None;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let fps = f64::from(self.frame_count) / seconds;
+        let frame_time_ms = seconds * 1000.0 / f64::from(self.frame_count);
+        self.last_report = now;
+        self.frame_count = 0;
+        Some((fps, frame_time_ms))
+    }
+}
+
+/// A screenshot requested with F12. The copy out of the swapchain image is
+/// recorded into the next frame, and the buffer is read back once that
+/// frame's submission is done.
+struct PendingScreenshot {
+    path: PathBuf,
+    buffer: Buffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+/// The one light of the scene, as shader.frag reads it. Laid out to match
+/// std140, where `ambient` fills the gap after `color`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// A point light when `w` is 1. When it's 0, `xyz` is the direction
+    /// towards a light infinitely far away.
+    pub position: [f32; 4],
+    pub color: [f32; 3],
+    /// How much of the color lights everything regardless of where it faces.
+    pub ambient: f32,
+}
+
+impl Default for Light {
+    /// White, above the scene and a little off to the side.
+    fn default() -> Self {
+        Light {
+            position: [2.0, -2.0, 3.0, 1.0],
+            color: [1.0; 3],
+            ambient: 0.2,
+        }
+    }
+}
+
+/// Scene-wide uniforms, matching the uniform block in shader.vert and
+/// shader.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UniformBufferObject {
+    model: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    proj: [[f32; 4]; 4],
+    /// `view` for each `--multiview` eye, left first. The vertex shaders
+    /// only use them in their multiview variants.
+    eye_views: [[[f32; 4]; 4]; 2],
+    /// The camera position, `w` is unused.
+    eye: [f32; 4],
+    light: Light,
+}
+
+/// A uniform buffer that stays mapped for its whole lifetime.
+struct UniformBuffer {
+    buffer: Buffer,
+    mapped: *mut UniformBufferObject,
+}
+
+/// How much bigger the `--outline` is than the model.
+const OUTLINE_SCALE: f32 = 1.06;
+
+/// Where the address of the vertices goes in the push constants with
+/// `--vertex-pulling`, after the model matrix and the `--outline` scale,
+/// aligned for its 64 bits.
+pub(crate) const VERTEX_ADDRESS_OFFSET: u32 = 72;
+
+/// Per-object data pushed straight into the command buffer, matching the
+/// `push_constant` block in shader.vert.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PushConstants {
+    pub(crate) model: [[f32; 4]; 4],
+}
+
+impl PushConstants {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// The pipelines a frame is recorded with.
+#[derive(Clone, Copy, PartialEq)]
+struct FramePipelines {
+    /// For the `Renderer`, solid or wireframe.
+    scene: vk::Pipeline,
+    grid: Option<vk::Pipeline>,
+    skybox: Option<vk::Pipeline>,
+    lighting: Option<vk::Pipeline>,
+    /// For the objects of `scene`, if there are any.
+    objects: Option<ObjectPipelines>,
+    particles: Option<vk::Pipeline>,
+    outline: Option<vk::Pipeline>,
+    present: Option<vk::Pipeline>,
+    /// For `--occlusion-culling`, if there are scene objects.
+    occlusion_box: Option<vk::Pipeline>,
+}
+
+/// What the scene's secondary command buffers are recorded with, besides
+/// what invalidates them when it changes.
+#[derive(Clone, Copy, PartialEq)]
+struct SceneRecordingKey {
+    pipelines: FramePipelines,
+    /// Of the viewport, as width and height.
+    extent: (u32, u32),
+}
+
+/// Per-instance vertex attributes, matching the inputs of shader.vert.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct InstanceData {
+    offset: [f32; 3],
+    /// Multiplied with the vertex colors.
+    color: [f32; 3],
+}
+
+impl InstanceData {
+    fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }]
+    }
+
+    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: std::mem::size_of::<[f32; 3]>() as u32,
+            },
+        ]
+    }
+
+    /// Lays out `count` instances in a square grid around the origin, each
+    /// tinted with its own hue. A single instance is left untinted.
+    fn grid(count: u32) -> Vec<InstanceData> {
+        // Triangles are 1 unit across, leave a little gap between them
+        const SPACING: f32 = 1.2;
+        let columns = (count as f32).sqrt().ceil() as u32;
+        let rows = count.div_ceil(columns);
+        (0..count)
+            .map(|index| {
+                let column = index % columns;
+                let row = index / columns;
+                let offset = [
+                    (column as f32 - (columns - 1) as f32 / 2.0) * SPACING,
+                    (row as f32 - (rows - 1) as f32 / 2.0) * SPACING,
+                    0.0,
+                ];
+                let color = if count == 1 {
+                    [1.0; 3]
+                } else {
+                    hue_to_rgb(index as f32 / count as f32)
+                };
+                InstanceData { offset, color }
+            })
+            .collect()
+    }
+}
+
+/// A fully saturated color for `hue` in 0.0..1.0, lightened so the vertex
+/// colors still show through.
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let channel = |shift: f32| {
+        let value = (((hue * 6.0 + shift) % 6.0) - 3.0).abs() - 1.0;
+        0.5 + 0.5 * value.clamp(0.0, 1.0)
+    };
+    [channel(0.0), channel(4.0), channel(2.0)]
+}
+
+/// Watches a directory for changes to compiled `.spv` shaders.
+#[cfg(feature = "hot-reload")]
+struct ShaderWatcher {
+    // Stops watching when dropped
+    _watcher: notify::RecommendedWatcher,
+    events: std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+}
+
+#[cfg(feature = "hot-reload")]
+impl ShaderWatcher {
+    fn new(shader_dir: &Path) -> Self {
+        use notify::Watcher;
+
+        let (sender, events) = std::sync::mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).expect("Failed to create shader watcher");
+        watcher
+            .watch(shader_dir, notify::RecursiveMode::NonRecursive)
+            .unwrap_or_else(|error| panic!("Failed to watch {:?}: {}", shader_dir, error));
+        println!("Watching {} for shader changes", shader_dir.display());
+        Self {
+            _watcher: watcher,
+            events,
+        }
+    }
+
+    /// Drains pending events and reports whether any `.spv` file changed.
+    fn has_changes(&self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(_) => continue,
+            };
+            if !(event.kind.is_create() || event.kind.is_modify()) {
+                continue;
+            }
+            changed |= event
+                .paths
+                .iter()
+                .any(|path| path.extension().is_some_and(|extension| extension == "spv"));
+        }
+        changed
+    }
+}
+
+struct SyncObjects {
+    image_available_semaphores: Vec<vk::Semaphore>,
+    render_finished_semaphores: Vec<vk::Semaphore>,
+}
+
+/// Device extensions we enable only if the device has them.
+struct OptionalExtensions {
+    memory_budget: bool,
+    descriptor_indexing: bool,
+    timeline_semaphore: bool,
+    dynamic_rendering: bool,
+    /// Core in Vulkan 1.1, so only its feature is enabled.
+    multiview: bool,
+    buffer_device_address: bool,
+    /// Along with what it builds on, except buffer device addresses,
+    /// which have a flag of their own.
+    ray_tracing: bool,
+}
+
+/// One memory heap's entry in [`VulkanApp::memory_usage`], in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapUsage {
+    /// Whether this is GPU memory, rather than system memory.
+    pub device_local: bool,
+    pub used: vk::DeviceSize,
+    pub budget: vk::DeviceSize,
+}
+
+/// Owns every Vulkan object needed to draw into a window.
+pub struct VulkanApp {
+    /// What the app was set up with, to set the device up again if it's lost.
+    cli_args: CliArgs,
+    physical_device: vk::PhysicalDevice,
+    /// Whether `VK_EXT_memory_budget` is enabled, for `memory_usage`.
+    memory_budget: bool,
+    /// How many textures `add_texture` binds as separate images with
+    /// `VK_EXT_descriptor_indexing`. `None` if the device can't, see
+    /// `TextureArray`.
+    bindless_capacity: Option<u32>,
+    graphics_family: u32,
+    graphics_queue: vk::Queue,
+    present_queue: vk::Queue,
+    /// Same as `graphics_family` if the device has no dedicated transfer
+    /// family.
+    transfer_family: u32,
+    transfer_queue: vk::Queue,
+    swapchain: Swapchain,
+    /// Window size in pixels, for surfaces that leave the extent up to us.
+    window_extent: vk::Extent2D,
+    /// Present mode we ask for, the swapchain falls back to FIFO without it.
+    preferred_present_mode: vk::PresentModeKHR,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<ShaderWatcher>,
+    pipeline_cache: vk::PipelineCache,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    /// `None` with `dynamic_rendering`.
+    render_pass: Option<vk::RenderPass>,
+    /// Only with `--dynamic-rendering`, on devices that support it.
+    dynamic_rendering: Option<DynamicRendering>,
+    /// Only with `--vertex-pulling`, on devices that support it.
+    device_addresses: Option<DeviceAddresses>,
+    pipelines: PipelineManager,
+    /// What the last frame was drawn with, and the `PipelineManager`
+    /// generation they belong to. A frame whose pipelines can't be created,
+    /// e.g. after toggling wireframe with a broken shader on disk, is drawn
+    /// with these instead.
+    last_pipelines: Option<(u64, FramePipelines)>,
+    /// The last error `frame_pipelines` failed with, so it's only printed
+    /// once.
+    pipelines_error: Option<String>,
+    /// Whether the device supports `fill_mode_non_solid`.
+    wireframe_supported: bool,
+    /// Whether the device supports `sample_rate_shading`.
+    sample_shading_supported: bool,
+    wireframe: bool,
+    /// Whether the scene pipeline blends, see `PipelineKey`. There's no
+    /// sorting, so transparent geometry has to be drawn back to front.
+    alpha_blending: bool,
+    /// `--outline`, unless there's no mesh or stencil buffer for it.
+    outline: bool,
+    /// Only loaded with `--skybox`.
+    skybox: Option<Skybox>,
+    /// Created by the first `add_texture`.
+    textures: Option<TextureArray>,
+    /// Only with `--deferred`.
+    deferred: Option<Deferred>,
+    /// Only with `--offscreen`.
+    offscreen: Option<Offscreen>,
+    /// The debug window, `None` if its shaders are broken.
+    #[cfg(feature = "gui")]
+    egui: Option<EguiOverlay>,
+    /// The debug window, `None` if its shaders are broken.
+    #[cfg(feature = "imgui")]
+    imgui: Option<ImguiOverlay>,
+    /// Only with `--raytracing`, on devices that support it. Draws the
+    /// frame instead of the main pass.
+    #[cfg(feature = "raytracing")]
+    ray_tracer: Option<RayTracer>,
+    /// `--multiview`, on devices that support it.
+    multiview: bool,
+    post_effect: PostEffect,
+    /// Adjusted with +/- and [/], with `--offscreen`.
+    tone_mapping: ToneMapping,
+    light: Light,
+    clear_color: [f32; 4],
+    command_pool: vk::CommandPool,
+    /// For short-lived command buffers submitted to `transfer_queue`.
+    transfer_command_pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    /// The scene, executed by `command_buffers`. Their lanes are the
+    /// renderer, `--threads` lanes of scene objects, and the unlit draws.
+    scene_commands: SecondaryCommands<SceneRecordingKey>,
+    // One uniform buffer per frame in flight, so we never write to one the
+    // GPU is still reading.
+    uniform_buffers: Vec<UniformBuffer>,
+    objects: Objects,
+    instance_buffer: Buffer,
+    instance_count: u32,
+    /// Only `--demo quad` and `--demo cube` have one.
+    mesh: Option<Mesh>,
+    /// What `add_object` added.
+    scene: Scene,
+    /// What's destroyed while frames may still be using it.
+    deletion_queue: DeletionQueue,
+    /// Only with `--compute` on the triangle demo.
+    compute_wave: Option<ComputeWave>,
+    /// Only if `compute_wave` runs on a queue of its own, otherwise it goes
+    /// on the frame's command buffer.
+    async_compute: Option<AsyncCompute>,
+    /// Only with `--particles`.
+    particles: Option<Particles>,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    image_available_semaphores: Vec<vk::Semaphore>,
+    /// Binary, since that's all present can wait on.
+    render_finished_semaphores: Vec<vk::Semaphore>,
+    frame_sync: FrameSync,
+    /// The submission of the frame that last rendered to each swapchain
+    /// image. There can be more images than frames in flight, and they
+    /// don't come back from the swapchain in order.
+    images_in_flight: Vec<Option<Submission>>,
+    current_frame: usize,
+    framebuffer_resized: bool,
+    screenshot_request: Option<PathBuf>,
+    renderer: Box<dyn Renderer>,
+    /// Called once per event loop iteration with the time since the last
+    /// call, before the redraw is requested.
+    on_update: Box<dyn FnMut(Duration)>,
+    /// `None` if the graphics queue can't write timestamps.
+    gpu_timer: Option<GpuTimer>,
+    /// GPU time of the most recent frame we got timestamps back for.
+    gpu_time_ms: Option<f64>,
+    /// `--occlusion-culling` only.
+    occlusion: Option<OcclusionQueries>,
+    /// When the previous frame was drawn, to animate by elapsed time.
+    last_frame: Instant,
+    /// Model rotation around Z, in radians.
+    angle: f32,
+    /// Model rotation speed, in radians per second.
+    speed: f32,
+    camera: Camera,
+    // Keep these last, fields are dropped in declaration order and
+    // everything above may need the allocator or the device to clean up.
+    allocator: SharedAllocator,
+    core: VulkanCore,
+}
+
+/// The objects everything else is created from, destroyed in reverse.
+struct InstanceCore {
+    /// The Vulkan API version the instance was created for. Anything past
+    /// Vulkan 1.0 has to be checked against this before use.
+    api_version: u32,
+    /// Where limits and features past Vulkan 1.0's are read from. `None` on
+    /// a 1.0 instance without `VK_KHR_get_physical_device_properties2`.
+    properties2: Option<Properties2>,
+    instance: ash::Instance,
+    surface_loader: Surface,
+    surface: vk::SurfaceKHR,
+    /// `None` if the instance doesn't have `VK_EXT_debug_utils`, which
+    /// leaves us without validation messages and debug names.
+    debug_utils_loader: Option<DebugUtils>,
+    /// Null along with `debug_utils_loader`.
+    debug_messenger: vk::DebugUtilsMessengerEXT,
+    // Holds the loaded Vulkan library, so it has to outlive the instance
+    _entry: ash::Entry,
+}
+
+impl Drop for InstanceCore {
+    fn drop(&mut self) {
+        unsafe {
+            self.surface_loader.destroy_surface(self.surface, None);
+            if let Some(debug_utils_loader) = &self.debug_utils_loader {
+                debug_utils_loader.destroy_debug_utils_messenger(self.debug_messenger, None);
+            }
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+/// The device, and the instance it was created from. The instance is shared
+/// so it can outlive a lost device and be used to create a new one.
+struct VulkanCore {
+    device: ash::Device,
+    instance: Rc<InstanceCore>,
+}
+
+impl VulkanCore {
+    fn debug_utils_loader(&self) -> Option<&DebugUtils> {
+        if !ENABLE_DEBUG_NAMES {
+            return None;
+        }
+        self.instance.debug_utils_loader.as_ref()
+    }
+
+    /// Gives `object` a name that validation messages and tools like
+    /// RenderDoc show instead of the raw handle.
+    fn set_debug_name<T: vk::Handle>(&self, object: T, name: &str) {
+        let debug_utils_loader = match self.debug_utils_loader() {
+            Some(debug_utils_loader) => debug_utils_loader,
+            None => return,
+        };
+        let name = CString::new(name).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT {
+            object_type: T::TYPE,
+            object_handle: object.as_raw(),
+            p_object_name: name.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            // Names are only a debugging aid, not worth failing over
+            if let Err(error) =
+                debug_utils_loader.set_debug_utils_object_name(self.device.handle(), &name_info)
+            {
+                eprintln!("Failed to set debug name {:?}: {:?}", name, error);
+            }
+        }
+    }
+
+    /// Opens a labeled region in `command_buffer`, which GPU captures group
+    /// the commands by. Must be closed with `end_debug_label`.
+    fn begin_debug_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        let debug_utils_loader = match self.debug_utils_loader() {
+            Some(debug_utils_loader) => debug_utils_loader,
+            None => return,
+        };
+        let name = CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT {
+            p_label_name: name.as_ptr(),
+            color,
+            ..Default::default()
+        };
+        unsafe {
+            debug_utils_loader.cmd_begin_debug_utils_label(command_buffer, &label);
+        }
+    }
+
+    fn end_debug_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils_loader) = self.debug_utils_loader() {
+            unsafe {
+                debug_utils_loader.cmd_end_debug_utils_label(command_buffer);
+            }
+        }
+    }
+}
+
+impl Drop for VulkanCore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_device(None);
+        }
+    }
+}
+
+impl VulkanApp {
+    /// Sets up Vulkan for rendering into `window`. Fails if there's no
+    /// Vulkan loader or no device that can render into the window, and
+    /// panics on anything else going wrong.
+    pub fn new(window: &Window, cli_args: &CliArgs) -> Result<VulkanApp, String> {
+        PANIC_ON_VALIDATION_ERROR.store(cli_args.strict_validation, Ordering::SeqCst);
+        let entry = load_entry()?;
+        let debug_utils = Self::supports_debug_utils(&entry);
+        if !debug_utils {
+            eprintln!(
+                "Warning: VK_EXT_debug_utils is not available, running without validation \
+                 messages and debug names"
+            );
+        }
+        let (instance, api_version, properties2) = Self::create_instance(
+            &entry,
+            cli_args.validation,
+            cli_args.validation_extra,
+            debug_utils,
+        )?;
+        let surface_stuff = Self::create_surface(&entry, &instance, window);
+        let (debug_utils_loader, debug_messenger) = if debug_utils {
+            let (debug_utils_loader, debug_messenger) = Self::setup_debug_utils(&entry, &instance);
+            (Some(debug_utils_loader), debug_messenger)
+        } else {
+            (None, vk::DebugUtilsMessengerEXT::null())
+        };
+        let instance_core = InstanceCore {
+            api_version,
+            properties2,
+            instance,
+            surface_loader: surface_stuff.surface_loader,
+            surface: surface_stuff.surface,
+            debug_utils_loader,
+            debug_messenger,
+            _entry: entry,
+        };
+        Self::with_instance(Rc::new(instance_core), window, cli_args)
+    }
+
+    /// Sets up everything that belongs to the device, on an instance that
+    /// may have had a device before.
+    fn with_instance(
+        instance_core: Rc<InstanceCore>,
+        window: &Window,
+        cli_args: &CliArgs,
+    ) -> Result<VulkanApp, String> {
+        let instance = &instance_core.instance;
+        let surface_stuff = SurfaceStuff {
+            surface_loader: instance_core.surface_loader.clone(),
+            surface: instance_core.surface,
+        };
+        let (physical_device, indices) = Self::pick_physical_device(
+            instance,
+            &surface_stuff,
+            cli_args.gpu.as_ref(),
+            cli_args.features,
+        )?;
+        if PRINT_MEMORY_PROPERTIES {
+            Self::print_memory_properties(instance, physical_device);
+        }
+        // Embedded shaders can't change, so hot reload watches the directory
+        // build.rs compiles them into unless told otherwise.
+        #[cfg(feature = "hot-reload")]
+        let shader_dir = Some(
+            cli_args
+                .shader_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(env!("OUT_DIR"))),
+        );
+        #[cfg(not(feature = "hot-reload"))]
+        let shader_dir = cli_args.shader_dir.clone();
+        let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let (enabled_features, denied_features) = cli_args.features.resolve(&supported_features);
+        if !denied_features.is_empty() {
+            println!(
+                "Device doesn't support {}, doing without",
+                denied_features.names().join(", ")
+            );
+        }
+        let memory_budget = Self::supports_memory_budget(&instance_core, physical_device);
+        let bindless_capacity = Self::bindless_capacity(&instance_core, physical_device);
+        let timeline_semaphore =
+            Self::supports_timeline_semaphores(&instance_core, physical_device);
+        // Deferred shading reads the G-buffer in a second subpass, which
+        // only render passes have
+        let use_dynamic_rendering = cli_args.dynamic_rendering
+            && if cli_args.deferred {
+                println!("Deferred shading needs subpasses, rendering with a render pass");
+                false
+            } else if cli_args.offscreen {
+                println!("Offscreen rendering needs a render pass, using one");
+                false
+            } else if !Self::supports_dynamic_rendering(&instance_core, physical_device) {
+                println!("The device doesn't support dynamic rendering, using a render pass");
+                false
+            } else {
+                true
+            };
+        // The lighting subpass would have to read the G-buffer of each eye
+        let multiview = cli_args.multiview
+            && if cli_args.deferred {
+                println!("Deferred shading doesn't support --multiview, ignoring it");
+                false
+            } else if !Self::supports_multiview(&instance_core, physical_device) {
+                println!("The device doesn't support multiview, ignoring --multiview");
+                false
+            } else {
+                true
+            };
+        let view_count = if multiview { EYE_COUNT } else { 1 };
+        // The outline is drawn by the renderer again, with the vertex
+        // buffer it doesn't bind then
+        let vertex_pulling = cli_args.vertex_pulling
+            && if cli_args.demo == Demo::Triangle {
+                println!("--vertex-pulling only draws the quad or the cube, ignoring it");
+                false
+            } else if cli_args.outline {
+                println!("--outline needs a vertex buffer, ignoring --vertex-pulling");
+                false
+            } else if !Self::supports_buffer_device_address(&instance_core, physical_device) {
+                println!(
+                    "The device doesn't support buffer device addresses, ignoring \
+                     --vertex-pulling"
+                );
+                false
+            } else {
+                true
+            };
+        // The traced image takes the place of the main pass, which with
+        // --multiview renders two of them
+        let ray_tracing = cli_args.raytracing
+            && if !cfg!(feature = "raytracing") {
+                println!("Built without the raytracing feature, ignoring --raytracing");
+                false
+            } else if cli_args.demo == Demo::Triangle {
+                println!("--raytracing only traces the quad or the cube, ignoring it");
+                false
+            } else if multiview {
+                println!("--multiview renders two views, ignoring --raytracing");
+                false
+            } else if !Self::supports_ray_tracing(&instance_core, physical_device) {
+                println!("The device doesn't support ray tracing, ignoring --raytracing");
+                false
+            } else {
+                true
+            };
+        let device_features = enabled_features.to_vk();
+        let (device, graphics_queue, present_queue, transfer_queue, compute_queue) =
+            Self::create_logical_device(
+                instance,
+                physical_device,
+                &indices,
+                &device_features,
+                cli_args.validation,
+                &OptionalExtensions {
+                    memory_budget,
+                    descriptor_indexing: bindless_capacity.is_some(),
+                    timeline_semaphore,
+                    dynamic_rendering: use_dynamic_rendering,
+                    multiview,
+                    buffer_device_address: vertex_pulling || ray_tracing,
+                    ray_tracing,
+                },
+            );
+        // Benchmarks measure how fast we can go, not the refresh rate
+        let preferred_present_mode = if cli_args.benchmark_frames.is_some() || cli_args.uncapped {
+            vk::PresentModeKHR::IMMEDIATE
+        } else {
+            cli_args.present_mode
+        };
+        // The lighting subpass would have to read every sample of the
+        // G-buffer, and dynamic rendering would need resolve attachments of
+        // its own
+        let msaa_samples = if cli_args.msaa_samples == 1 {
+            vk::SampleCountFlags::TYPE_1
+        } else if cli_args.deferred {
+            println!("Deferred shading doesn't support --msaa, ignoring it");
+            vk::SampleCountFlags::TYPE_1
+        } else if use_dynamic_rendering {
+            println!("Dynamic rendering doesn't support --msaa, ignoring it");
+            vk::SampleCountFlags::TYPE_1
+        } else {
+            let supported = Self::supported_sample_counts(instance, physical_device);
+            let samples = choose_sample_count(cli_args.msaa_samples, supported);
+            if samples.as_raw() != cli_args.msaa_samples {
+                println!(
+                    "The device doesn't support {}x MSAA, using {}x",
+                    cli_args.msaa_samples,
+                    samples.as_raw()
+                );
+            }
+            samples
+        };
+        let window_extent = window_extent(window);
+        let gbuffer_formats: &[vk::Format] = if cli_args.deferred {
+            &GBUFFER_FORMATS
+        } else {
+            &[]
+        };
+        let allocator = allocator::create_allocator(
+            instance,
+            physical_device,
+            &device,
+            vertex_pulling || ray_tracing,
+        );
+        let mut swapchain = Swapchain::new(
+            instance,
+            &device,
+            &allocator,
+            physical_device,
+            surface_stuff.clone(),
+            &indices,
+            preferred_present_mode,
+            window_extent,
+            gbuffer_formats,
+            cli_args.offscreen.then_some(OFFSCREEN_FORMAT),
+            view_count,
+            cli_args.swapchain_images,
+            msaa_samples,
+        );
+        let mut outline = cli_args.outline;
+        if outline && cli_args.demo == Demo::Triangle {
+            println!("--outline only goes around the quad or the cube, ignoring it");
+            outline = false;
+        } else if outline && swapchain.stencil_format() == vk::Format::UNDEFINED {
+            println!("The depth format has no stencil, ignoring --outline");
+            outline = false;
+        }
+        let dynamic_rendering = use_dynamic_rendering.then(|| {
+            DynamicRendering::new(&device, DynamicRenderingLoader::new(instance, &device))
+        });
+        let device_addresses = vertex_pulling
+            .then(|| DeviceAddresses::new(BufferDeviceAddress::new(instance, &device)));
+        let (render_pass, render_target) = if dynamic_rendering.is_some() {
+            let render_target = RenderTarget::Dynamic {
+                color_format: swapchain.format,
+                depth_format: swapchain.depth_format,
+                stencil_format: swapchain.stencil_format(),
+            };
+            (None, render_target)
+        } else {
+            let render_pass = Self::create_render_pass(
+                &device,
+                swapchain.format,
+                swapchain.depth_format,
+                gbuffer_formats,
+                cli_args.offscreen,
+                view_count,
+                msaa_samples,
+            );
+            swapchain.create_framebuffers(render_pass);
+            (Some(render_pass), RenderTarget::RenderPass(render_pass))
+        };
+        let pipeline_cache = Self::create_pipeline_cache(instance, physical_device, &device);
+        let descriptor_set_layout = Self::create_descriptor_set_layout(&device);
+        let pipeline_layout =
+            Self::create_pipeline_layout(instance, physical_device, &device, descriptor_set_layout);
+        #[cfg(feature = "hot-reload")]
+        let shader_watcher = shader_dir.as_deref().map(ShaderWatcher::new);
+        let mut pipelines = PipelineManager::new(
+            &device,
+            render_target,
+            pipeline_cache,
+            shader_dir,
+            BRIGHTNESS_CYCLE[0],
+            cli_args.deferred,
+            enabled_features.wide_lines,
+            multiview,
+            msaa_samples,
+        );
+        let offscreen = cli_args
+            .offscreen
+            .then(|| Offscreen::new(&device, &swapchain));
+        if let Some(offscreen) = &offscreen {
+            pipelines.set_present_render_pass(offscreen.render_pass);
+        }
+        if cli_args.sample_shading {
+            if !enabled_features.sample_rate_shading {
+                println!("The device doesn't support sample shading, ignoring --sample-shading");
+            } else {
+                if msaa_samples == vk::SampleCountFlags::TYPE_1 {
+                    println!("--sample-shading only makes a difference with --msaa");
+                }
+                pipelines.set_sample_shading(true)?;
+            }
+        }
+
+        let command_pool = Self::create_command_pool(&device, &indices);
+        let transfer_command_pool = Self::create_transfer_command_pool(&device, &indices);
+        let command_buffers = Self::create_command_buffers(&device, command_pool);
+        // The renderer and the unlit draws get a lane each, around the
+        // objects' lanes
+        let scene_commands = SecondaryCommands::new(
+            &device,
+            indices.graphics_family.unwrap(),
+            MAX_FRAMES_IN_FLIGHT,
+            cli_args.threads + 2,
+        );
+        let sync_objects = Self::create_sync_objects(&device);
+        let timeline = timeline_semaphore.then(|| TimelineSemaphore::new(instance, &device));
+        let frame_sync = FrameSync::new(&device, timeline, MAX_FRAMES_IN_FLIGHT);
+        if !frame_sync.is_timeline() {
+            println!("The device doesn't support timeline semaphores, waiting on fences instead");
+        }
+        let gpu_timer = GpuTimer::new(
+            instance,
+            physical_device,
+            &device,
+            indices.graphics_family.unwrap(),
+            MAX_FRAMES_IN_FLIGHT,
+        );
+        if gpu_timer.is_none() {
+            println!("The graphics queue doesn't support timestamps, GPU time is unavailable");
+        }
+        // Queries in a multiview render pass take one per view, and the
+        // boxes would have to go in the lighting subpass, after the G-buffer
+        let occlusion = cli_args.occlusion_culling
+            && if cli_args.deferred {
+                println!("Deferred shading doesn't support --occlusion-culling, ignoring it");
+                false
+            } else if multiview {
+                println!("--multiview doesn't support --occlusion-culling, ignoring it");
+                false
+            } else {
+                true
+            };
+        let occlusion = occlusion.then(|| OcclusionQueries::new(&device, MAX_FRAMES_IN_FLIGHT));
+        let uniform_buffers = Self::create_uniform_buffers(&device, &allocator);
+        let instances = InstanceData::grid(cli_args.instances);
+        let instance_buffer = Self::create_instance_buffer(&device, &allocator, &instances);
+        let min_uniform_buffer_offset_alignment =
+            unsafe { instance.get_physical_device_properties(physical_device) }
+                .limits
+                .min_uniform_buffer_offset_alignment;
+        let objects = Objects::new(&device, &allocator, min_uniform_buffer_offset_alignment);
+        let descriptor_pool = Self::create_descriptor_pool(&device);
+        let descriptor_sets = Self::create_descriptor_sets(
+            &device,
+            descriptor_pool,
+            descriptor_set_layout,
+            &uniform_buffers,
+            &objects,
+        );
+
+        let deferred = cli_args
+            .deferred
+            .then(|| Deferred::new(&device, &swapchain.gbuffer_views()));
+
+        let mut app = VulkanApp {
+            cli_args: cli_args.clone(),
+            physical_device,
+            memory_budget,
+            bindless_capacity,
+
+            graphics_family: indices.graphics_family.unwrap(),
+            graphics_queue,
+            present_queue,
+            transfer_family: indices.upload_family().unwrap(),
+            transfer_queue,
+
+            images_in_flight: vec![None; swapchain.image_count()],
+            swapchain,
+            window_extent,
+            preferred_present_mode,
+
+            #[cfg(feature = "hot-reload")]
+            shader_watcher,
+            pipeline_cache,
+            descriptor_set_layout,
+            pipeline_layout,
+            render_pass,
+            dynamic_rendering,
+            device_addresses,
+            pipelines,
+            last_pipelines: None,
+            pipelines_error: None,
+            wireframe_supported: enabled_features.fill_mode_non_solid,
+            sample_shading_supported: enabled_features.sample_rate_shading,
+            wireframe: false,
+            alpha_blending: cli_args.alpha_blending,
+            outline,
+            skybox: None,
+            textures: None,
+            deferred,
+            offscreen,
+            #[cfg(feature = "gui")]
+            egui: None,
+            #[cfg(feature = "imgui")]
+            imgui: None,
+            #[cfg(feature = "raytracing")]
+            ray_tracer: None,
+            multiview,
+            post_effect: cli_args.post,
+            tone_mapping: ToneMapping::default(),
+            light: Light::default(),
+            clear_color: cli_args.clear_color,
+
+            command_pool,
+            transfer_command_pool,
+            command_buffers,
+            scene_commands,
+
+            uniform_buffers,
+            objects,
+            instance_buffer,
+            instance_count: instances.len() as u32,
+            mesh: None,
+            scene: Scene::new(),
+            deletion_queue: DeletionQueue::new(),
+            compute_wave: None,
+            async_compute: None,
+            particles: None,
+            descriptor_pool,
+            descriptor_sets,
+
+            image_available_semaphores: sync_objects.image_available_semaphores,
+            render_finished_semaphores: sync_objects.render_finished_semaphores,
+            frame_sync,
+            current_frame: 0,
+            framebuffer_resized: false,
+            screenshot_request: None,
+            renderer: cli_args.demo.renderer(),
+            on_update: Box::new(|_| {}),
+            gpu_timer,
+            gpu_time_ms: None,
+            occlusion,
+            last_frame: Instant::now(),
+            angle: 0.0,
+            // A quarter turn per second
+            speed: std::f32::consts::FRAC_PI_2,
+            camera: Camera::new(),
+
+            allocator,
+            core: VulkanCore {
+                device,
+                instance: instance_core,
+            },
+        };
+        if let Some((vertices, indices)) = cli_args.demo.mesh() {
+            app.mesh = Some(app.create_mesh(vertices, indices));
+            #[cfg(feature = "raytracing")]
+            if ray_tracing {
+                app.ray_tracer = app.create_ray_tracer(vertices, indices);
+            }
+        }
+        if cli_args.compute {
+            if cli_args.demo != Demo::Triangle {
+                println!("--compute only moves the triangles, ignoring it");
+            } else if let Some(compute_family) = indices.compute_family {
+                if compute_family != app.graphics_family {
+                    app.async_compute = Some(AsyncCompute::new(
+                        &app.core.device,
+                        compute_family,
+                        compute_queue.unwrap(),
+                    ));
+                }
+                let mut queue_families = vec![app.graphics_family];
+                queue_families.extend(app.async_compute.as_ref().map(|queue| queue.family));
+                app.compute_wave = Some(ComputeWave::new(
+                    &app.core.device,
+                    &app.allocator,
+                    app.pipeline_cache,
+                    app.pipelines.shader_dir(),
+                    &instances,
+                    &queue_families,
+                ));
+            } else {
+                println!("The device can't run compute shaders, ignoring --compute");
+            }
+        }
+        if let Some(count) = cli_args.particles {
+            let queue_families = unsafe {
+                app.core
+                    .instance
+                    .instance
+                    .get_physical_device_queue_family_properties(app.physical_device)
+            };
+            if queue_families[app.graphics_family as usize]
+                .queue_flags
+                .contains(vk::QueueFlags::COMPUTE)
+            {
+                let buffer = app.create_device_local_buffer(
+                    "particles",
+                    &Particle::fountain(count),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+                );
+                app.particles = Some(Particles::new(
+                    &app.core.device,
+                    app.pipeline_cache,
+                    app.pipelines.shader_dir(),
+                    buffer,
+                    count,
+                ));
+            } else {
+                println!("The graphics queue can't run compute shaders, ignoring --particles");
+            }
+        }
+        if let Some(skybox_dir) = &cli_args.skybox {
+            let mut faces = CubemapFaces::load(skybox_dir)?;
+            let format_properties = unsafe {
+                app.core
+                    .instance
+                    .instance
+                    .get_physical_device_format_properties(app.physical_device, faces.format)
+            };
+            if !format_properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+            {
+                println!(
+                    "The device can't sample {:?}, decoding the skybox to RGBA8",
+                    faces.format
+                );
+                faces.decompress()?;
+            }
+            app.skybox = Some(Skybox::new(
+                &app.core.device,
+                app.create_cubemap(&faces),
+                app.descriptor_set_layout,
+            ));
+        }
+        #[cfg(feature = "gui")]
+        {
+            app.egui = app.create_egui_overlay(window.hidpi_factor());
+        }
+        #[cfg(feature = "imgui")]
+        {
+            app.imgui = app.create_imgui_overlay(window.hidpi_factor());
+        }
+        // Creates the pipelines of the first frame, to fail right away if
+        // the shaders are broken
+        let pipelines = app.frame_pipelines()?;
+        app.last_pipelines = Some((app.pipelines.generation(), pipelines));
+        app.name_objects();
+        app.name_swapchain_objects();
+        Ok(app)
+    }
+
+    /// Names the objects that live as long as the app. The swapchain ones
+    /// are named by `name_swapchain_objects`, as they get recreated.
+    fn name_objects(&self) {
+        let core = &self.core;
+        core.set_debug_name(core.device.handle(), "device");
+        core.set_debug_name(self.graphics_queue, "graphics_queue");
+        core.set_debug_name(self.present_queue, "present_queue");
+        core.set_debug_name(self.transfer_queue, "transfer_queue");
+        if let Some(async_compute) = &self.async_compute {
+            core.set_debug_name(async_compute.queue, "compute_queue");
+        }
+        if let Some(render_pass) = self.render_pass {
+            core.set_debug_name(render_pass, "render_pass");
+        }
+        if let Some(offscreen) = &self.offscreen {
+            core.set_debug_name(offscreen.render_pass, "present_render_pass");
+        }
+        if let Some(compute_wave) = &self.compute_wave {
+            core.set_debug_name(compute_wave.pipeline.pipeline, "compute_wave_pipeline");
+        }
+        if let Some(particles) = &self.particles {
+            core.set_debug_name(particles.pipeline.pipeline, "particles_pipeline");
+        }
+        self.name_pipelines();
+    }
+
+    /// Names every pipeline created so far after its key.
+    fn name_pipelines(&self) {
+        for (key, pipeline) in self.pipelines.iter() {
+            self.core.set_debug_name(pipeline, &format!("{:?}", key));
+        }
+    }
+
+    /// The pipeline for `key`, created and named on first use.
+    fn pipeline(
+        &mut self,
+        key: PipelineKey,
+        layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, String> {
+        let is_new = !self.pipelines.contains(&key);
+        let pipeline = self.pipelines.get(key, layout)?;
+        if is_new {
+            self.core.set_debug_name(pipeline, &format!("{:?}", key));
+        }
+        Ok(pipeline)
+    }
+
+    fn scene_pipeline_key(&self) -> PipelineKey {
+        PipelineKey {
+            material: match self.device_addresses {
+                Some(_) => Material::Pulled,
+                None => Material::Scene(self.cli_args.demo),
+            },
+            topology: Topology::Triangles,
+            // Marks where the outline doesn't go
+            stencil: if self.outline {
+                Stencil::Write
+            } else {
+                Stencil::Off
+            },
+            wireframe: self.wireframe,
+            alpha_blending: self.alpha_blending,
+        }
+    }
+
+    /// The pipelines the next frame draws with, or if they can't be
+    /// created, the last frame's. `None` if those were destroyed since, by
+    /// a rebuild. Failures are printed, once for each error.
+    fn usable_frame_pipelines(&mut self) -> Option<FramePipelines> {
+        match self.frame_pipelines() {
+            Ok(pipelines) => {
+                self.last_pipelines = Some((self.pipelines.generation(), pipelines));
+                self.pipelines_error = None;
+                Some(pipelines)
+            }
+            Err(error) => {
+                let generation = self.pipelines.generation();
+                let last_pipelines = self
+                    .last_pipelines
+                    .filter(|&(last_generation, _)| last_generation == generation)
+                    .map(|(_, pipelines)| pipelines);
+                if self.pipelines_error.as_ref() != Some(&error) {
+                    if last_pipelines.is_some() {
+                        eprintln!("{}, keeping the last frame's pipelines", error);
+                    } else {
+                        eprintln!("{}, skipping frames until that's fixed", error);
+                    }
+                    self.pipelines_error = Some(error);
+                }
+                last_pipelines
+            }
+        }
+    }
+
+    /// Looks up the pipelines the next frame draws with.
+    fn frame_pipelines(&mut self) -> Result<FramePipelines, String> {
+        let scene = self.pipeline(self.scene_pipeline_key(), self.pipeline_layout)?;
+        let grid = if self.cli_args.grid {
+            Some(self.pipeline(PipelineKey::new(Material::Grid), self.pipeline_layout)?)
+        } else {
+            None
+        };
+        let skybox = match self.skybox.as_ref().map(|skybox| skybox.pipeline_layout) {
+            Some(layout) => Some(self.pipeline(PipelineKey::new(Material::Skybox), layout)?),
+            None => None,
+        };
+        let lighting = match self
+            .deferred
+            .as_ref()
+            .map(|deferred| deferred.pipeline_layout)
+        {
+            Some(layout) => Some(self.pipeline(PipelineKey::new(Material::Lighting), layout)?),
+            None => None,
+        };
+        // Scene objects are made of Vertex, like the cube
+        let objects = if self.scene.is_empty() {
+            None
+        } else {
+            let (material, layout) = match &self.textures {
+                Some(textures) if textures.is_bindless() => {
+                    (Material::Bindless, textures.pipeline_layout)
+                }
+                Some(textures) => (Material::Textured, textures.pipeline_layout),
+                None => (Material::Scene(Demo::Cube), self.pipeline_layout),
+            };
+            let mut pipelines = ObjectPipelines::default();
+            for topology in Topology::ALL {
+                for stencil in Stencil::ALL {
+                    if self.scene.uses(topology, stencil) {
+                        let key = PipelineKey {
+                            material,
+                            topology,
+                            stencil,
+                            ..self.scene_pipeline_key()
+                        };
+                        pipelines.set(topology, stencil, self.pipeline(key, layout)?);
+                    }
+                }
+            }
+            Some(pipelines)
+        };
+        let particles = if self.particles.is_some() {
+            Some(self.pipeline(PipelineKey::new(Material::Particles), self.pipeline_layout)?)
+        } else {
+            None
+        };
+        let outline = if self.outline {
+            let key = PipelineKey {
+                stencil: Stencil::Outside,
+                ..PipelineKey::new(Material::Outline)
+            };
+            Some(self.pipeline(key, self.pipeline_layout)?)
+        } else {
+            None
+        };
+        let present = match self
+            .offscreen
+            .as_ref()
+            .map(|offscreen| offscreen.pipeline_layout)
+        {
+            Some(layout) => Some(self.pipeline(PipelineKey::new(Material::Present), layout)?),
+            None => None,
+        };
+        let occlusion_box = if self.occlusion.is_some() && !self.scene.is_empty() {
+            let key = PipelineKey::new(Material::OcclusionBox);
+            Some(self.pipeline(key, self.pipeline_layout)?)
+        } else {
+            None
+        };
+        Ok(FramePipelines {
+            scene,
+            grid,
+            skybox,
+            lighting,
+            objects,
+            particles,
+            outline,
+            present,
+            occlusion_box,
+        })
+    }
+
+    fn name_swapchain_objects(&self) {
+        let core = &self.core;
+        core.set_debug_name(self.swapchain.swapchain, "swapchain");
+        for (i, &framebuffer) in self.swapchain.framebuffers.iter().enumerate() {
+            core.set_debug_name(framebuffer, &format!("swapchain_framebuffer[{}]", i));
+        }
+        if let Some(offscreen) = &self.offscreen {
+            for (i, &framebuffer) in offscreen.framebuffers().iter().enumerate() {
+                core.set_debug_name(framebuffer, &format!("present_framebuffer[{}]", i));
+            }
+        }
+        #[cfg(feature = "gui")]
+        if let Some(egui) = &self.egui {
+            for (i, &framebuffer) in egui.framebuffers().iter().enumerate() {
+                core.set_debug_name(framebuffer, &format!("egui_framebuffer[{}]", i));
+            }
+        }
+        #[cfg(feature = "imgui")]
+        if let Some(imgui) = &self.imgui {
+            for (i, &framebuffer) in imgui.framebuffers().iter().enumerate() {
+                core.set_debug_name(framebuffer, &format!("imgui_framebuffer[{}]", i));
+            }
+        }
+    }
+
+    /// Picks the highest API version both we and the loader support.
+    fn choose_api_version(entry: &ash::Entry) -> u32 {
+        // Vulkan 1.0 loaders don't have vkEnumerateInstanceVersion at all
+        let available_version = match entry.try_enumerate_instance_version() {
+            Ok(Some(version)) => version,
+            Ok(None) => vk::make_api_version(0, 1, 0, 0),
+            Err(error) => panic!("Failed to enumerate instance version: {:?}", error),
+        };
+        // Patch versions don't matter for which API we get to use
+        let available_api = vk::make_api_version(
+            0,
+            vk::api_version_major(available_version),
+            vk::api_version_minor(available_version),
+            0,
+        );
+        if available_api < REQUESTED_API_VERSION {
+            println!(
+                "Warning: Vulkan {} requested, but only {} is available",
+                version_to_string(REQUESTED_API_VERSION),
+                version_to_string(available_version)
+            );
+        }
+        available_api.min(REQUESTED_API_VERSION)
+    }
+
+    /// Whether the instance can have `VK_EXT_debug_utils`. Stripped down
+    /// drivers may not ship it.
+    fn supports_debug_utils(entry: &ash::Entry) -> bool {
+        Self::missing_instance_extensions(entry, &[DebugUtils::name().as_ptr()]).is_empty()
+    }
+
+    /// Creates the instance, with `VK_EXT_debug_utils` if `debug_utils`. It's
+    /// returned along with the API version it was created for, and the
+    /// [`Properties2`] queries that read limits and features past Vulkan
+    /// 1.0's. Those are `None` on a 1.0 instance without
+    /// `VK_KHR_get_physical_device_properties2`.
+    ///
+    /// With `validation_extra`, the validation layer also runs its best
+    /// practices and synchronization checks. Those are slow and noisy, so
+    /// they're opt-in.
+    fn create_instance(
+        entry: &ash::Entry,
+        validation: bool,
+        validation_extra: bool,
+        debug_utils: bool,
+    ) -> Result<(ash::Instance, u32, Option<Properties2>), String> {
+        if validation && !Self::check_validation_layers_support(entry) {
+            panic!("Validation layers requested, but not available");
+        }
+        let api_version = Self::choose_api_version(entry);
+        println!("Using Vulkan {}", version_to_string(api_version));
+        let app_name = CString::new("Hello Triangle").unwrap();
+        let engine_name = CString::new("No Engine").unwrap();
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .application_version(vk::make_api_version(0, 1, 0, 0))
+            .engine_name(&engine_name)
+            .engine_version(vk::make_api_version(0, 1, 0, 0))
+            .api_version(api_version)
+            .build();
+
+        let debug_utils_create_info = populate_debug_messenger_create_info();
+
+        let mut extension_names = required_extension_names();
+        if debug_utils {
+            extension_names.push(DebugUtils::name().as_ptr());
+        }
+        // Core from Vulkan 1.1 on
+        let properties2_extension = api_version < vk::make_api_version(0, 1, 1, 0)
+            && Self::missing_instance_extensions(
+                entry,
+                &[vk::KhrGetPhysicalDeviceProperties2Fn::name().as_ptr()],
+            )
+            .is_empty();
+        if properties2_extension {
+            extension_names.push(vk::KhrGetPhysicalDeviceProperties2Fn::name().as_ptr());
+        }
+        let missing_extensions = Self::missing_instance_extensions(entry, &extension_names);
+        if !missing_extensions.is_empty() {
+            return Err(format!(
+                "Missing instance extensions: {}",
+                missing_extensions.join(", ")
+            ));
+        }
+        let validation_feature_enables = [
+            vk::ValidationFeatureEnableEXT::BEST_PRACTICES,
+            vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION,
+        ];
+        let mut validation_features = vk::ValidationFeaturesEXT {
+            enabled_validation_feature_count: validation_feature_enables.len() as u32,
+            p_enabled_validation_features: validation_feature_enables.as_ptr(),
+            ..Default::default()
+        };
+
+        let enabled_layer_raw_names = enabled_validation_layer_names(validation);
+
+        let enabled_layer_names: Vec<*const c_char> = enabled_layer_raw_names
+            .iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+        // Also reports on instance creation and destruction, which the
+        // messenger doesn't exist for yet or anymore
+        let mut p_next = if debug_utils {
+            &debug_utils_create_info as *const vk::DebugUtilsMessengerCreateInfoEXT as *const c_void
+        } else {
+            std::ptr::null()
+        };
+        // Goes on the instance itself, so it works without a messenger too.
+        // The layer prints to stdout then.
+        if validation && validation_extra {
+            // The extension comes with the validation layer itself
+            extension_names.push(vk::ExtValidationFeaturesFn::name().as_ptr());
+            validation_features.p_next = p_next;
+            p_next = &validation_features as *const vk::ValidationFeaturesEXT as *const c_void;
+        }
+        let create_info = vk::InstanceCreateInfo {
+            s_type: vk::StructureType::INSTANCE_CREATE_INFO,
+            p_next,
+            p_application_info: &app_info,
+            enabled_extension_count: extension_names.len() as u32,
+            pp_enabled_extension_names: extension_names.as_ptr(),
+            enabled_layer_count: enabled_layer_names.len() as u32,
+            pp_enabled_layer_names: enabled_layer_names.as_ptr(),
+            flags: vk::InstanceCreateFlags::empty(),
+        };
+
+        let instance: ash::Instance = unsafe {
+            entry
+                .create_instance(&create_info, None)
+                .expect("Failed to create instance")
+        };
+        let properties2 = Properties2::load(entry, &instance, api_version, properties2_extension);
+        Ok((instance, api_version, properties2))
+    }
+
+    fn create_surface(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        window: &Window,
+    ) -> SurfaceStuff {
+        let surface =
+            unsafe { create_surface(entry, instance, window).expect("Failed to create surface") };
+        let surface_loader = Surface::new(entry, instance);
+
+        SurfaceStuff {
+            surface_loader,
+            surface,
+        }
+    }
+
+    /// Prints every physical device the instance can see, without checking
+    /// whether they are suitable for rendering.
+    pub fn list_gpus() -> Result<(), String> {
+        let entry = load_entry()?;
+        let debug_utils = Self::supports_debug_utils(&entry);
+        let (instance, _, _) = Self::create_instance(&entry, false, false, debug_utils)?;
+        let physical_devices = unsafe {
+            instance
+                .enumerate_physical_devices()
+                .expect("Failed to enumerate physical devices")
+        };
+        for (index, &physical_device) in physical_devices.iter().enumerate() {
+            print!("[{}]", index);
+            Self::print_device_info(&instance, physical_device);
+        }
+        unsafe {
+            instance.destroy_instance(None);
+        }
+        Ok(())
+    }
+
+    /// Picks the device to render on. When there's none, the error says
+    /// why each device was turned down.
+    fn pick_physical_device(
+        instance: &ash::Instance,
+        surface_stuff: &SurfaceStuff,
+        gpu: Option<&GpuSelector>,
+        requested_features: RequestedFeatures,
+    ) -> Result<(vk::PhysicalDevice, QueueFamilyIndices), String> {
+        let physical_devices = unsafe {
+            instance
+                .enumerate_physical_devices()
+                .expect("Failed to enumerate physical devices")
+        };
+        if physical_devices.is_empty() {
+            return Err("No Vulkan devices found, is a driver installed?".to_string());
+        }
+        println!(
+            "Found {} devices with Vulkan support",
+            physical_devices.len()
+        );
+
+        if let Some(gpu) = gpu {
+            let requested_device = physical_devices
+                .iter()
+                .enumerate()
+                .find(|&(index, &physical_device)| match gpu {
+                    GpuSelector::Index(requested_index) => index == *requested_index,
+                    GpuSelector::Name(name) => {
+                        device_name(instance, physical_device).contains(name.as_str())
+                    }
+                    GpuSelector::DeviceId(device_id) => {
+                        let properties =
+                            unsafe { instance.get_physical_device_properties(physical_device) };
+                        properties.device_id == *device_id
+                    }
+                })
+                .map(|(_, &physical_device)| physical_device)
+                .ok_or("Requested GPU not found, run with --list-gpus to see them all")?;
+            Self::print_device_info(instance, requested_device);
+            let indices = Self::find_queue_family(instance, requested_device, surface_stuff);
+            Self::is_device_suitable(
+                instance,
+                requested_device,
+                surface_stuff,
+                &indices,
+                requested_features,
+            )
+            .map_err(|reason| format!("Requested GPU is not suitable: {}", reason))?;
+            return Ok((requested_device, indices));
+        }
+
+        // A device with every requested feature wins over the first one
+        // that can render at all
+        let mut fallback = None;
+        let mut rejections = vec![];
+        for &physical_device in physical_devices.iter() {
+            Self::print_device_info(instance, physical_device);
+            let indices = Self::find_queue_family(instance, physical_device, surface_stuff);
+            match Self::is_device_suitable(
+                instance,
+                physical_device,
+                surface_stuff,
+                &indices,
+                requested_features,
+            ) {
+                Ok(denied_features) if denied_features.is_empty() => {
+                    return Ok((physical_device, indices))
+                }
+                Ok(_) if fallback.is_none() => fallback = Some((physical_device, indices)),
+                Ok(_) => {}
+                Err(reason) => rejections.push(format!(
+                    "    {}: {}",
+                    device_name(instance, physical_device),
+                    reason
+                )),
+            }
+        }
+        fallback.ok_or_else(|| {
+            format!(
+                "No suitable Vulkan device found:\n{}",
+                rejections.join("\n")
+            )
+        })
+    }
+
+    /// Whether we can query memory budgets with `VK_EXT_memory_budget`. Its
+    /// query goes through vkGetPhysicalDeviceMemoryProperties2, which even
+    /// a Vulkan 1.0 device has with the instance extension.
+    fn supports_memory_budget(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        instance_core.properties2.is_some()
+            && Self::has_device_extension(
+                &instance_core.instance,
+                physical_device,
+                vk::ExtMemoryBudgetFn::name(),
+            )
+    }
+
+    /// How many textures a bindless `TextureArray` can hold, or `None` if
+    /// the device can't do the descriptor indexing it needs. Its features
+    /// and limits are queried the Vulkan 1.1 way.
+    fn bindless_capacity(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<u32> {
+        let instance = &instance_core.instance;
+        let properties2 = instance_core.properties2.as_ref()?;
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 1, 0)
+            || !Self::has_device_extension(
+                instance,
+                physical_device,
+                vk::ExtDescriptorIndexingFn::name(),
+            )
+        {
+            return None;
+        }
+        let indexing_features: vk::PhysicalDeviceDescriptorIndexingFeaturesEXT =
+            unsafe { properties2.features(physical_device) };
+        let required = [
+            indexing_features.runtime_descriptor_array,
+            indexing_features.descriptor_binding_partially_bound,
+            indexing_features.descriptor_binding_sampled_image_update_after_bind,
+        ];
+        if required.contains(&vk::FALSE) {
+            return None;
+        }
+        let indexing_properties: vk::PhysicalDeviceDescriptorIndexingPropertiesEXT =
+            unsafe { properties2.properties(physical_device) };
+        let limit = indexing_properties
+            .max_descriptor_set_update_after_bind_sampled_images
+            .min(indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images);
+        Some(limit.min(texture_array::MAX_BINDLESS_TEXTURES))
+    }
+
+    /// Whether we can render with `VK_KHR_dynamic_rendering`. The extension
+    /// depends on others that are core in Vulkan 1.2, so below that it's left
+    /// alone rather than enabling them too.
+    fn supports_dynamic_rendering(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let instance = &instance_core.instance;
+        let properties2 = match &instance_core.properties2 {
+            Some(properties2) => properties2,
+            None => return false,
+        };
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 2, 0)
+            || !Self::has_device_extension(
+                instance,
+                physical_device,
+                DynamicRenderingLoader::name(),
+            )
+        {
+            return false;
+        }
+        let rendering_features: vk::PhysicalDeviceDynamicRenderingFeatures =
+            unsafe { properties2.features(physical_device) };
+        rendering_features.dynamic_rendering == vk::TRUE
+    }
+
+    /// Whether frames can be synchronized with a timeline semaphore. Its
+    /// feature is queried the Vulkan 1.1 way, like `bindless_capacity`.
+    fn supports_timeline_semaphores(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let instance = &instance_core.instance;
+        let properties2 = match &instance_core.properties2 {
+            Some(properties2) => properties2,
+            None => return false,
+        };
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 1, 0)
+            || !Self::has_device_extension(instance, physical_device, TimelineSemaphore::name())
+        {
+            return false;
+        }
+        let timeline_features: vk::PhysicalDeviceTimelineSemaphoreFeatures =
+            unsafe { properties2.features(physical_device) };
+        timeline_features.timeline_semaphore == vk::TRUE
+    }
+
+    /// Whether a render pass can draw into more than one layer at a time,
+    /// with `VK_KHR_multiview` as part of Vulkan 1.1.
+    fn supports_multiview(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let instance = &instance_core.instance;
+        let properties2 = match &instance_core.properties2 {
+            Some(properties2) => properties2,
+            None => return false,
+        };
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 1, 0)
+        {
+            return false;
+        }
+        let multiview_properties: vk::PhysicalDeviceMultiviewProperties =
+            unsafe { properties2.properties(physical_device) };
+        let multiview_features: vk::PhysicalDeviceMultiviewFeatures =
+            unsafe { properties2.features(physical_device) };
+        multiview_features.multiview == vk::TRUE
+            && multiview_properties.max_multiview_view_count >= EYE_COUNT
+    }
+
+    /// Whether shaders can read buffers through their address, with
+    /// `VK_KHR_buffer_device_address`. Its feature is queried the Vulkan
+    /// 1.1 way, like `bindless_capacity`.
+    fn supports_buffer_device_address(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let instance = &instance_core.instance;
+        let properties2 = match &instance_core.properties2 {
+            Some(properties2) => properties2,
+            None => return false,
+        };
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 1, 0)
+            || !Self::has_device_extension(instance, physical_device, BufferDeviceAddress::name())
+        {
+            return false;
+        }
+        let address_features: vk::PhysicalDeviceBufferDeviceAddressFeatures =
+            unsafe { properties2.features(physical_device) };
+        address_features.buffer_device_address == vk::TRUE
+    }
+
+    /// Whether the device can trace rays for `--raytracing`, with all the
+    /// extensions of `RAY_TRACING_EXTENSIONS` and buffer device addresses.
+    /// Like `supports_buffer_device_address`, the features are queried the
+    /// Vulkan 1.1 way.
+    fn supports_ray_tracing(
+        instance_core: &InstanceCore,
+        physical_device: vk::PhysicalDevice,
+    ) -> bool {
+        let instance = &instance_core.instance;
+        let properties2 = match &instance_core.properties2 {
+            Some(properties2) => properties2,
+            None => return false,
+        };
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let mut extensions = RAY_TRACING_EXTENSIONS.iter().map(|name| name()).chain([
+            BufferDeviceAddress::name(),
+            vk::ExtDescriptorIndexingFn::name(),
+        ]);
+        if instance_core.api_version.min(device_properties.api_version)
+            < vk::make_api_version(0, 1, 1, 0)
+            || !extensions.all(|name| Self::has_device_extension(instance, physical_device, name))
+        {
+            return false;
+        }
+        let acceleration_structure_features: vk::PhysicalDeviceAccelerationStructureFeaturesKHR =
+            unsafe { properties2.features(physical_device) };
+        let ray_tracing_features: vk::PhysicalDeviceRayTracingPipelineFeaturesKHR =
+            unsafe { properties2.features(physical_device) };
+        let address_features: vk::PhysicalDeviceBufferDeviceAddressFeatures =
+            unsafe { properties2.features(physical_device) };
+        acceleration_structure_features.acceleration_structure == vk::TRUE
+            && ray_tracing_features.ray_tracing_pipeline == vk::TRUE
+            && address_features.buffer_device_address == vk::TRUE
+    }
+
+    fn has_device_extension(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        name: &CStr,
+    ) -> bool {
+        let extensions = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .expect("Failed to get device extension properties.")
+        };
+        let name = name.to_str().unwrap();
+        extensions
+            .iter()
+            .any(|extension| vk_to_string(&extension.extension_name) == name)
+    }
+
+    /// Prints the memory heaps and the memory types allocations can be made
+    /// from, which is what gpu-allocator has to choose between.
+    fn print_memory_properties(instance: &ash::Instance, physical_device: vk::PhysicalDevice) {
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let heaps = &memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize];
+        for (index, heap) in heaps.iter().enumerate() {
+            println!(
+                "\tMemory heap {}: {} MiB, {:?}",
+                index,
+                heap.size / (1024 * 1024),
+                heap.flags
+            );
+        }
+        let types = &memory_properties.memory_types[..memory_properties.memory_type_count as usize];
+        for (index, memory_type) in types.iter().enumerate() {
+            println!(
+                "\tMemory type {}: heap {}, {:?}",
+                index, memory_type.heap_index, memory_type.property_flags
+            );
+        }
+    }
+
+    fn print_device_info(instance: &ash::Instance, physical_device: vk::PhysicalDevice) {
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+
+        let device_type = match device_properties.device_type {
+            vk::PhysicalDeviceType::CPU => "Cpu",
+            vk::PhysicalDeviceType::INTEGRATED_GPU => "Integrated GPU",
+            vk::PhysicalDeviceType::DISCRETE_GPU => "Discrete GPU",
+            vk::PhysicalDeviceType::VIRTUAL_GPU => "Virtual GPU",
+            vk::PhysicalDeviceType::OTHER => "Unknown",
+            _ => panic!("Matching on device type failed"),
+        };
+        let device_name = vk_to_string(&device_properties.device_name);
+        println!(
+            "\tDevice Name: {}, id: {:#06x}, type: {}",
+            device_name, device_properties.device_id, device_type,
+        );
+    }
+
+    /// Returns the reason the device can't be used for rendering, if any.
+    /// Otherwise returns which of `requested_features` it lacks.
+    fn is_device_suitable(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        surface_stuff: &SurfaceStuff,
+        indices: &QueueFamilyIndices,
+        requested_features: RequestedFeatures,
+    ) -> Result<RequestedFeatures, String> {
+        if indices.graphics_family.is_none() {
+            return Err("no queue family supports graphics".to_string());
+        }
+        if indices.present_family.is_none() {
+            return Err("no queue family can present to the window's surface".to_string());
+        }
+        let missing_extensions = Self::missing_device_extensions(instance, physical_device);
+        if !missing_extensions.is_empty() {
+            return Err(format!(
+                "missing device extensions {}",
+                missing_extensions.join(", ")
+            ));
+        }
+        let swapchain_support = SwapChainSupportDetails::query(physical_device, surface_stuff);
+        if swapchain_support.formats.is_empty() {
+            return Err("no surface formats available".to_string());
+        }
+        if swapchain_support.present_modes.is_empty() {
+            return Err("no present modes available".to_string());
+        }
+        let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+        let (_, denied_features) = requested_features.resolve(&supported_features);
+        Ok(denied_features)
+    }
+
+    fn find_queue_family(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        surface_stuff: &SurfaceStuff,
+    ) -> QueueFamilyIndices {
+        let queue_families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+        let present_support: Vec<bool> = (0..queue_families.len() as u32)
+            .map(|index| unsafe {
+                surface_stuff
+                    .surface_loader
+                    .get_physical_device_surface_support(
+                        physical_device,
+                        index,
+                        surface_stuff.surface,
+                    )
+                    .expect("Failed to query surface support!")
+            })
+            .collect();
+        let mut queue_family_indices = QueueFamilyIndices::pick(&queue_families, &present_support);
+
+        let supports_compute = |index: u32| {
+            queue_families[index as usize].queue_count > 0
+                && queue_families[index as usize]
+                    .queue_flags
+                    .contains(vk::QueueFlags::COMPUTE)
+        };
+        let supports_graphics = |index: u32| {
+            queue_families[index as usize]
+                .queue_flags
+                .contains(vk::QueueFlags::GRAPHICS)
+        };
+        let families = 0..queue_families.len() as u32;
+        queue_family_indices.compute_family = families
+            .clone()
+            .find(|&index| supports_compute(index) && !supports_graphics(index))
+            .or(queue_family_indices
+                .graphics_family
+                .filter(|&index| supports_compute(index)))
+            .or_else(|| families.clone().find(|&index| supports_compute(index)));
+
+        queue_family_indices
+    }
+
+    /// Which of `DEVICE_EXTENSIONS` the device doesn't have.
+    fn missing_device_extensions(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Vec<String> {
+        let available_extensions = unsafe {
+            instance
+                .enumerate_device_extension_properties(physical_device)
+                .expect("Failed to get device extension properties.")
+        };
+        let mut available_extension_names = vec![];
+        for extension in available_extensions.iter() {
+            let extension_name = vk_to_string(&extension.extension_name);
+            available_extension_names.push(extension_name);
+        }
+        let mut required_extensions = HashSet::new();
+        for extension in DEVICE_EXTENSIONS.iter() {
+            required_extensions.insert(extension.to_string());
+        }
+        for extension_name in available_extension_names.iter() {
+            required_extensions.remove(extension_name);
+        }
+        let mut missing_extensions: Vec<String> = required_extensions.into_iter().collect();
+        missing_extensions.sort();
+        missing_extensions
+    }
+
+    /// Creates a pipeline cache seeded from the previous run, if there is
+    /// a cache file on disk that was written by the same device.
+    fn create_pipeline_cache(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+    ) -> vk::PipelineCache {
+        let device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let initial_data = match std::fs::read(PIPELINE_CACHE_PATH) {
+            Ok(data) if Self::is_pipeline_cache_compatible(&data, &device_properties) => data,
+            Ok(_) => {
+                println!("Ignoring stale or corrupt {}", PIPELINE_CACHE_PATH);
+                vec![]
+            }
+            Err(_) => vec![],
+        };
+
+        let create_info = vk::PipelineCacheCreateInfo {
+            initial_data_size: initial_data.len(),
+            p_initial_data: initial_data.as_ptr() as *const c_void,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_pipeline_cache(&create_info, None)
+                .or_else(|_| {
+                    // The driver rejected the data, start with an empty cache instead
+                    device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+                })
+                .expect("Failed to create pipeline cache")
+        }
+    }
+
+    /// Checks the pipeline cache header described in the spec for
+    /// vkGetPipelineCacheData against the current device.
+    fn is_pipeline_cache_compatible(
+        data: &[u8],
+        device_properties: &vk::PhysicalDeviceProperties,
+    ) -> bool {
+        const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+        if data.len() < HEADER_SIZE {
+            return false;
+        }
+        let read_u32 = |offset: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&data[offset..offset + 4]);
+            u32::from_ne_bytes(bytes)
+        };
+        read_u32(0) as usize >= HEADER_SIZE
+            && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && read_u32(8) == device_properties.vendor_id
+            && read_u32(12) == device_properties.device_id
+            && data[16..HEADER_SIZE] == device_properties.pipeline_cache_uuid
+    }
+
+    fn save_pipeline_cache(&self) {
+        let data = unsafe {
+            self.core
+                .device
+                .get_pipeline_cache_data(self.pipeline_cache)
+        };
+        match data {
+            Ok(data) => {
+                if let Err(error) = std::fs::write(PIPELINE_CACHE_PATH, data) {
+                    eprintln!("Failed to write {}: {}", PIPELINE_CACHE_PATH, error);
+                }
+            }
+            Err(error) => eprintln!("Failed to get pipeline cache data: {:?}", error),
+        }
+    }
+
+    fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                // The fragment shader reads the light
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            // Per-object transforms, see `Objects`
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create Descriptor Set Layout!")
+        }
+    }
+
+    fn create_pipeline_layout(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> vk::PipelineLayout {
+        // With the `--outline` scale and the `--vertex-pulling` address
+        // after them
+        let push_constants_size =
+            VERTEX_ADDRESS_OFFSET + std::mem::size_of::<vk::DeviceAddress>() as u32;
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+        // The spec guarantees at least 128 bytes, but don't rely on it silently
+        assert!(
+            push_constants_size <= limits.max_push_constants_size,
+            "Push constants take {} bytes, but the device only allows {}",
+            push_constants_size,
+            limits.max_push_constants_size
+        );
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: push_constants_size,
+        }];
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create pipeline layout")
+        }
+    }
+
+    /// A single subpass drawing into the swapchain image, unless there are
+    /// `gbuffer_formats`. Then the first of two subpasses draws into the
+    /// G-buffer, and the second lights it into the swapchain image and draws
+    /// everything that isn't lit, see `deferred.rs`. With `offscreen` it
+    /// draws into the offscreen image instead, and leaves it to be sampled.
+    /// More than one view draws every subpass into that many layers. With
+    /// more than one of `samples`, the color attachment is resolved into
+    /// one after the G-buffer at the end.
+    fn create_render_pass(
+        device: &ash::Device,
+        swapchain_image_format: vk::Format,
+        depth_format: vk::Format,
+        gbuffer_formats: &[vk::Format],
+        offscreen: bool,
+        view_count: u32,
+        samples: vk::SampleCountFlags,
+    ) -> vk::RenderPass {
+        let color_attachment = vk::AttachmentDescription {
+            format: if offscreen {
+                OFFSCREEN_FORMAT
+            } else {
+                swapchain_image_format
+            },
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            // We don't use stencil buffer
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            // We don't care about initial layout because we're gonna clear on load
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            // We want to present the image with the swapchain after
+            // rendering, or sample the offscreen image
+            final_layout: if offscreen {
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            } else {
+                vk::ImageLayout::PRESENT_SRC_KHR
+            },
+            ..Default::default()
+        };
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+        let mut attachments = vec![
+            // With --msaa, the samples are only needed until they're
+            // resolved into the color attachment, which then comes last
+            if multisampled {
+                vk::AttachmentDescription {
+                    samples,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    ..color_attachment
+                }
+            } else {
+                color_attachment
+            },
+            // Depth and stencil are only needed while rendering, so they're
+            // neither loaded nor stored. Formats without stencil ignore its
+            // load op.
+            vk::AttachmentDescription {
+                format: depth_format,
+                samples,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::DONT_CARE,
+                stencil_load_op: vk::AttachmentLoadOp::CLEAR,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            },
+        ];
+        // Same goes for the G-buffer
+        attachments.extend(
+            gbuffer_formats
+                .iter()
+                .map(|&format| vk::AttachmentDescription {
+                    format,
+                    samples: vk::SampleCountFlags::TYPE_1,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                    stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    initial_layout: vk::ImageLayout::UNDEFINED,
+                    final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    ..Default::default()
+                }),
+        );
+        // Resolving overwrites all of it
+        let resolve_attachment_refs = [vk::AttachmentReference {
+            attachment: attachments.len() as u32,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        if multisampled {
+            attachments.push(vk::AttachmentDescription {
+                load_op: vk::AttachmentLoadOp::DONT_CARE,
+                ..color_attachment
+            });
+        }
+
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let gbuffer_attachments = 2..2 + gbuffer_formats.len() as u32;
+        let gbuffer_output_refs: Vec<vk::AttachmentReference> = gbuffer_attachments
+            .clone()
+            .map(|attachment| vk::AttachmentReference {
+                attachment,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect();
+        let gbuffer_input_refs: Vec<vk::AttachmentReference> = gbuffer_attachments
+            .map(|attachment| vk::AttachmentReference {
+                attachment,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+            .collect();
+
+        let subpasses = if gbuffer_formats.is_empty() {
+            vec![vk::SubpassDescription {
+                pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+                color_attachment_count: color_attachment_refs.len() as u32,
+                p_color_attachments: color_attachment_refs.as_ptr(),
+                p_resolve_attachments: if multisampled {
+                    resolve_attachment_refs.as_ptr()
+                } else {
+                    std::ptr::null()
+                },
+                p_depth_stencil_attachment: &depth_attachment_ref,
+                ..Default::default()
+            }]
+        } else {
+            vec![
+                vk::SubpassDescription {
+                    pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+                    color_attachment_count: gbuffer_output_refs.len() as u32,
+                    p_color_attachments: gbuffer_output_refs.as_ptr(),
+                    p_depth_stencil_attachment: &depth_attachment_ref,
+                    ..Default::default()
+                },
+                // Still depth tested, for what's drawn after the lighting
+                vk::SubpassDescription {
+                    pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+                    input_attachment_count: gbuffer_input_refs.len() as u32,
+                    p_input_attachments: gbuffer_input_refs.as_ptr(),
+                    color_attachment_count: color_attachment_refs.len() as u32,
+                    p_color_attachments: color_attachment_refs.as_ptr(),
+                    p_depth_stencil_attachment: &depth_attachment_ref,
+                    ..Default::default()
+                },
+            ]
+        };
+
+        // The image layout transition at the start of the render pass has to
+        // wait until the swapchain is done reading the image, which
+        // draw_frame expresses by waiting on COLOR_ATTACHMENT_OUTPUT. The one
+        // depth image is shared between frames, so clearing it also has to
+        // wait for the previous frame's depth tests. The same goes for the
+        // G-buffer and the previous frame's lighting.
+        let mut dependencies = vec![vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        }];
+        // Lighting reads each G-buffer pixel right where it was written, and
+        // the depth tests after it need the scene's depth
+        if subpasses.len() > 1 {
+            dependencies.push(vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: 1,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::INPUT_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dependency_flags: vk::DependencyFlags::BY_REGION,
+            });
+        }
+        // The present pass samples the offscreen image once it's written
+        if offscreen {
+            dependencies.push(vk::SubpassDependency {
+                src_subpass: subpasses.len() as u32 - 1,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                dependency_flags: vk::DependencyFlags::empty(),
+            });
+        }
+
+        // One bit per view, and the views are close enough for the driver
+        // to render them together
+        let view_masks = vec![(1 << view_count) - 1; subpasses.len()];
+        let correlation_masks = [(1 << view_count) - 1];
+        let multiview_info = vk::RenderPassMultiviewCreateInfo {
+            subpass_count: view_masks.len() as u32,
+            p_view_masks: view_masks.as_ptr(),
+            correlation_mask_count: correlation_masks.len() as u32,
+            p_correlation_masks: correlation_masks.as_ptr(),
+            ..Default::default()
+        };
+        let render_pass_info = vk::RenderPassCreateInfo {
+            p_next: if view_count > 1 {
+                &multiview_info as *const _ as *const c_void
+            } else {
+                std::ptr::null()
+            },
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: subpasses.len() as u32,
+            p_subpasses: subpasses.as_ptr(),
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .create_render_pass(&render_pass_info, None)
+                .expect("Failed to create render pass")
+        }
+    }
+
+    /// Loads `<name>.spv` from `shader_dir` if given, otherwise falls back to
+    /// the copy embedded in the binary.
+    fn load_shader_code(shader_dir: Option<&Path>, name: &str) -> Result<Vec<u32>, String> {
+        let bytes = match shader_dir {
+            Some(shader_dir) => Self::read_shader_code(&shader_dir.join(format!("{}.spv", name)))?,
+            None => EMBEDDED_SHADERS
+                .iter()
+                .chain(EMBEDDED_RAYTRACING_SHADERS.iter())
+                .find(|&&(shader_name, _)| shader_name == name)
+                .map(|&(_, code)| code.to_vec())
+                .ok_or_else(|| format!("No embedded shader named {}", name))?,
+        };
+        spv_to_words(&bytes).map_err(|error| format!("Invalid SPIR-V in {}: {}", name, error))
+    }
+
+    fn read_shader_code(shader_path: &Path) -> Result<Vec<u8>, String> {
+        std::fs::read(shader_path)
+            .map_err(|error| format!("Failed to read spv file at {:?}: {}", shader_path, error))
+    }
+
+    fn create_shader_module(
+        device: &ash::Device,
+        code: &[u32],
+    ) -> Result<vk::ShaderModule, String> {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            // code_size is in bytes, even though the code is made of u32 words
+            code_size: std::mem::size_of_val(code),
+            p_code: code.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .map_err(|error| format!("Failed to create Shader Module: {:?}", error))
+        }
+    }
+
+    /// Rebuilds the pipelines from the shaders on disk. If that fails, the
+    /// old pipelines are kept, so a broken shader doesn't kill the app.
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self) {
+        match self.pipelines.rebuild(self.pipelines.brightness()) {
+            Ok(()) => {
+                self.scene_commands.invalidate();
+                self.name_pipelines();
+                println!("Reloaded shaders");
+            }
+            Err(error) => eprintln!("Failed to reload shaders, keeping the old ones: {}", error),
+        }
+    }
+
+    /// Sample counts both the color and the depth attachments can have.
+    fn supported_sample_counts(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::SampleCountFlags {
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+        limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts
+    }
+
+    /// Doubles the MSAA samples up to 8 or the most the device supports,
+    /// then goes back to 1. That takes a new render pass, and with it new
+    /// framebuffers, color and depth images and pipelines.
+    fn cycle_msaa(&mut self) {
+        let render_pass = match self.render_pass {
+            Some(render_pass) if self.deferred.is_none() => render_pass,
+            _ => {
+                println!("MSAA needs a render pass without deferred shading");
+                return;
+            }
+        };
+        let current = self.pipelines.samples();
+        let supported =
+            Self::supported_sample_counts(&self.core.instance.instance, self.physical_device);
+        let next = choose_sample_count(current.as_raw() * 2, supported);
+        let samples = if next == current || next.as_raw() > 8 {
+            vk::SampleCountFlags::TYPE_1
+        } else {
+            next
+        };
+        if samples == current {
+            println!("The device doesn't support MSAA");
+            return;
+        }
+        let new_render_pass = Self::create_render_pass(
+            &self.core.device,
+            self.swapchain.format,
+            self.swapchain.depth_format,
+            &[],
+            self.offscreen.is_some(),
+            if self.multiview { EYE_COUNT } else { 1 },
+            samples,
+        );
+        if let Err(error) = self
+            .pipelines
+            .retarget(RenderTarget::RenderPass(new_render_pass), samples)
+        {
+            eprintln!("Failed to rebuild pipelines: {}", error);
+            unsafe {
+                self.core.device.destroy_render_pass(new_render_pass, None);
+            }
+            return;
+        }
+        self.render_pass = Some(new_render_pass);
+        self.core.set_debug_name(new_render_pass, "render_pass");
+        self.swapchain.set_samples(samples);
+        self.recreate_swapchain();
+        unsafe {
+            self.core.device.destroy_render_pass(render_pass, None);
+        }
+        self.scene_commands.invalidate();
+        self.name_pipelines();
+        println!("MSAA: {}x", samples.as_raw());
+    }
+
+    fn toggle_sample_shading(&mut self) {
+        if !self.sample_shading_supported {
+            println!("The device doesn't support sample shading");
+            return;
+        }
+        let sample_shading = !self.pipelines.sample_shading();
+        match self.pipelines.set_sample_shading(sample_shading) {
+            Ok(()) => {
+                self.scene_commands.invalidate();
+                self.name_pipelines();
+                println!(
+                    "Sample shading {}{}",
+                    if sample_shading { "on" } else { "off" },
+                    if self.pipelines.samples() == vk::SampleCountFlags::TYPE_1 {
+                        ", which needs MSAA (F6)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            Err(error) => eprintln!("Failed to rebuild pipelines: {}", error),
+        }
+    }
+
+    /// Switches to the next value in `BRIGHTNESS_CYCLE`.
+    fn cycle_brightness(&mut self) {
+        let current_index = BRIGHTNESS_CYCLE
+            .iter()
+            .position(|&brightness| brightness == self.pipelines.brightness())
+            .unwrap_or(0);
+        let brightness = BRIGHTNESS_CYCLE[(current_index + 1) % BRIGHTNESS_CYCLE.len()];
+        match self.pipelines.rebuild(brightness) {
+            Ok(()) => {
+                self.scene_commands.invalidate();
+                self.name_pipelines();
+                println!("Brightness: {}", brightness);
+            }
+            Err(error) => eprintln!("Failed to rebuild pipelines: {}", error),
+        }
+    }
+
+    fn toggle_alpha_blending(&mut self) {
+        self.alpha_blending = !self.alpha_blending;
+        // Create the pipeline right away, so it can be switched back if
+        // that fails
+        match self.pipeline(self.scene_pipeline_key(), self.pipeline_layout) {
+            Ok(_) => println!(
+                "Alpha blending {}",
+                if self.alpha_blending { "on" } else { "off" }
+            ),
+            Err(error) => {
+                eprintln!("Failed to create pipeline: {}", error);
+                self.alpha_blending = !self.alpha_blending;
+            }
+        }
+    }
+
+    fn create_logical_device(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        indices: &QueueFamilyIndices,
+        enabled_features: &vk::PhysicalDeviceFeatures,
+        validation: bool,
+        optional_extensions: &OptionalExtensions,
+    ) -> (
+        ash::Device,
+        vk::Queue,
+        vk::Queue,
+        vk::Queue,
+        Option<vk::Queue>,
+    ) {
+        let graphics_family = indices.graphics_family.unwrap();
+        let present_family = indices.present_family.unwrap();
+        let transfer_family = indices.upload_family().unwrap();
+
+        let mut unique_queue_families = HashSet::new();
+        unique_queue_families.insert(graphics_family);
+        unique_queue_families.insert(present_family);
+        unique_queue_families.insert(transfer_family);
+        unique_queue_families.extend(indices.compute_family);
+
+        let queue_priorities = [1.0_f32];
+        let mut queue_create_infos = vec![];
+        for &queue_family in unique_queue_families.iter() {
+            let queue_create_info = vk::DeviceQueueCreateInfo {
+                queue_family_index: queue_family,
+                p_queue_priorities: queue_priorities.as_ptr(),
+                queue_count: queue_priorities.len() as u32,
+                ..Default::default()
+            };
+            queue_create_infos.push(queue_create_info);
+        }
+
+        // Device layers are ignored by current loaders, but older ones still
+        // want them to match the instance's
+        let enabled_layer_raw_names = enabled_validation_layer_names(validation);
+        let enabled_layer_names: Vec<*const c_char> = enabled_layer_raw_names
+            .iter()
+            .map(|layer_name| layer_name.as_ptr())
+            .collect();
+
+        let mut enabled_extension_names = vec![khr::Swapchain::name().as_ptr()];
+        if optional_extensions.memory_budget {
+            enabled_extension_names.push(vk::ExtMemoryBudgetFn::name().as_ptr());
+        }
+        // Feature structs of the optional extensions, chained through p_next
+        let mut features_chain: *const c_void = std::ptr::null();
+        // What a bindless TextureArray needs, see `bindless_capacity`
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeaturesEXT {
+            runtime_descriptor_array: vk::TRUE,
+            descriptor_binding_partially_bound: vk::TRUE,
+            descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.descriptor_indexing {
+            enabled_extension_names.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+            indexing_features.p_next = features_chain as *mut c_void;
+            features_chain = &indexing_features as *const _ as *const c_void;
+        }
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures {
+            timeline_semaphore: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.timeline_semaphore {
+            enabled_extension_names.push(TimelineSemaphore::name().as_ptr());
+            timeline_features.p_next = features_chain as *mut c_void;
+            features_chain = &timeline_features as *const _ as *const c_void;
+        }
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures {
+            multiview: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.multiview {
+            multiview_features.p_next = features_chain as *mut c_void;
+            features_chain = &multiview_features as *const _ as *const c_void;
+        }
+        let mut rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures {
+            dynamic_rendering: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.dynamic_rendering {
+            enabled_extension_names.push(DynamicRenderingLoader::name().as_ptr());
+            rendering_features.p_next = features_chain as *mut c_void;
+            features_chain = &rendering_features as *const _ as *const c_void;
+        }
+        let mut address_features = vk::PhysicalDeviceBufferDeviceAddressFeatures {
+            buffer_device_address: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.buffer_device_address {
+            enabled_extension_names.push(BufferDeviceAddress::name().as_ptr());
+            address_features.p_next = features_chain as *mut c_void;
+            features_chain = &address_features as *const _ as *const c_void;
+        }
+        let mut acceleration_structure_features =
+            vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+                acceleration_structure: vk::TRUE,
+                ..Default::default()
+            };
+        let mut ray_tracing_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
+            ray_tracing_pipeline: vk::TRUE,
+            ..Default::default()
+        };
+        if optional_extensions.ray_tracing {
+            enabled_extension_names.extend(RAY_TRACING_EXTENSIONS.map(|name| name().as_ptr()));
+            // Only the extension, its features are for bindless textures
+            if !optional_extensions.descriptor_indexing {
+                enabled_extension_names.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+            }
+            acceleration_structure_features.p_next = features_chain as *mut c_void;
+            ray_tracing_features.p_next =
+                &mut acceleration_structure_features as *mut _ as *mut c_void;
+            features_chain = &ray_tracing_features as *const _ as *const c_void;
+        }
+
+        #[allow(deprecated)]
+        let device_create_info = vk::DeviceCreateInfo {
+            p_next: features_chain,
+            queue_create_info_count: queue_create_infos.len() as u32,
+            p_queue_create_infos: queue_create_infos.as_ptr(),
+            enabled_layer_count: enabled_layer_names.len() as u32,
+            pp_enabled_layer_names: enabled_layer_names.as_ptr(),
+            p_enabled_features: enabled_features,
+            enabled_extension_count: enabled_extension_names.len() as u32,
+            pp_enabled_extension_names: enabled_extension_names.as_ptr(),
+            ..Default::default()
+        };
+
+        let device: ash::Device = unsafe {
+            instance
+                .create_device(physical_device, &device_create_info, None)
+                .expect("Failed to create logical Device!")
+        };
+        let graphics_queue = unsafe { device.get_device_queue(graphics_family, 0) };
+        let present_queue = unsafe { device.get_device_queue(present_family, 0) };
+        let transfer_queue = unsafe { device.get_device_queue(transfer_family, 0) };
+        let compute_queue = indices
+            .compute_family
+            .map(|compute_family| unsafe { device.get_device_queue(compute_family, 0) });
+        (
+            device,
+            graphics_queue,
+            present_queue,
+            transfer_queue,
+            compute_queue,
+        )
+    }
+
+    fn setup_debug_utils(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> (DebugUtils, vk::DebugUtilsMessengerEXT) {
+        let debug_utils_loader = DebugUtils::new(entry, instance);
+
+        let messenger_create_info = populate_debug_messenger_create_info();
+        let utils_messenger = unsafe {
+            debug_utils_loader
+                .create_debug_utils_messenger(&messenger_create_info, None)
+                .expect("Failed to create debug utils messenger")
+        };
+        (debug_utils_loader, utils_messenger)
+    }
+
+    fn create_command_pool(device: &ash::Device, indices: &QueueFamilyIndices) -> vk::CommandPool {
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            // We re-record command buffers every frame
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: indices.graphics_family.unwrap(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .expect("Failed to create Command Pool!")
+        }
+    }
+
+    fn create_transfer_command_pool(
+        device: &ash::Device,
+        indices: &QueueFamilyIndices,
+    ) -> vk::CommandPool {
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            // Upload command buffers are recorded once, submitted and freed
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: indices.upload_family().unwrap(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .expect("Failed to create Command Pool!")
+        }
+    }
+
+    fn create_command_buffers(
+        device: &ash::Device,
+        command_pool: vk::CommandPool,
+    ) -> Vec<vk::CommandBuffer> {
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: MAX_FRAMES_IN_FLIGHT as u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .expect("Failed to allocate Command Buffers!")
+        }
+    }
+
+    fn create_sync_objects(device: &ash::Device) -> SyncObjects {
+        let mut sync_objects = SyncObjects {
+            image_available_semaphores: vec![],
+            render_finished_semaphores: vec![],
+        };
+
+        let semaphore_create_info = vk::SemaphoreCreateInfo::default();
+
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            unsafe {
+                let image_available_semaphore = device
+                    .create_semaphore(&semaphore_create_info, None)
+                    .expect("Failed to create Semaphore!");
+                let render_finished_semaphore = device
+                    .create_semaphore(&semaphore_create_info, None)
+                    .expect("Failed to create Semaphore!");
+
+                sync_objects
+                    .image_available_semaphores
+                    .push(image_available_semaphore);
+                sync_objects
+                    .render_finished_semaphores
+                    .push(render_finished_semaphore);
+            }
+        }
+
+        sync_objects
+    }
+
+    fn create_buffer(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Buffer {
+        Self::create_shared_buffer(device, allocator, name, size, usage, location, &[])
+    }
+
+    /// Like `create_buffer`, but concurrently accessible from all of
+    /// `queue_families`, so it doesn't have to be handed over between them.
+    /// Exclusive to one queue family if there are less than two.
+    fn create_shared_buffer(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+        queue_families: &[u32],
+    ) -> Buffer {
+        let sharing_mode = if queue_families.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
+        let buffer_create_info = vk::BufferCreateInfo {
+            size,
+            usage,
+            sharing_mode,
+            queue_family_index_count: queue_families.len() as u32,
+            p_queue_family_indices: queue_families.as_ptr(),
+            ..Default::default()
+        };
+        let buffer = unsafe {
+            device
+                .create_buffer(&buffer_create_info, None)
+                .expect("Failed to create Buffer!")
+        };
+
+        let memory_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        let allocation = allocator::allocate(allocator, name, memory_requirements, location, true);
+        unsafe {
+            device
+                .bind_buffer_memory(buffer, allocator::memory(&allocation), allocation.offset())
+                .expect("Failed to bind Buffer memory!");
+        }
+        Buffer::new(device, allocator, buffer, allocation)
+    }
+
+    /// Allocates a one-off command buffer from `command_pool` and starts
+    /// recording it.
+    fn begin_single_time_commands(&self, command_pool: vk::CommandPool) -> vk::CommandBuffer {
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+        unsafe {
+            let command_buffer = self
+                .core
+                .device
+                .allocate_command_buffers(&allocate_info)
+                .expect("Failed to allocate Command Buffer!")[0];
+            self.core
+                .device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("Failed to begin recording Command Buffer!");
+            command_buffer
+        }
+    }
+
+    /// Submits a command buffer from `begin_single_time_commands`, waits for
+    /// it to finish and frees it.
+    fn end_single_time_commands(
+        &self,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo {
+            command_buffer_count: command_buffers.len() as u32,
+            p_command_buffers: command_buffers.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            let device = &self.core.device;
+            device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record Command Buffer!");
+            device
+                .queue_submit(queue, &[submit_info], vk::Fence::null())
+                .expect("Failed to submit upload Command Buffer!");
+            device
+                .queue_wait_idle(queue)
+                .expect("Failed to wait for upload queue!");
+            device.free_command_buffers(command_pool, &command_buffers);
+        }
+    }
+
+    /// Copies `size` bytes from `src` to `dst` on the transfer queue, and
+    /// hands `dst` over to the graphics queue family if that's a different
+    /// one.
+    ///
+    /// `dst` must have been created with `SharingMode::EXCLUSIVE`, which is
+    /// what `create_buffer` does.
+    fn copy_buffer(&self, src: vk::Buffer, dst: vk::Buffer, size: vk::DeviceSize) {
+        let device = &self.core.device;
+        let command_buffer = self.begin_single_time_commands(self.transfer_command_pool);
+        let region = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size,
+        };
+        unsafe {
+            device.cmd_copy_buffer(command_buffer, src, dst, &[region]);
+        }
+        if self.transfer_family == self.graphics_family {
+            self.end_single_time_commands(
+                self.transfer_command_pool,
+                self.transfer_queue,
+                command_buffer,
+            );
+            return;
+        }
+
+        // Ownership transfer: the same barrier is recorded once on each side,
+        // releasing on the transfer queue and acquiring on the graphics one.
+        let ownership_barrier = vk::BufferMemoryBarrier {
+            src_queue_family_index: self.transfer_family,
+            dst_queue_family_index: self.graphics_family,
+            buffer: dst,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        let release_barrier = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            ..ownership_barrier
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[release_barrier],
+                &[],
+            );
+        }
+        self.end_single_time_commands(
+            self.transfer_command_pool,
+            self.transfer_queue,
+            command_buffer,
+        );
+
+        // The transfer queue is idle by now, so no semaphore is needed
+        // between the release and the acquire.
+        let command_buffer = self.begin_single_time_commands(self.command_pool);
+        let acquire_barrier = vk::BufferMemoryBarrier {
+            dst_access_mask: vk::AccessFlags::INDIRECT_COMMAND_READ
+                | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                | vk::AccessFlags::INDEX_READ
+                | vk::AccessFlags::UNIFORM_READ,
+            ..ownership_barrier
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::DRAW_INDIRECT
+                    | vk::PipelineStageFlags::VERTEX_INPUT
+                    | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[acquire_barrier],
+                &[],
+            );
+        }
+        self.end_single_time_commands(self.command_pool, self.graphics_queue, command_buffer);
+    }
+
+    /// Instance data is written once up front, so it stays in host visible
+    /// memory rather than going through a staging buffer.
+    fn create_instance_buffer(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        instances: &[InstanceData],
+    ) -> Buffer {
+        let size = std::mem::size_of_val(instances);
+        let buffer = Self::create_buffer(
+            device,
+            allocator,
+            "instance_buffer",
+            size as vk::DeviceSize,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = buffer
+            .mapped_ptr()
+            .expect("Instance Buffer memory isn't host visible!");
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                instances.as_ptr(),
+                mapped.cast::<InstanceData>().as_ptr(),
+                instances.len(),
+            );
+        }
+        buffer
+    }
+
+    /// Uploads a mesh to device local memory through staging buffers.
+    /// Uploads a mesh, packing `indices` into 16 bits when `vertices` are
+    /// few enough.
+    fn create_mesh(&self, vertices: &[Vertex], indices: &[u32]) -> Mesh {
+        let index_type = mesh::index_type(vertices.len());
+        let index_buffer = if index_type == vk::IndexType::UINT16 {
+            let indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+            self.create_device_local_buffer(
+                "index_buffer",
+                &indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            )
+        } else {
+            self.create_device_local_buffer(
+                "index_buffer",
+                indices,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            )
+        };
+        // The whole mesh in one draw
+        let draw_commands = [vk::DrawIndexedIndirectCommand {
+            index_count: indices.len() as u32,
+            instance_count: 1,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        }];
+        let vertex_usage = match self.device_addresses {
+            Some(_) => vk::BufferUsageFlags::VERTEX_BUFFER | device_address::USAGE,
+            None => vk::BufferUsageFlags::VERTEX_BUFFER,
+        };
+        let vertex_buffer =
+            self.create_device_local_buffer("vertex_buffer", vertices, vertex_usage);
+        let vertex_address = self
+            .device_addresses
+            .as_ref()
+            .map(|addresses| addresses.of(vertex_buffer.buffer));
+        Mesh {
+            vertex_buffer,
+            vertex_address,
+            index_buffer,
+            index_type,
+            index_count: indices.len() as u32,
+            indirect_buffer: self.create_indirect_buffer(&draw_commands),
+            draw_count: draw_commands.len() as u32,
+        }
+    }
+
+    /// Packs `draw_commands` into a buffer for `cmd_draw_indexed_indirect`,
+    /// so the GPU reads the draw parameters instead of the CPU passing them.
+    fn create_indirect_buffer(&self, draw_commands: &[vk::DrawIndexedIndirectCommand]) -> Buffer {
+        // More than one draw per call needs the multiDrawIndirect feature,
+        // which we don't enable, and is capped by maxDrawIndirectCount
+        assert_eq!(
+            draw_commands.len(),
+            1,
+            "Multi-draw indirect isn't supported"
+        );
+        self.create_device_local_buffer(
+            "indirect_buffer",
+            draw_commands,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+        )
+    }
+
+    fn create_device_local_buffer<T: Copy>(
+        &self,
+        name: &str,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Buffer {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let staging_buffer = Self::create_buffer(
+            &self.core.device,
+            &self.allocator,
+            "staging_buffer",
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = staging_buffer
+            .mapped_ptr()
+            .expect("Staging Buffer memory isn't host visible!");
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.cast::<T>().as_ptr(), data.len());
+        }
+        let buffer = Self::create_buffer(
+            &self.core.device,
+            &self.allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST | usage,
+            MemoryLocation::GpuOnly,
+        );
+        // Waits for the copy, so the staging buffer can go right away
+        self.copy_buffer(staging_buffer.buffer, buffer.buffer, size);
+        buffer
+    }
+
+    /// Uploads `faces` into a cube compatible image with a layer per face,
+    /// ready to be sampled.
+    fn create_cubemap(&self, faces: &CubemapFaces) -> Image {
+        let device = &self.core.device;
+        let format = faces.format;
+        let staging_buffer = Self::create_buffer(
+            device,
+            &self.allocator,
+            "cubemap_staging_buffer",
+            faces.pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = staging_buffer
+            .mapped_ptr()
+            .expect("Staging Buffer memory isn't host visible!");
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                faces.pixels.as_ptr(),
+                mapped.cast::<u8>().as_ptr(),
+                faces.pixels.len(),
+            );
+        }
+
+        let image_info = vk::ImageCreateInfo {
+            flags: vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: faces.size,
+                height: faces.size,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 6,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+        let image = unsafe {
+            device
+                .create_image(&image_info, None)
+                .expect("Failed to create cubemap Image!")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let allocation = allocator::allocate(
+            &self.allocator,
+            "cubemap",
+            requirements,
+            MemoryLocation::GpuOnly,
+            false,
+        );
+        unsafe {
+            device
+                .bind_image_memory(image, allocator::memory(&allocation), allocation.offset())
+                .expect("Failed to bind cubemap Image memory!");
+        }
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 6,
+        };
+        // Recorded on the graphics queue, so the image needs no ownership
+        // transfer before it's sampled there.
+        let command_buffer = self.begin_single_time_commands(self.command_pool);
+        let to_transfer_dst = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range,
+            ..Default::default()
+        };
+        // The faces are tightly packed one after the other, which is just
+        // how a copy of all layers at once expects them.
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 6,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: image_info.extent,
+        };
+        let to_shader_read = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..to_transfer_dst
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_dst],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_shader_read],
+            );
+        }
+        self.end_single_time_commands(self.command_pool, self.graphics_queue, command_buffer);
+
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type: vk::ImageViewType::CUBE,
+            format,
+            subresource_range,
+            ..Default::default()
+        };
+        let view = unsafe {
+            device
+                .create_image_view(&view_info, None)
+                .expect("Failed to create cubemap Image View!")
+        };
+        Image::new(
+            device,
+            &self.allocator,
+            image,
+            view,
+            ImageMemory::Allocation(allocation),
+        )
+    }
+
+    /// Creates an image for a `TextureArray`, with every layer ready to be
+    /// sampled so the descriptor set is valid before they're filled in.
+    fn create_texture_image(
+        &self,
+        name: &str,
+        width: u32,
+        height: u32,
+        layer_count: u32,
+        view_type: vk::ImageViewType,
+    ) -> Image {
+        let device = &self.core.device;
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format: texture_array::FORMAT,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: layer_count,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+        let image = unsafe {
+            device
+                .create_image(&image_info, None)
+                .expect("Failed to create texture array Image!")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let allocation = allocator::allocate(
+            &self.allocator,
+            name,
+            requirements,
+            MemoryLocation::GpuOnly,
+            false,
+        );
+        unsafe {
+            device
+                .bind_image_memory(image, allocator::memory(&allocation), allocation.offset())
+                .expect("Failed to bind texture array Image memory!");
+        }
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count,
+        };
+        let command_buffer = self.begin_single_time_commands(self.command_pool);
+        let to_shader_read = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range,
+            ..Default::default()
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_shader_read],
+            );
+        }
+        self.end_single_time_commands(self.command_pool, self.graphics_queue, command_buffer);
+
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type,
+            format: texture_array::FORMAT,
+            subresource_range,
+            ..Default::default()
+        };
+        let view = unsafe {
+            device
+                .create_image_view(&view_info, None)
+                .expect("Failed to create texture array Image View!")
+        };
+        self.core.set_debug_name(image, name);
+        Image::new(
+            device,
+            &self.allocator,
+            image,
+            view,
+            ImageMemory::Allocation(allocation),
+        )
+    }
+
+    /// Copies `width` by `height` `pixels` into `layer` of a texture image.
+    /// Waits for the copy, so frames in flight never sample a half written
+    /// layer.
+    fn upload_texture_layer(
+        &self,
+        image: vk::Image,
+        layer: u32,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) {
+        let device = &self.core.device;
+        let staging_buffer = Self::create_buffer(
+            device,
+            &self.allocator,
+            "texture_staging_buffer",
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = staging_buffer
+            .mapped_ptr()
+            .expect("Staging Buffer memory isn't host visible!");
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                pixels.as_ptr(),
+                mapped.cast::<u8>().as_ptr(),
+                pixels.len(),
+            );
+        }
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: layer,
+            layer_count: 1,
+        };
+        let command_buffer = self.begin_single_time_commands(self.command_pool);
+        // Nothing samples a layer before it's added, so its old contents can
+        // be discarded
+        let to_transfer_dst = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range,
+            ..Default::default()
+        };
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: layer,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        };
+        let to_shader_read = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ,
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..to_transfer_dst
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_dst],
+            );
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_shader_read],
+            );
+        }
+        self.end_single_time_commands(self.command_pool, self.graphics_queue, command_buffer);
+    }
+
+    fn create_uniform_buffers(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+    ) -> Vec<UniformBuffer> {
+        let size = std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize;
+        let mut uniform_buffers = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
+            let buffer = Self::create_buffer(
+                device,
+                allocator,
+                "uniform_buffer",
+                size,
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                MemoryLocation::CpuToGpu,
+            );
+            let mapped = buffer
+                .mapped_ptr()
+                .expect("Uniform Buffer memory isn't host visible!")
+                .cast()
+                .as_ptr();
+            uniform_buffers.push(UniformBuffer { buffer, mapped });
+        }
+        uniform_buffers
+    }
+
+    fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                descriptor_count: MAX_FRAMES_IN_FLIGHT as u32,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: MAX_FRAMES_IN_FLIGHT as u32,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create Descriptor Pool!")
+        }
+    }
+
+    fn create_descriptor_sets(
+        device: &ash::Device,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        uniform_buffers: &[UniformBuffer],
+        objects: &Objects,
+    ) -> Vec<vk::DescriptorSet> {
+        let set_layouts = [descriptor_set_layout; MAX_FRAMES_IN_FLIGHT];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate Descriptor Sets!")
+        };
+
+        for (&descriptor_set, uniform_buffer) in descriptor_sets.iter().zip(uniform_buffers) {
+            let buffer_infos = [vk::DescriptorBufferInfo {
+                buffer: uniform_buffer.buffer.buffer,
+                offset: 0,
+                range: std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize,
+            }];
+            let descriptor_writes = [vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 0,
+                dst_array_element: 0,
+                descriptor_count: buffer_infos.len() as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                p_buffer_info: buffer_infos.as_ptr(),
+                ..Default::default()
+            }];
+            unsafe {
+                device.update_descriptor_sets(&descriptor_writes, &[]);
+            }
+        }
+        Self::write_object_descriptors(device, &descriptor_sets, objects);
+        descriptor_sets
+    }
+
+    /// Points binding 1 of each frame's descriptor set at that frame's
+    /// object buffer. None of the sets may be in use.
+    fn write_object_descriptors(
+        device: &ash::Device,
+        descriptor_sets: &[vk::DescriptorSet],
+        objects: &Objects,
+    ) {
+        for (frame, &descriptor_set) in descriptor_sets.iter().enumerate() {
+            // The dynamic offset picks the object within the range
+            let buffer_infos = [vk::DescriptorBufferInfo {
+                buffer: objects.buffer(frame),
+                offset: 0,
+                range: Objects::range(),
+            }];
+            let descriptor_writes = [vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 1,
+                dst_array_element: 0,
+                descriptor_count: buffer_infos.len() as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                p_buffer_info: buffer_infos.as_ptr(),
+                ..Default::default()
+            }];
+            unsafe {
+                device.update_descriptor_sets(&descriptor_writes, &[]);
+            }
+        }
+    }
+
+    /// The projection of the camera, or of each eye with `--multiview`.
+    fn projection(&self) -> Mat4 {
+        let mut extent = self.swapchain.extent;
+        // Each eye gets half the window, see present.frag
+        if self.multiview {
+            extent.width /= 2;
+        }
+        projection_matrix(extent)
+    }
+
+    fn update_uniform_buffer(&self) {
+        let proj = self.projection();
+        let [left_view, right_view] = self.camera.eye_view_matrices(EYE_SEPARATION);
+        let ubo = UniformBufferObject {
+            model: Mat4::from_rotation_z(self.angle).to_cols_array_2d(),
+            view: self.camera.view_matrix().to_cols_array_2d(),
+            proj: proj.to_cols_array_2d(),
+            eye_views: [left_view.to_cols_array_2d(), right_view.to_cols_array_2d()],
+            eye: self.camera.eye().extend(1.0).to_array(),
+            light: self.light,
+        };
+        unsafe {
+            self.uniform_buffers[self.current_frame].mapped.write(ubo);
+        }
+        self.objects.write(self.current_frame);
+    }
+
+    /// Leaves the scene objects outside the camera's view out of the
+    /// frame's draws, or outside both eyes' views with `--multiview`.
+    /// They're moved by the scene rotation and object 0 of the dynamic
+    /// uniform buffer, like textured.vert moves them.
+    fn cull_scene(&mut self) {
+        if self.scene.is_empty() {
+            return;
+        }
+        let proj = self.projection();
+        let views = if self.multiview {
+            self.camera.eye_view_matrices(EYE_SEPARATION).to_vec()
+        } else {
+            vec![self.camera.view_matrix()]
+        };
+        let frustums: Vec<Frustum> = views
+            .into_iter()
+            .map(|view| Frustum::from_view_proj(proj * view))
+            .collect();
+        let parent = Mat4::from_rotation_z(self.angle)
+            * self
+                .objects
+                .transforms()
+                .first()
+                .copied()
+                .unwrap_or_default();
+        if self.scene.cull(&frustums, parent) {
+            self.scene_commands.invalidate();
+        }
+    }
+
+    /// Makes room for a query per scene object, and notes which objects
+    /// this frame queries, the ones `cull_scene` kept.
+    fn prepare_occlusion_queries(&mut self) {
+        let occlusion = match &mut self.occlusion {
+            Some(occlusion) => occlusion,
+            None => return,
+        };
+        if occlusion.reserve(self.scene.len()) {
+            self.scene_commands.invalidate();
+        }
+        let ids = self
+            .scene
+            .occlusion_boxes()
+            .iter()
+            .map(|occlusion_box| occlusion_box.id)
+            .collect();
+        occlusion.set_queried(self.current_frame, ids);
+    }
+
+    /// Requests a screenshot of the next presented frame, saved as PNG.
+    fn save_screenshot(&mut self, path: PathBuf) {
+        if !self
+            .swapchain
+            .image_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            println!("Screenshots are not supported, swapchain images can't be copied");
+            return;
+        }
+        match self.swapchain.format {
+            vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB => self.screenshot_request = Some(path),
+            format => println!("Screenshots are not supported for {:?}", format),
+        }
+    }
+
+    fn begin_screenshot(&self, path: PathBuf) -> PendingScreenshot {
+        let extent = self.swapchain.extent;
+        let size = vk::DeviceSize::from(extent.width * extent.height * 4);
+        let buffer = Self::create_buffer(
+            &self.core.device,
+            &self.allocator,
+            "screenshot",
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        );
+        PendingScreenshot {
+            path,
+            buffer,
+            extent,
+            format: self.swapchain.format,
+        }
+    }
+
+    /// Copies the swapchain image into the screenshot buffer. The render
+    /// pass leaves the image in PRESENT_SRC_KHR, so it's transitioned for
+    /// the copy and back again.
+    fn record_screenshot_copy(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        screenshot: &PendingScreenshot,
+    ) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_transfer_barrier = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            old_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range,
+            ..Default::default()
+        };
+        let to_present_barrier = vk::ImageMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_READ,
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..to_transfer_barrier
+        };
+        let host_read_barrier = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: screenshot.buffer.buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            // Tightly packed
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width: screenshot.extent.width,
+                height: screenshot.extent.height,
+                depth: 1,
+            },
+        };
+
+        unsafe {
+            self.core.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer_barrier],
+            );
+            self.core.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                screenshot.buffer.buffer,
+                &[region],
+            );
+            self.core.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_read_barrier],
+                &[to_present_barrier],
+            );
+        }
+    }
+
+    /// Waits for the frame that copied the screenshot and writes it to disk.
+    fn finish_screenshot(&self, screenshot: PendingScreenshot, submission: Submission) {
+        let extent = screenshot.extent;
+        let size = (extent.width * extent.height * 4) as usize;
+        self.frame_sync
+            .wait(submission)
+            .expect("Failed to wait for the screenshot frame!");
+        // The allocation may be bigger than what we asked for
+        let mut pixels = screenshot
+            .buffer
+            .mapped_slice()
+            .expect("Screenshot memory isn't host visible!")[..size]
+            .to_vec();
+
+        let is_bgra = matches!(
+            screenshot.format,
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+        );
+        for pixel in pixels.chunks_exact_mut(4) {
+            if is_bgra {
+                pixel.swap(0, 2);
+            }
+            // The swapchain is composited as opaque, whatever alpha we wrote
+            pixel[3] = 255;
+        }
+
+        match image::save_buffer(
+            &screenshot.path,
+            &pixels,
+            extent.width,
+            extent.height,
+            image::ColorType::Rgba8,
+        ) {
+            Ok(()) => println!("Saved screenshot to {}", screenshot.path.display()),
+            Err(error) => eprintln!(
+                "Failed to save screenshot to {}: {}",
+                screenshot.path.display(),
+                error
+            ),
+        }
+    }
+
+    /// Begins the render pass on the framebuffer of swapchain image
+    /// `image_index`, clearing all of its attachments.
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer, image_index: usize) {
+        let mut clear_values = vec![
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.clear_color,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        // A G-buffer position with an alpha of 0 tells the lighting pass
+        // there's nothing there
+        if self.deferred.is_some() {
+            clear_values.extend(GBUFFER_FORMATS.iter().map(|_| vk::ClearValue {
+                color: vk::ClearColorValue { float32: [0.0; 4] },
+            }));
+        }
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass.expect("No render pass to begin"),
+            framebuffer: self.swapchain.framebuffers[image_index],
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.swapchain.extent,
+            },
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            self.core.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+            );
+        }
+    }
+
+    /// The secondary command buffers with the frame slot's scene, recorded
+    /// again if anything they're recorded with changed. That's everything
+    /// in the first subpass: the renderer's draws, then the scene objects
+    /// split across `--threads` lanes, and without `--deferred` what
+    /// `record_unlit` draws in the last one.
+    fn record_scene_commands(&self, pipelines: &FramePipelines) -> &[vk::CommandBuffer] {
+        let extent = self.swapchain.extent;
+        let key = SceneRecordingKey {
+            pipelines: *pipelines,
+            extent: (extent.width, extent.height),
+        };
+        let color_formats;
+        let rendering_inheritance;
+        let inheritance = match self.render_pass {
+            // Any framebuffer will do, so one recording serves all
+            // swapchain images
+            Some(render_pass) => vk::CommandBufferInheritanceInfo {
+                render_pass,
+                subpass: 0,
+                ..Default::default()
+            },
+            None => {
+                color_formats = [self.swapchain.format];
+                rendering_inheritance = vk::CommandBufferInheritanceRenderingInfo {
+                    color_attachment_count: color_formats.len() as u32,
+                    p_color_attachment_formats: color_formats.as_ptr(),
+                    depth_attachment_format: self.swapchain.depth_format,
+                    stencil_attachment_format: self.swapchain.stencil_format(),
+                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
+                    ..Default::default()
+                };
+                vk::CommandBufferInheritanceInfo {
+                    p_next: &rendering_inheritance as *const _ as *const c_void,
+                    ..Default::default()
+                }
+            }
+        };
+        let command_buffers = self.scene_commands.command_buffers(self.current_frame);
+        if !self
+            .scene_commands
+            .begin(self.current_frame, key, &inheritance)
+        {
+            return command_buffers;
+        }
+        let (&renderer_lane, lanes) = command_buffers.split_first().unwrap();
+        let (&unlit_lane, object_lanes) = lanes.split_last().unwrap();
+
+        let device = &self.core.device;
+        let frame = self.frame_context(renderer_lane, pipelines.scene);
+        let draws = self.scene.draws();
+        let object_bindings = pipelines
+            .objects
+            .map(|pipelines| ObjectBindings::new(pipelines, self.textures.as_ref()));
+        // The last lanes get fewer objects, or none at all
+        let chunk_size = draws.len().div_ceil(object_lanes.len()).max(1);
+        let mut chunks = draws.chunks(chunk_size);
+        let first_chunk = chunks.next().unwrap_or(&[]);
+        let record_objects = |command_buffer: vk::CommandBuffer, draws: &[ObjectDraw]| {
+            set_viewport(device, command_buffer, extent);
+            if let Some(object_bindings) = object_bindings {
+                let frame = FrameContext {
+                    command_buffer,
+                    ..frame
+                };
+                scene::record(&frame, object_bindings, draws);
+            }
+            end_secondary(device, command_buffer);
+        };
+        // The renderer and the unlit draws need the app, which can't be
+        // shared with other threads, so they're recorded on this one along
+        // with the first lane of objects
+        std::thread::scope(|scope| {
+            for &command_buffer in &object_lanes[1..] {
+                let draws = chunks.next().unwrap_or(&[]);
+                scope.spawn(move || record_objects(command_buffer, draws));
+            }
+            set_viewport(device, renderer_lane, extent);
+            self.renderer.record(&frame);
+            end_secondary(device, renderer_lane);
+
+            record_objects(object_lanes[0], first_chunk);
+
+            if self.deferred.is_none() {
+                set_viewport(device, unlit_lane, extent);
+                self.record_unlit(unlit_lane, pipelines);
+            }
+            // After everything that could hide the objects
+            if let (Some(occlusion), Some(occlusion_box_pipeline)) =
+                (&self.occlusion, pipelines.occlusion_box)
+            {
+                occlusion.record(
+                    &self.frame_context(unlit_lane, occlusion_box_pipeline),
+                    occlusion_box_pipeline,
+                    self.current_frame,
+                    &self.scene.occlusion_boxes(),
+                );
+            }
+            end_secondary(device, unlit_lane);
+        });
+        command_buffers
+    }
+
+    /// What the renderer records `command_buffer` with, drawing with
+    /// `pipeline`.
+    fn frame_context(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+    ) -> FrameContext<'_> {
+        FrameContext {
+            device: &self.core.device,
+            command_buffer,
+            framebuffer: vk::Framebuffer::null(),
+            extent: self.swapchain.extent,
+            pipeline,
+            pipeline_layout: self.pipeline_layout,
+            descriptor_set: self.descriptor_sets[self.current_frame],
+            instance_buffer: match &self.compute_wave {
+                Some(compute_wave) => compute_wave.instance_buffer(self.current_frame),
+                None => self.instance_buffer.buffer,
+            },
+            instance_count: self.instance_count,
+            object_count: self.objects.count() as u32,
+            object_stride: self.objects.stride as u32,
+            mesh: self.mesh.as_ref().map(Mesh::buffers),
+        }
+    }
+
+    /// Draws the grid, the outline, the particles and the skybox, which
+    /// are never lit.
+    fn record_unlit(&self, command_buffer: vk::CommandBuffer, pipelines: &FramePipelines) {
+        if let Some(grid_pipeline) = pipelines.grid {
+            self.record_grid(command_buffer, grid_pipeline);
+        }
+        if let Some(outline_pipeline) = pipelines.outline {
+            self.record_outline(command_buffer, outline_pipeline);
+        }
+        if let (Some(particles), Some(particles_pipeline)) = (&self.particles, pipelines.particles)
+        {
+            particles.draw(
+                command_buffer,
+                particles_pipeline,
+                self.pipeline_layout,
+                self.descriptor_sets[self.current_frame],
+            );
+        }
+        if let (Some(skybox), Some(skybox_pipeline)) = (&self.skybox, pipelines.skybox) {
+            skybox.record(
+                command_buffer,
+                skybox_pipeline,
+                self.descriptor_sets[self.current_frame],
+            );
+        }
+    }
+
+    fn record_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        screenshot: Option<&PendingScreenshot>,
+        pipelines: &FramePipelines,
+    ) {
+        let begin_info = vk::CommandBufferBeginInfo::default();
+        unsafe {
+            self.core
+                .device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("Failed to begin recording Command Buffer!");
+        }
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.write_start(command_buffer, self.current_frame);
+        }
+        if let Some(occlusion) = &self.occlusion {
+            occlusion.reset(command_buffer, self.current_frame);
+        }
+
+        if let (Some(compute_wave), None) = (&self.compute_wave, &self.async_compute) {
+            self.dispatch_compute(command_buffer, compute_wave);
+        }
+        if let Some(particles) = &self.particles {
+            self.core
+                .begin_debug_label(command_buffer, "Particles", [1.0, 0.8, 0.4, 1.0]);
+            particles.record(command_buffer);
+            self.core.end_debug_label(command_buffer);
+        }
+        #[cfg(feature = "raytracing")]
+        let ray_traced = self.record_ray_tracing(command_buffer, image_index);
+        #[cfg(not(feature = "raytracing"))]
+        let ray_traced = false;
+        if !ray_traced {
+            self.record_main_pass(command_buffer, image_index, pipelines);
+        }
+        #[cfg(feature = "gui")]
+        if let Some(egui) = &self.egui {
+            self.core
+                .begin_debug_label(command_buffer, "egui Pass", [0.4, 1.0, 0.6, 1.0]);
+            egui.record(
+                command_buffer,
+                self.current_frame,
+                image_index,
+                self.swapchain.extent,
+            );
+            self.core.end_debug_label(command_buffer);
+        }
+        #[cfg(feature = "imgui")]
+        if let Some(imgui) = &self.imgui {
+            self.core
+                .begin_debug_label(command_buffer, "ImGui Pass", [0.4, 1.0, 0.6, 1.0]);
+            imgui.record(
+                command_buffer,
+                self.current_frame,
+                image_index,
+                self.swapchain.extent,
+            );
+            self.core.end_debug_label(command_buffer);
+        }
+        if let Some(screenshot) = screenshot {
+            self.record_screenshot_copy(
+                command_buffer,
+                self.swapchain.images[image_index],
+                screenshot,
+            );
+        }
+        if let Some(gpu_timer) = &self.gpu_timer {
+            gpu_timer.write_end(command_buffer, self.current_frame);
+        }
+        unsafe {
+            self.core
+                .device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record Command Buffer!");
+        }
+    }
+
+    /// Draws the scene onto swapchain image `image_index`, through the
+    /// offscreen image with `--offscreen`.
+    fn record_main_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        pipelines: &FramePipelines,
+    ) {
+        self.core
+            .begin_debug_label(command_buffer, "Main Pass", [0.2, 0.6, 1.0, 1.0]);
+        match &self.dynamic_rendering {
+            Some(dynamic_rendering) => dynamic_rendering.begin(
+                command_buffer,
+                &self.swapchain,
+                image_index,
+                self.clear_color,
+            ),
+            None => self.begin_render_pass(command_buffer, image_index),
+        }
+        let scene_commands = self.record_scene_commands(pipelines);
+        unsafe {
+            self.core
+                .device
+                .cmd_execute_commands(command_buffer, scene_commands);
+        }
+        if let (Some(deferred), Some(lighting_pipeline)) = (&self.deferred, pipelines.lighting) {
+            unsafe {
+                self.core
+                    .device
+                    .cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+            }
+            // Secondary command buffers leave the dynamic state undefined
+            set_viewport(&self.core.device, command_buffer, self.swapchain.extent);
+            deferred.record(command_buffer, lighting_pipeline);
+            self.record_unlit(command_buffer, pipelines);
+        }
+        match &self.dynamic_rendering {
+            Some(dynamic_rendering) => {
+                dynamic_rendering.end(command_buffer, &self.swapchain, image_index)
+            }
+            None => unsafe {
+                self.core.device.cmd_end_render_pass(command_buffer);
+            },
+        }
+        self.core.end_debug_label(command_buffer);
+        if let (Some(offscreen), Some(present_pipeline)) = (&self.offscreen, pipelines.present) {
+            self.core
+                .begin_debug_label(command_buffer, "Present Pass", [0.6, 0.4, 1.0, 1.0]);
+            offscreen.record(
+                command_buffer,
+                image_index,
+                self.swapchain.extent,
+                present_pipeline,
+                self.post_effect,
+                self.tone_mapping,
+            );
+            self.core.end_debug_label(command_buffer);
+        }
+    }
+
+    /// Traces the demo's mesh onto swapchain image `image_index` instead of
+    /// drawing the scene, with `--raytracing`. False without it.
+    #[cfg(feature = "raytracing")]
+    fn record_ray_tracing(&self, command_buffer: vk::CommandBuffer, image_index: usize) -> bool {
+        let ray_tracer = match &self.ray_tracer {
+            Some(ray_tracer) => ray_tracer,
+            None => return false,
+        };
+        self.core
+            .begin_debug_label(command_buffer, "Ray Tracing", [1.0, 0.4, 0.4, 1.0]);
+        // Moved like cube.vert moves the mesh
+        let model = Mat4::from_rotation_z(self.angle)
+            * self
+                .objects
+                .transforms()
+                .first()
+                .copied()
+                .unwrap_or_default();
+        ray_tracer.record(
+            command_buffer,
+            self.swapchain.images[image_index],
+            self.projection(),
+            self.camera.view_matrix() * model,
+        );
+        self.core.end_debug_label(command_buffer);
+        true
+    }
+
+    /// Runs `compute_wave` on the frame's command buffer, when there's no
+    /// async compute queue. The graphics queue can run compute shaders as
+    /// well, the draws just can't start before the dispatch is done.
+    fn dispatch_compute(&self, command_buffer: vk::CommandBuffer, compute_wave: &ComputeWave) {
+        self.core
+            .begin_debug_label(command_buffer, "Compute Wave", [1.0, 0.6, 0.2, 1.0]);
+        compute_wave.record(command_buffer, self.current_frame);
+        let write_before_read = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: compute_wave.instance_buffer(self.current_frame),
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        unsafe {
+            self.core.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[write_before_read],
+                &[],
+            );
+        }
+        self.core.end_debug_label(command_buffer);
+    }
+
+    /// Draws the floor grid after the model, so the depth test skips the
+    /// parts of it the model covers.
+    fn record_grid(&self, command_buffer: vk::CommandBuffer, grid_pipeline: vk::Pipeline) {
+        let device = &self.core.device;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                grid_pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_sets[self.current_frame]],
+                // The grid doesn't use the object transforms
+                &[0],
+            );
+            device.cmd_draw(command_buffer, 6, 1, 0, 0);
+        }
+    }
+
+    /// Has the renderer draw the model again with `outline_pipeline`, scaled
+    /// up, which only covers what the model didn't mark in the stencil
+    /// buffer.
+    fn record_outline(&self, command_buffer: vk::CommandBuffer, outline_pipeline: vk::Pipeline) {
+        // Right after the model matrix the renderer pushes, which the
+        // pipeline layout makes room for
+        unsafe {
+            self.core.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                std::mem::size_of::<PushConstants>() as u32,
+                &OUTLINE_SCALE.to_ne_bytes(),
+            );
+        }
+        self.renderer
+            .record(&self.frame_context(command_buffer, outline_pipeline));
+    }
+
+    /// Replaces what gets drawn each frame, [`TriangleRenderer`] by default.
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        self.renderer = renderer;
+        self.scene_commands.invalidate();
+    }
+
+    /// Has the renderer record its draws again for the next frames, for
+    /// when they changed. The app does this itself when the scene changes.
+    pub fn invalidate_recording(&self) {
+        self.scene_commands.invalidate();
+    }
+
+    /// Makes room for `count` objects, each with its own model matrix in
+    /// a dynamic uniform buffer. New ones start out unmoved, and there's a
+    /// single one to begin with. Renderers draw them through
+    /// [`FrameContext::bind_descriptor_set`].
+    pub fn register_objects(&mut self, count: usize) {
+        unsafe {
+            self.core
+                .device
+                .device_wait_idle()
+                .expect("Failed to wait device idle!");
+        }
+        if self.objects.resize(count) {
+            Self::write_object_descriptors(&self.core.device, &self.descriptor_sets, &self.objects);
+        }
+        self.scene_commands.invalidate();
+    }
+
+    /// Places object `index` of those from `register_objects` in the scene.
+    pub fn set_object_transform(&mut self, index: usize, transform: Mat4) {
+        self.objects.set_transform(index, transform);
+    }
+
+    /// Replaces the scene's light, starting with the next frame.
+    pub fn set_light(&mut self, light: Light) {
+        self.light = light;
+    }
+
+    /// Adds a mesh to the scene at `transform`, drawn from the next frame on
+    /// until `remove_object`.
+    pub fn add_object(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        transform: Mat4,
+    ) -> ObjectId {
+        let mesh = self.create_mesh(vertices, indices);
+        self.scene_commands.invalidate();
+        self.scene
+            .add(vertices.to_vec(), indices.to_vec(), transform, mesh)
+    }
+
+    /// Places object `id` at `transform`. Returns false if it was removed.
+    pub fn move_object(&mut self, id: ObjectId, transform: Mat4) -> bool {
+        // The transform is a push constant
+        self.scene_commands.invalidate();
+        self.scene.set_transform(id, transform)
+    }
+
+    /// Adds a `width` by `height` RGBA8 texture for `set_object_texture`.
+    /// Without descriptor indexing it's scaled to the size all textures
+    /// share. Fails if the pixels don't add up or there's no room left for
+    /// another texture.
+    pub fn add_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+    ) -> Result<TextureId, String> {
+        let pixels = texture_array::texture_pixels(width, height, rgba)?;
+        if self.textures.is_none() {
+            let storage = match self.bindless_capacity {
+                Some(capacity) => Storage::Bindless {
+                    images: vec![],
+                    capacity,
+                },
+                None => Storage::Layers(Box::new(self.create_texture_image(
+                    "texture_array",
+                    texture_array::LAYER_SIZE,
+                    texture_array::LAYER_SIZE,
+                    texture_array::MAX_LAYERS,
+                    vk::ImageViewType::TYPE_2D_ARRAY,
+                ))),
+            };
+            self.textures = Some(TextureArray::new(
+                &self.core.device,
+                storage,
+                self.descriptor_set_layout,
+            ));
+        }
+        let textures = self.textures.as_ref().unwrap();
+        let index = textures.textures.len() as u32;
+        if index == textures.capacity() {
+            return Err(format!("All {} textures are taken", textures.capacity()));
+        }
+        let pixels = match &textures.storage {
+            Storage::Bindless { .. } => {
+                let image = self.create_texture_image(
+                    &format!("texture[{}]", index),
+                    width,
+                    height,
+                    1,
+                    vk::ImageViewType::TYPE_2D,
+                );
+                self.upload_texture_layer(image.image, 0, width, height, &pixels);
+                self.textures.as_mut().unwrap().push_image(index, image);
+                pixels
+            }
+            Storage::Layers(image) => {
+                let pixels = texture_array::fit_to_layer(pixels);
+                self.upload_texture_layer(
+                    image.image,
+                    index,
+                    texture_array::LAYER_SIZE,
+                    texture_array::LAYER_SIZE,
+                    &pixels,
+                );
+                pixels
+            }
+        };
+        let (width, height) = pixels.dimensions();
+        self.textures
+            .as_mut()
+            .unwrap()
+            .textures
+            .push((width, height, pixels.into_raw()));
+        Ok(TextureId(index))
+    }
+
+    /// Draws object `id` with `texture` instead of its vertex colors, or
+    /// with the vertex colors again for `None`. Returns false if it was
+    /// removed.
+    pub fn set_object_texture(&mut self, id: ObjectId, texture: Option<TextureId>) -> bool {
+        self.scene_commands.invalidate();
+        self.scene.set_texture(id, texture)
+    }
+
+    /// Draws the indices of object `id` as `topology`, instead of the
+    /// triangles objects are added as. Returns false if it was removed.
+    pub fn set_object_topology(&mut self, id: ObjectId, topology: Topology) -> bool {
+        self.scene_commands.invalidate();
+        self.scene.set_topology(id, topology)
+    }
+
+    /// Has object `id` mark the stencil buffer, or only draw where it's
+    /// marked or where it isn't. Objects are drawn in the order they were
+    /// added, after whatever the renderer draws, so those that mark it have
+    /// to come before those it masks. Does nothing to the draws of a device
+    /// whose depth format has no stencil. Returns false if it was removed.
+    pub fn set_object_stencil(&mut self, id: ObjectId, stencil: Stencil) -> bool {
+        self.scene_commands.invalidate();
+        self.scene.set_stencil(id, stencil)
+    }
+
+    /// Stops drawing object `id`. Its buffers are freed once the frames in
+    /// flight are done with them. Returns false if it was already removed.
+    pub fn remove_object(&mut self, id: ObjectId) -> bool {
+        match self.scene.remove(id) {
+            Some(object) => {
+                self.scene_commands.invalidate();
+                self.deletion_queue
+                    .push(self.current_frame, move || drop(object));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the per-frame update hook, a place for animation and input logic
+    /// that doesn't want to touch the event loop. Does nothing by default.
+    pub fn set_on_update(&mut self, on_update: impl FnMut(Duration) + 'static) {
+        self.on_update = Box::new(on_update);
+    }
+
+    /// How many errors the validation layer reported so far, across all
+    /// apps in this process. Tests check this is still zero after a run.
+    pub fn validation_error_count() -> usize {
+        VALIDATION_ERROR_COUNT.load(Ordering::SeqCst)
+    }
+
+    /// Renders and presents a single frame. Returns whether it was
+    /// presented, which it isn't when the swapchain is out of date.
+    pub fn draw_frame(&mut self) -> Result<bool, DeviceLost> {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.angle = (self.angle + self.speed * dt) % std::f32::consts::TAU;
+        if let Some(compute_wave) = &mut self.compute_wave {
+            compute_wave.advance(dt);
+        }
+        if let Some(particles) = &mut self.particles {
+            particles.advance(dt);
+        }
+
+        self.frame_sync
+            .wait(self.frame_sync.last_submission(self.current_frame))
+            .map_err(|error| device_lost_or_panic(error, "Failed to wait for the frame!"))?;
+        self.deletion_queue.flush(self.current_frame);
+        // This frame slot's previous submission is done, so its timestamps
+        // are available without stalling
+        if let Some(gpu_timer) = &self.gpu_timer {
+            if let Some(gpu_time_ms) = gpu_timer.read_ms(self.current_frame) {
+                self.gpu_time_ms = Some(gpu_time_ms);
+            }
+        }
+        if let Some(occluded) = self
+            .occlusion
+            .as_ref()
+            .and_then(|occlusion| occlusion.read_occluded(self.current_frame))
+        {
+            if self.scene.set_occluded(occluded) {
+                self.scene_commands.invalidate();
+            }
+        }
+        // Its buffers for this frame slot are free again as well
+        #[cfg(feature = "gui")]
+        self.update_egui(dt);
+        #[cfg(feature = "imgui")]
+        self.update_imgui(dt);
+
+        // Before acquiring an image, so there's nothing to undo if the frame
+        // can't be drawn
+        let pipelines = match self.usable_frame_pipelines() {
+            Some(pipelines) => pipelines,
+            None => return Ok(false),
+        };
+
+        let result = unsafe {
+            self.swapchain.loader.acquire_next_image(
+                self.swapchain.swapchain,
+                u64::MAX,
+                self.image_available_semaphores[self.current_frame],
+                vk::Fence::null(),
+            )
+        };
+        let image_index = match result {
+            Ok((image_index, _is_suboptimal)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.recreate_swapchain();
+                return Ok(false);
+            }
+            Err(error) => {
+                return Err(device_lost_or_panic(
+                    error,
+                    "Failed to acquire Swapchain Image!",
+                ))
+            }
+        };
+
+        // Sized off the swapchain's images, which recreate_swapchain keeps
+        // in step
+        debug_assert_eq!(self.images_in_flight.len(), self.swapchain.image_count());
+        // The image may still be rendered to by an older frame, that isn't
+        // necessarily the one we just waited for
+        if let Some(image_in_flight) = self.images_in_flight[image_index as usize] {
+            self.frame_sync
+                .wait(image_in_flight)
+                .map_err(|error| device_lost_or_panic(error, "Failed to wait for the frame!"))?;
+        }
+        // Only start a new submission once we know we're going to submit it,
+        // as that resets the fence
+        let submission = self.frame_sync.next_submission(self.current_frame);
+        self.images_in_flight[image_index as usize] = Some(submission);
+
+        let command_buffer = self.command_buffers[self.current_frame];
+        unsafe {
+            self.core
+                .device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .expect("Failed to reset Command Buffer!");
+        }
+        let screenshot = self
+            .screenshot_request
+            .take()
+            .map(|path| self.begin_screenshot(path));
+        self.update_uniform_buffer();
+        self.cull_scene();
+        self.prepare_occlusion_queries();
+        self.record_command_buffer(
+            command_buffer,
+            image_index as usize,
+            screenshot.as_ref(),
+            &pipelines,
+        );
+
+        let mut wait_semaphores = vec![self.image_available_semaphores[self.current_frame]];
+        let mut wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        if let (Some(compute_wave), Some(async_compute)) = (&self.compute_wave, &self.async_compute)
+        {
+            let compute_finished = async_compute
+                .submit(compute_wave, self.current_frame)
+                .map_err(|error| {
+                    device_lost_or_panic(error, "Failed to submit compute Command Buffer!")
+                })?;
+            wait_semaphores.push(compute_finished);
+            wait_stages.push(vk::PipelineStageFlags::VERTEX_INPUT);
+        }
+        let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo {
+            wait_semaphore_count: wait_semaphores.len() as u32,
+            p_wait_semaphores: wait_semaphores.as_ptr(),
+            p_wait_dst_stage_mask: wait_stages.as_ptr(),
+            command_buffer_count: command_buffers.len() as u32,
+            p_command_buffers: command_buffers.as_ptr(),
+            signal_semaphore_count: signal_semaphores.len() as u32,
+            p_signal_semaphores: signal_semaphores.as_ptr(),
+            ..Default::default()
+        };
+        self.frame_sync
+            .submit(self.graphics_queue, submission, submit_info)
+            .map_err(|error| {
+                device_lost_or_panic(error, "Failed to submit draw Command Buffer!")
+            })?;
+
+        let swapchains = [self.swapchain.swapchain];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR {
+            wait_semaphore_count: signal_semaphores.len() as u32,
+            p_wait_semaphores: signal_semaphores.as_ptr(),
+            swapchain_count: swapchains.len() as u32,
+            p_swapchains: swapchains.as_ptr(),
+            p_image_indices: image_indices.as_ptr(),
+            ..Default::default()
+        };
+        let result = unsafe {
+            self.swapchain
+                .loader
+                .queue_present(self.present_queue, &present_info)
+        };
+        let (presented, is_out_of_date) = match result {
+            Ok(is_suboptimal) => (true, is_suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => (false, true),
+            Err(error) => {
+                return Err(device_lost_or_panic(
+                    error,
+                    "Failed to present Swapchain Image!",
+                ))
+            }
+        };
+        if let Some(screenshot) = screenshot {
+            self.finish_screenshot(screenshot, submission);
+        }
+        if is_out_of_date || self.framebuffer_resized {
+            self.framebuffer_resized = false;
+            self.recreate_swapchain();
+        }
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        Ok(presented)
+    }
+
+    /// Replaces everything created from the lost device with a new device
+    /// and objects created from it. The instance, surface and what the user
+    /// sees of the app, like the camera and renderer, are kept.
+    fn recreate_device(mut self, window: &Window) -> Result<VulkanApp, String> {
+        let instance_core = Rc::clone(&self.core.instance);
+        let mut cli_args = self.cli_args.clone();
+        cli_args.present_mode = self.preferred_present_mode;
+        cli_args.msaa_samples = self.pipelines.samples().as_raw();
+        cli_args.sample_shading = self.pipelines.sample_shading();
+        cli_args.alpha_blending = self.alpha_blending;
+        let renderer = std::mem::replace(&mut self.renderer, Box::new(TriangleRenderer));
+        let on_update = std::mem::replace(&mut self.on_update, Box::new(|_| {}));
+        let object_transforms = self.objects.transforms().to_vec();
+        // Only the CPU side of them, their buffers go with the old device
+        let scene_objects: Vec<_> = self
+            .scene
+            .drain()
+            .into_iter()
+            .map(|object| {
+                let RenderObject {
+                    id,
+                    vertices,
+                    indices,
+                    transform,
+                    texture,
+                    topology,
+                    stencil,
+                    bounds,
+                    ..
+                } = object;
+                (
+                    id, vertices, indices, transform, texture, topology, stencil, bounds,
+                )
+            })
+            .collect();
+        let textures = match &mut self.textures {
+            Some(textures) => std::mem::take(&mut textures.textures),
+            None => vec![],
+        };
+        let (camera, angle, speed, wireframe, brightness) = (
+            self.camera,
+            self.angle,
+            self.speed,
+            self.wireframe,
+            self.pipelines.brightness(),
+        );
+        // The old device's objects have to go before the new ones are made
+        drop(self);
+
+        let mut app = Self::with_instance(instance_core, window, &cli_args)?;
+        app.renderer = renderer;
+        app.on_update = on_update;
+        app.register_objects(object_transforms.len());
+        for (index, &transform) in object_transforms.iter().enumerate() {
+            app.set_object_transform(index, transform);
+        }
+        // In the order they were added, so they keep their ids
+        for (width, height, pixels) in textures {
+            app.add_texture(width, height, &pixels)?;
+        }
+        for (id, vertices, indices, transform, texture, topology, stencil, bounds) in scene_objects
+        {
+            let mesh = app.create_mesh(&vertices, &indices);
+            app.scene.insert(RenderObject {
+                id,
+                vertices,
+                indices,
+                transform,
+                texture,
+                topology,
+                stencil,
+                mesh,
+                bounds,
+            });
+        }
+        app.camera = camera;
+        app.angle = angle;
+        app.speed = speed;
+        app.wireframe = wireframe && app.wireframe_supported;
+        if brightness != app.pipelines.brightness() {
+            match app.pipelines.rebuild(brightness) {
+                Ok(()) => app.name_pipelines(),
+                Err(error) => eprintln!("Failed to rebuild pipelines: {}", error),
+            }
+        }
+        Ok(app)
+    }
+
+    /// Rebuilds everything that depends on the surface size. The render pass
+    /// and pipeline are kept, since viewport and scissor are dynamic state.
+    fn recreate_swapchain(&mut self) {
+        unsafe {
+            self.core
+                .device
+                .device_wait_idle()
+                .expect("Failed to wait device idle!");
+        }
+        self.swapchain.recreate(
+            self.preferred_present_mode,
+            self.window_extent,
+            self.render_pass,
+        );
+        if let Some(deferred) = &self.deferred {
+            deferred.update(&self.swapchain.gbuffer_views());
+        }
+        if let Some(offscreen) = &mut self.offscreen {
+            offscreen.recreate(&self.swapchain);
+        }
+        #[cfg(feature = "gui")]
+        if let Some(egui) = &mut self.egui {
+            egui.recreate(&self.swapchain);
+        }
+        #[cfg(feature = "imgui")]
+        if let Some(imgui) = &mut self.imgui {
+            imgui.recreate(&self.swapchain);
+        }
+        #[cfg(feature = "raytracing")]
+        if let Some(ray_tracer) = &mut self.ray_tracer {
+            ray_tracer.recreate(self.swapchain.extent);
+        }
+        self.images_in_flight = vec![None; self.swapchain.image_count()];
+        self.name_swapchain_objects();
+    }
+
+    fn on_key_pressed(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::F1 => self.toggle_wireframe(),
+            VirtualKeyCode::F2 => self.cycle_present_mode(),
+            VirtualKeyCode::F3 => self.cycle_brightness(),
+            VirtualKeyCode::F4 => self.toggle_alpha_blending(),
+            VirtualKeyCode::F5 => self.cycle_post_effect(),
+            VirtualKeyCode::F6 => self.cycle_msaa(),
+            VirtualKeyCode::F7 => self.toggle_sample_shading(),
+            VirtualKeyCode::Equals | VirtualKeyCode::Add => self.adjust_tone_mapping(1, 0),
+            VirtualKeyCode::Minus | VirtualKeyCode::Subtract => self.adjust_tone_mapping(-1, 0),
+            VirtualKeyCode::RBracket => self.adjust_tone_mapping(0, 1),
+            VirtualKeyCode::LBracket => self.adjust_tone_mapping(0, -1),
+            VirtualKeyCode::F12 => {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                self.save_screenshot(PathBuf::from(format!("screenshot-{}.png", timestamp)));
+            }
+            _ => {}
+        }
+    }
+
+    /// Builds the acceleration structures of `--raytracing` from the demo's
+    /// mesh. `None` if the traced image can't be blitted onto the swapchain
+    /// images or the ray tracing shaders are broken, which isn't worth failing over
+    /// either, the mesh is drawn as usual then.
+    #[cfg(feature = "raytracing")]
+    fn create_ray_tracer(&self, vertices: &[Vertex], indices: &[u32]) -> Option<RayTracer> {
+        // Always there, ray tracing needs Vulkan 1.1
+        let properties2 = self.core.instance.properties2.as_ref()?;
+        let blit_features = unsafe {
+            self.core
+                .instance
+                .instance
+                .get_physical_device_format_properties(self.physical_device, self.swapchain.format)
+        }
+        .optimal_tiling_features;
+        if !self
+            .swapchain
+            .image_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_DST)
+            || !blit_features.contains(vk::FormatFeatureFlags::BLIT_DST)
+        {
+            println!("The swapchain images can't be blitted to, ignoring --raytracing");
+            return None;
+        }
+        let shader_dir = self.pipelines.shader_dir();
+        let code = match ["raytrace.rgen", "raytrace.rmiss", "raytrace.rchit"]
+            .map(|name| Self::load_shader_code(shader_dir, name))
+        {
+            [Ok(raygen), Ok(miss), Ok(closest_hit)] => [raygen, miss, closest_hit],
+            [Err(error), ..] | [_, Err(error), _] | [.., Err(error)] => {
+                eprintln!("Failed to load the ray tracing shaders: {}", error);
+                return None;
+            }
+        };
+        let usage = raytracing::mesh_usage() | device_address::USAGE;
+        let mesh = TracedMesh {
+            vertex_buffer: self.create_device_local_buffer("traced_vertex_buffer", vertices, usage),
+            vertex_count: vertices.len() as u32,
+            index_buffer: self.create_device_local_buffer("traced_index_buffer", indices, usage),
+            triangle_count: indices.len() as u32 / 3,
+        };
+        let command_buffer = self.begin_single_time_commands(self.command_pool);
+        let ray_tracer = RayTracer::new(
+            &self.core.instance.instance,
+            &self.core.device,
+            &self.allocator,
+            properties2,
+            self.physical_device,
+            self.pipeline_cache,
+            &code,
+            mesh,
+            self.swapchain.extent,
+            command_buffer,
+        );
+        // Even if that failed, which it does before recording anything
+        self.end_single_time_commands(self.command_pool, self.graphics_queue, command_buffer);
+        match ray_tracer {
+            Ok((ray_tracer, _scratch_buffer)) => Some(ray_tracer),
+            Err(error) => {
+                eprintln!("Failed to create the ray tracer: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Uploads the font atlas of a new imgui context, and creates the
+    /// overlay to draw it with. `None` if its shaders are broken, which
+    /// isn't worth failing over.
+    #[cfg(feature = "imgui")]
+    fn create_imgui_overlay(&self, hidpi_factor: f64) -> Option<ImguiOverlay> {
+        let mut context = imgui::Context::create();
+        // Nothing to remember between runs
+        context.set_ini_filename(None);
+        let fonts = context.fonts();
+        let texture = fonts.build_rgba32_texture();
+        let font_image = self.create_texture_image(
+            "imgui_font_image",
+            texture.width,
+            texture.height,
+            1,
+            vk::ImageViewType::TYPE_2D,
+        );
+        self.upload_texture_layer(
+            font_image.image,
+            0,
+            texture.width,
+            texture.height,
+            texture.data,
+        );
+        let overlay = ImguiOverlay::new(
+            &self.core.device,
+            &self.allocator,
+            &self.swapchain,
+            self.pipeline_cache,
+            self.pipelines.shader_dir(),
+            context,
+            font_image,
+        );
+        match overlay {
+            Ok(mut overlay) => {
+                overlay.set_hidpi_factor(hidpi_factor);
+                self.core
+                    .set_debug_name(overlay.render_pass(), "imgui_render_pass");
+                Some(overlay)
+            }
+            Err(error) => {
+                eprintln!("Failed to create the imgui overlay: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Lays out this frame's debug window, with a control for each key
+    /// `on_key_pressed` handles, and acts on the ones that were used.
+    #[cfg(feature = "imgui")]
+    fn update_imgui(&mut self, dt: f32) {
+        let imgui = match &mut self.imgui {
+            Some(imgui) => imgui,
+            None => return,
+        };
+        let mut wireframe = self.wireframe;
+        let mut alpha_blending = self.alpha_blending;
+        let present_mode = self.preferred_present_mode;
+        let brightness = self.pipelines.brightness();
+        let msaa_samples = self.pipelines.samples().as_raw();
+        let mut sample_shading = self.pipelines.sample_shading();
+        let offscreen = self.offscreen.is_some();
+        let post_effect = self.post_effect;
+        let tone_mapping = self.tone_mapping;
+        let gpu_time_ms = self.gpu_time_ms;
+        let mut pressed = vec![];
+        imgui.prepare(self.current_frame, self.swapchain.extent, dt, |ui| {
+            ui.window("Debug").always_auto_resize(true).build(|| {
+                ui.text(format!("{:.0} FPS", ui.io().framerate));
+                if let Some(gpu_time_ms) = gpu_time_ms {
+                    ui.text(format!("GPU {:.2} ms", gpu_time_ms));
+                }
+                if ui.checkbox("Wireframe (F1)", &mut wireframe) {
+                    pressed.push(VirtualKeyCode::F1);
+                }
+                if ui.button(format!("Present mode: {:?} (F2)", present_mode)) {
+                    pressed.push(VirtualKeyCode::F2);
+                }
+                if ui.button(format!("Brightness: {:.1} (F3)", brightness)) {
+                    pressed.push(VirtualKeyCode::F3);
+                }
+                if ui.checkbox("Alpha blending (F4)", &mut alpha_blending) {
+                    pressed.push(VirtualKeyCode::F4);
+                }
+                if ui.button(format!("MSAA: {}x (F6)", msaa_samples)) {
+                    pressed.push(VirtualKeyCode::F6);
+                }
+                if ui.checkbox("Sample shading (F7)", &mut sample_shading) {
+                    pressed.push(VirtualKeyCode::F7);
+                }
+                if offscreen {
+                    if ui.button(format!("Post effect: {:?} (F5)", post_effect)) {
+                        pressed.push(VirtualKeyCode::F5);
+                    }
+                    ui.text(format!("Exposure: {:.2}", tone_mapping.exposure));
+                    ui.same_line();
+                    if ui.button("-##exposure") {
+                        pressed.push(VirtualKeyCode::Minus);
+                    }
+                    ui.same_line();
+                    if ui.button("+##exposure") {
+                        pressed.push(VirtualKeyCode::Equals);
+                    }
+                    ui.text(format!("Gamma: {:.1}", tone_mapping.gamma));
+                    ui.same_line();
+                    if ui.button("-##gamma") {
+                        pressed.push(VirtualKeyCode::LBracket);
+                    }
+                    ui.same_line();
+                    if ui.button("+##gamma") {
+                        pressed.push(VirtualKeyCode::RBracket);
+                    }
+                }
+                if ui.button("Screenshot (F12)") {
+                    pressed.push(VirtualKeyCode::F12);
+                }
+            });
+        });
+        for key in pressed {
+            self.on_key_pressed(key);
+        }
+    }
+
+    /// Creates the egui overlay, whose font atlas is uploaded by the first
+    /// `update_egui`. `None` if its shaders are broken, which isn't worth
+    /// failing over.
+    #[cfg(feature = "gui")]
+    fn create_egui_overlay(&self, hidpi_factor: f64) -> Option<EguiOverlay> {
+        let overlay = EguiOverlay::new(
+            &self.core.device,
+            &self.allocator,
+            &self.swapchain,
+            self.pipeline_cache,
+            self.pipelines.shader_dir(),
+        );
+        match overlay {
+            Ok(mut overlay) => {
+                overlay.set_hidpi_factor(hidpi_factor);
+                self.core
+                    .set_debug_name(overlay.render_pass(), "egui_render_pass");
+                Some(overlay)
+            }
+            Err(error) => {
+                eprintln!("Failed to create the egui overlay: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Lays out this frame's debug window, with the frame rate, present
+    /// mode and camera, and acts on the toggles that were used. Uploads
+    /// the font atlas again if egui added glyphs to it.
+    #[cfg(feature = "gui")]
+    fn update_egui(&mut self, dt: f32) {
+        let egui = match &mut self.egui {
+            Some(egui) => egui,
+            None => return,
+        };
+        let mut wireframe = self.wireframe;
+        let present_mode = self.preferred_present_mode;
+        let mut vsync = matches!(
+            present_mode,
+            vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED
+        );
+        let camera = self.camera;
+        let gpu_time_ms = self.gpu_time_ms;
+        let mut pressed = vec![];
+        let mut vsync_changed = false;
+        egui.prepare(self.current_frame, self.swapchain.extent, dt, |ctx| {
+            egui::Window::new("Debug").resizable(false).show(ctx, |ui| {
+                let frame_time = ctx.input(|input| input.stable_dt);
+                ui.label(format!("{:.0} FPS", 1.0 / frame_time.max(f32::EPSILON)));
+                if let Some(gpu_time_ms) = gpu_time_ms {
+                    ui.label(format!("GPU {:.2} ms", gpu_time_ms));
+                }
+                ui.label(format!(
+                    "Camera: yaw {:.0}°, pitch {:.0}°, distance {:.2}",
+                    camera.yaw.to_degrees(),
+                    camera.pitch.to_degrees(),
+                    camera.distance
+                ));
+                if ui.checkbox(&mut wireframe, "Wireframe (F1)").changed() {
+                    pressed.push(VirtualKeyCode::F1);
+                }
+                vsync_changed = ui.checkbox(&mut vsync, "Vsync").changed();
+                if ui
+                    .button(format!("Present mode: {:?} (F2)", present_mode))
+                    .clicked()
+                {
+                    pressed.push(VirtualKeyCode::F2);
+                }
+            });
+        });
+        if let Some((width, height, pixels)) = egui.take_font_update() {
+            // Frames in flight may still be drawing with the old one
+            unsafe {
+                self.core
+                    .device
+                    .device_wait_idle()
+                    .expect("Failed to wait device idle!");
+            }
+            let font_image = self.create_texture_image(
+                "egui_font_image",
+                width,
+                height,
+                1,
+                vk::ImageViewType::TYPE_2D,
+            );
+            self.upload_texture_layer(font_image.image, 0, width, height, &pixels);
+            if let Some(egui) = &mut self.egui {
+                egui.set_font_image(font_image);
+            }
+        }
+        if vsync_changed {
+            self.set_vsync(vsync);
+        }
+        for key in pressed {
+            self.on_key_pressed(key);
+        }
+    }
+
+    /// Presents in FIFO order with `vsync`, or as soon as possible without.
+    #[cfg(feature = "gui")]
+    fn set_vsync(&mut self, vsync: bool) {
+        self.preferred_present_mode = if vsync {
+            vk::PresentModeKHR::FIFO
+        } else {
+            vk::PresentModeKHR::IMMEDIATE
+        };
+        self.recreate_swapchain();
+    }
+
+    fn toggle_wireframe(&mut self) {
+        if !self.wireframe_supported {
+            println!("Wireframe mode is not supported by this device");
+            return;
+        }
+        self.wireframe = !self.wireframe;
+        if let Err(error) = self.pipeline(self.scene_pipeline_key(), self.pipeline_layout) {
+            eprintln!("Failed to create pipeline: {}", error);
+            self.wireframe = !self.wireframe;
+        }
+    }
+
+    /// Switches to the next present mode in `PRESENT_MODE_CYCLE`, which
+    /// effectively toggles vsync.
+    fn cycle_present_mode(&mut self) {
+        let current_index = PRESENT_MODE_CYCLE
+            .iter()
+            .position(|&mode| mode == self.preferred_present_mode)
+            .unwrap_or(0);
+        self.preferred_present_mode =
+            PRESENT_MODE_CYCLE[(current_index + 1) % PRESENT_MODE_CYCLE.len()];
+        self.recreate_swapchain();
+    }
+
+    /// Switches to the next `PostEffect`. The present pass is recorded
+    /// every frame, so the next one has it.
+    fn cycle_post_effect(&mut self) {
+        if self.offscreen.is_none() {
+            println!("Post effects need --offscreen");
+            return;
+        }
+        self.post_effect = self.post_effect.next();
+        println!("Post effect: {:?}", self.post_effect);
+    }
+
+    /// Steps the exposure and the gamma of `tone_mapping` up or down, which
+    /// the next frame's present pass picks up.
+    fn adjust_tone_mapping(&mut self, exposure_steps: i32, gamma_steps: i32) {
+        if self.offscreen.is_none() {
+            println!("Exposure and gamma need --offscreen");
+            return;
+        }
+        self.tone_mapping.adjust_exposure(exposure_steps);
+        self.tone_mapping.adjust_gamma(gamma_steps);
+        println!(
+            "Exposure: {:.2}, gamma: {:.1}",
+            self.tone_mapping.exposure, self.tone_mapping.gamma
+        );
+    }
+
+    /// The color image the scene is rendered into with `--offscreen`, in
+    /// `SHADER_READ_ONLY_OPTIMAL` once a frame is done with it. It's an
+    /// array view, with a layer per eye with `--multiview`. It's created
+    /// again along with the swapchain, so it's only valid until the window
+    /// is resized.
+    pub fn offscreen_image_view(&self) -> Option<vk::ImageView> {
+        self.swapchain.offscreen_view()
+    }
+
+    /// How much of each memory heap is in use, by this process and others,
+    /// and how much of it we can use. `None` without `VK_EXT_memory_budget`.
+    pub fn memory_usage(&self) -> Option<Vec<HeapUsage>> {
+        if !self.memory_budget {
+            return None;
+        }
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2 {
+            p_next: &mut budget as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        let properties2 = self.core.instance.properties2.as_ref()?;
+        unsafe {
+            properties2.get_memory_properties2(self.physical_device, &mut properties);
+        }
+        let heaps = &properties.memory_properties.memory_heaps
+            [..properties.memory_properties.memory_heap_count as usize];
+        let usage = heaps
+            .iter()
+            .enumerate()
+            .map(|(index, heap)| HeapUsage {
+                device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                used: budget.heap_usage[index],
+                budget: budget.heap_budget[index],
+            })
+            .collect();
+        Some(usage)
+    }
+
+    /// Runs the event loop until the window is closed, then exits the process.
+    pub fn run(self, event_loop: EventLoop<()>, window: Window) -> ! {
+        let mut fps_counter = FpsCounter::new();
+        let mut benchmark = self.cli_args.benchmark_frames.map(Benchmark::new);
+        let uncapped = self.cli_args.uncapped;
+        // Frames left to present with --frames
+        let mut frames_left = self.cli_args.frames;
+        let mut last_update = Instant::now();
+        // Where the cursor was, while the left mouse button is held
+        let mut drag_position: Option<LogicalPosition> = None;
+        let mut cursor_position = LogicalPosition::new(0.0, 0.0);
+        // Nothing is drawn while the window is out of focus, to save power.
+        // Benchmarks and --frames keep going so they still finish, and
+        // --uncapped so nothing holds it back.
+        let mut paused = false;
+        // winit exits the process once the loop ends instead of returning, so
+        // this is our only chance to run Drop and clean up after ourselves.
+        let mut app_slot = Some(self);
+        let mut device_lost_count = 0;
+        // Ctrl+C would kill the process right away, leaking everything Drop
+        // destroys. The handler runs on a thread of its own, so it only
+        // wakes the loop up to exit, even while paused. If that gets stuck,
+        // a second Ctrl+C exits for real.
+        let proxy = event_loop.create_proxy();
+        let handler = ctrlc::set_handler(move || {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+            let _ = proxy.send_event(());
+        });
+        if let Err(error) = handler {
+            eprintln!("Failed to handle Ctrl+C, it won't clean up: {}", error);
+        }
+        event_loop.run(move |event, _, control_flow| {
+            if let Event::LoopDestroyed = event {
+                drop(app_slot.take());
+                if let Some(summary) = benchmark.as_ref().and_then(Benchmark::summary) {
+                    summary.print();
+                }
+                return;
+            }
+            let app = match app_slot.as_mut() {
+                Some(app) => app,
+                None => return,
+            };
+            #[cfg(feature = "gui")]
+            if let (Event::WindowEvent { event, .. }, Some(egui)) = (&event, &mut app.egui) {
+                // Clicks and scrolling over its windows are only for egui
+                if egui.handle_event(event) {
+                    return;
+                }
+            }
+            #[cfg(feature = "imgui")]
+            if let (Event::WindowEvent { event, .. }, Some(imgui)) = (&event, &mut app.imgui) {
+                // Clicks and scrolling over its windows are only for imgui
+                if imgui.handle_event(event) {
+                    return;
+                }
+            }
+            let mut device_lost = false;
+            match event {
+                Event::EventsCleared if paused => *control_flow = ControlFlow::Wait,
+                Event::EventsCleared => {
+                    // Update application here
+                    #[cfg(feature = "hot-reload")]
+                    {
+                        let shaders_changed = app
+                            .shader_watcher
+                            .as_ref()
+                            .is_some_and(ShaderWatcher::has_changes);
+                        if shaders_changed {
+                            app.reload_shaders();
+                        }
+                    }
+                    let now = Instant::now();
+                    (app.on_update)(now.duration_since(last_update));
+                    last_update = now;
+                    window.request_redraw();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::RedrawRequested,
+                    ..
+                } => {
+                    let size = window.inner_size();
+                    // Nothing to draw into while the window is minimized
+                    if !paused && size.width > 0.0 && size.height > 0.0 {
+                        let frame_start = Instant::now();
+                        let result = app.draw_frame();
+                        device_lost = result.is_err();
+                        // Only frames that made it to the screen count
+                        let presented = result.unwrap_or(false);
+                        if let Some(benchmark) = benchmark.as_mut().filter(|_| presented) {
+                            if benchmark.record(frame_start) {
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
+                        if let Some(frames_left) = frames_left.as_mut().filter(|_| presented) {
+                            // Redraws can still come in after asking to exit
+                            *frames_left = frames_left.saturating_sub(1);
+                            if *frames_left == 0 {
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
+                        if let Some((fps, frame_time_ms)) =
+                            presented.then(|| fps_counter.tick()).flatten()
+                        {
+                            let gpu_time = match app.gpu_time_ms {
+                                Some(gpu_time_ms) => format!(", GPU {:.2} ms", gpu_time_ms),
+                                None => String::new(),
+                            };
+                            let memory = match app.memory_usage() {
+                                Some(heaps) => {
+                                    let (used, budget) = heaps
+                                        .iter()
+                                        .filter(|heap| heap.device_local)
+                                        .fold((0, 0), |(used, budget), heap| {
+                                            (used + heap.used, budget + heap.budget)
+                                        });
+                                    format!(
+                                        ", VRAM {}/{} MiB",
+                                        used / (1024 * 1024),
+                                        budget / (1024 * 1024)
+                                    )
+                                }
+                                None => String::new(),
+                            };
+                            let objects = if app.scene.is_empty() {
+                                String::new()
+                            } else {
+                                let stats = app.scene.cull_stats();
+                                let occluded = if app.occlusion.is_some() {
+                                    format!(", {} occluded", stats.occluded)
+                                } else {
+                                    String::new()
+                                };
+                                format!(
+                                    ", {} drawn, {} culled{}",
+                                    stats.drawn, stats.culled, occluded
+                                )
+                            };
+                            window.set_title(&format!(
+                                "{} - {:.0} FPS ({:.2} ms{}){}{}",
+                                WINDOW_TITLE, fps, frame_time_ms, gpu_time, memory, objects
+                            ));
+                        }
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    app.window_extent = physical_extent(size, window.hidpi_factor());
+                    app.framebuffer_resized = true;
+                }
+                // Moving to a monitor with another scale factor changes the
+                // size in pixels, whether or not a Resized follows. The
+                // window may still report the old factor at this point.
+                Event::WindowEvent {
+                    event: WindowEvent::HiDpiFactorChanged(hidpi_factor),
+                    ..
+                } => {
+                    app.window_extent = physical_extent(window.inner_size(), hidpi_factor);
+                    app.framebuffer_resized = true;
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(key),
+                                    modifiers,
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => match key {
+                    VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                    VirtualKeyCode::F11 => {
+                        toggle_fullscreen(&window, modifiers.shift);
+                        app.window_extent = window_extent(&window);
+                        app.framebuffer_resized = true;
+                    }
+                    _ => app.on_key_pressed(key),
+                },
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::MouseInput {
+                            state,
+                            button: MouseButton::Left,
+                            ..
+                        },
+                    ..
+                } => {
+                    drag_position = match state {
+                        ElementState::Pressed => Some(cursor_position),
+                        ElementState::Released => None,
+                    };
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::CursorMoved { position, .. },
+                    ..
+                } => {
+                    cursor_position = position;
+                    if let Some(last_position) = drag_position {
+                        app.camera.rotate(
+                            (position.x - last_position.x) as f32,
+                            (position.y - last_position.y) as f32,
+                        );
+                        drag_position = Some(position);
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::MouseWheel { delta, .. },
+                    ..
+                } => {
+                    let steps = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        // Roughly one line's worth of pixels per step
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                    };
+                    app.camera.zoom(steps);
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(focused),
+                    ..
+                } if benchmark.is_none() && frames_left.is_none() && !uncapped => {
+                    paused = !focused;
+                    *control_flow = ControlFlow::Poll;
+                    if focused {
+                        // Pick up the animation where it stopped
+                        let now = Instant::now();
+                        app.last_frame = now;
+                        last_update = now;
+                    }
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                // Sent by the Ctrl+C handler
+                Event::UserEvent(()) => {
+                    println!("Interrupted, shutting down");
+                    *control_flow = ControlFlow::Exit;
+                }
+                _ => *control_flow = ControlFlow::Poll,
+            }
+            if device_lost {
+                device_lost_count += 1;
+                let app = app_slot.take().unwrap();
+                if device_lost_count > app.cli_args.device_lost_retries {
+                    eprintln!("Lost the device {} times, giving up", device_lost_count);
+                    drop(app);
+                    std::process::exit(1);
+                }
+                eprintln!("Lost the device, recreating it");
+                match app.recreate_device(&window) {
+                    Ok(app) => app_slot = Some(app),
+                    Err(message) => {
+                        eprintln!("{}", message);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Which of `extension_names` the Vulkan implementation doesn't have.
+    /// Extensions that come with layers aren't listed.
+    fn missing_instance_extensions(
+        entry: &ash::Entry,
+        extension_names: &[*const c_char],
+    ) -> Vec<String> {
+        let available_extensions = entry
+            .enumerate_instance_extension_properties(None)
+            .expect("Failed to enumerate Instance Extension Properties!");
+        extension_names
+            .iter()
+            .map(|&extension_name| unsafe { CStr::from_ptr(extension_name) })
+            .filter(|&extension_name| {
+                !available_extensions.iter().any(|extension| {
+                    extension_name == unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) }
+                })
+            })
+            .map(|extension_name| extension_name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn check_validation_layers_support(entry: &ash::Entry) -> bool {
+        let layer_properties = entry
+            .enumerate_instance_layer_properties()
+            .expect("Failed to enumerate Instance Layer Properties!");
+        if layer_properties.is_empty() {
+            eprintln!("No available layers.");
+            return false;
+        }
+        for required_layer_name in REQUIRED_VALIDATION_LAYERS.iter() {
+            if layer_properties
+                .iter()
+                .find(|property| *required_layer_name == vk_to_string(&property.layer_name))
+                .is_none()
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Sets the viewport and scissor to cover all of `extent`.
+fn set_viewport(device: &ash::Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [vk::Rect2D {
+        offset: vk::Offset2D { x: 0, y: 0 },
+        extent,
+    }];
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, &viewports);
+        device.cmd_set_scissor(command_buffer, 0, &scissors);
+    }
+}
+
+fn end_secondary(device: &ash::Device, command_buffer: vk::CommandBuffer) {
+    unsafe {
+        device
+            .end_command_buffer(command_buffer)
+            .expect("Failed to record secondary Command Buffer!");
+    }
+}
+
+fn populate_debug_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT {
+    vk::DebugUtilsMessengerCreateInfoEXT {
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            // | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+            // | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+            | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+            | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+            | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+        pfn_user_callback: Some(vulkan_debug_utils_callback),
+        ..Default::default()
+    }
+}
+
+impl Drop for VulkanApp {
+    fn drop(&mut self) {
+        unsafe {
+            // A lost device has nothing left to wait for, but its objects
+            // still have to be destroyed
+            match self.core.device.device_wait_idle() {
+                Ok(()) | Err(vk::Result::ERROR_DEVICE_LOST) => {}
+                Err(error) => panic!("Failed to wait device idle: {:?}", error),
+            }
+            for i in 0..MAX_FRAMES_IN_FLIGHT {
+                self.core
+                    .device
+                    .destroy_semaphore(self.image_available_semaphores[i], None);
+                self.core
+                    .device
+                    .destroy_semaphore(self.render_finished_semaphores[i], None);
+            }
+            self.core
+                .device
+                .destroy_command_pool(self.command_pool, None);
+            self.core
+                .device
+                .destroy_command_pool(self.transfer_command_pool, None);
+            self.core
+                .device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.save_pipeline_cache();
+            self.core
+                .device
+                .destroy_pipeline_cache(self.pipeline_cache, None);
+            self.core
+                .device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            self.core
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            if let Some(render_pass) = self.render_pass {
+                self.core.device.destroy_render_pass(render_pass, None);
+            }
+        }
+        // Pipelines, buffers and finally the device itself are destroyed as
+        // the fields get dropped
+    }
+}
+
+/// The most of `supported` sample counts up to `requested`. Every device
+/// supports a single sample.
+fn choose_sample_count(requested: u32, supported: vk::SampleCountFlags) -> vk::SampleCountFlags {
+    (0..=requested.max(1).ilog2())
+        .rev()
+        .map(|exponent| vk::SampleCountFlags::from_raw(1 << exponent))
+        .find(|&samples| supported.contains(samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+fn window_extent(window: &Window) -> vk::Extent2D {
+    physical_extent(window.inner_size(), window.hidpi_factor())
+}
+
+/// A perspective projection for images of `extent`, so the scene keeps its
+/// proportions whatever the window's shape.
+fn projection_matrix(extent: vk::Extent2D) -> Mat4 {
+    let aspect = extent.width as f32 / extent.height as f32;
+    let mut proj = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 10.0);
+    // glam follows OpenGL, where clip space Y points up. In Vulkan it
+    // points down, so flip it or everything renders upside down.
+    proj.y_axis.y *= -1.0;
+    proj
+}
+
+/// winit sizes windows in logical pixels, the swapchain wants physical ones.
+/// With fractional scale factors the round trip through logical pixels can
+/// land just under the physical size, so round instead of truncating.
+fn physical_extent(size: LogicalSize, hidpi_factor: f64) -> vk::Extent2D {
+    let size = size.to_physical(hidpi_factor);
+    vk::Extent2D {
+        width: size.width.round() as u32,
+        height: size.height.round() as u32,
+    }
+}
+
+/// Switches between a window and borderless fullscreen on the window's
+/// monitor, or exclusive fullscreen in the monitor's biggest and fastest
+/// video mode if `exclusive` is set. Exclusive fullscreen can skip the
+/// compositor, but changing modes makes the screen blink.
+fn toggle_fullscreen(window: &Window, exclusive: bool) {
+    if window.fullscreen().is_some() {
+        window.set_fullscreen(None);
+        return;
+    }
+    let monitor = window.current_monitor();
+    let video_mode = monitor.video_modes().max_by_key(|mode| {
+        let size = mode.size();
+        (
+            (size.width * size.height) as u64,
+            mode.refresh_rate(),
+            mode.bit_depth(),
+        )
+    });
+    let fullscreen = match video_mode {
+        Some(video_mode) if exclusive => Fullscreen::Exclusive(video_mode),
+        _ => Fullscreen::Borderless(monitor),
+    };
+    window.set_fullscreen(Some(fullscreen));
+}
+
+/// Creates the window the app renders into, sized as `cli_args` asks.
+pub fn init_window(event_loop: &EventLoop<()>, cli_args: &CliArgs) -> Window {
+    let window_builder = WindowBuilder::new()
+        .with_title(WINDOW_TITLE)
+        .with_inner_size((cli_args.width, cli_args.height).into());
+    #[cfg(unix)]
+    let window_builder = if cli_args.x11_dialog {
+        window_builder.with_x11_window_type(XWindowType::Dialog)
+    } else {
+        window_builder
+    };
+    window_builder
+        .build(event_loop)
+        .expect("Failed to create a window")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPIRV_MAGIC_NUMBER: u32 = 0x0723_0203;
+
+    #[test]
+    fn spv_to_words_handles_misaligned_buffer() {
+        let words = [SPIRV_MAGIC_NUMBER, 0x0001_0000, 42];
+        let mut bytes = vec![0u8];
+        for word in words.iter() {
+            bytes.extend_from_slice(&word.to_ne_bytes());
+        }
+        // Skip the padding byte, so the slice starts at an odd address
+        let misaligned = &bytes[1..];
+        assert_ne!(
+            misaligned.as_ptr() as usize % std::mem::align_of::<u32>(),
+            0
+        );
+
+        assert_eq!(spv_to_words(misaligned).unwrap(), words);
+    }
+
+    #[test]
+    fn spv_to_words_rejects_truncated_code() {
+        let mut bytes = SPIRV_MAGIC_NUMBER.to_ne_bytes().to_vec();
+        bytes.push(0);
+        assert!(spv_to_words(&bytes).is_err());
+    }
+
+    #[test]
+    fn parse_device_id_takes_hex_and_decimal() {
+        assert_eq!(CliArgs::parse_device_id("0x1f82"), Ok(0x1f82));
+        assert_eq!(CliArgs::parse_device_id("8066"), Ok(8066));
+        assert!(CliArgs::parse_device_id("0xg").is_err());
+        assert!(CliArgs::parse_device_id("").is_err());
+    }
+
+    #[test]
+    fn spv_to_words_rejects_missing_magic_number() {
+        assert!(spv_to_words(&[0u8; 8]).is_err());
+    }
+
+    #[test]
+    fn embedded_shaders_are_valid_spv() {
+        for &(name, code) in EMBEDDED_SHADERS
+            .iter()
+            .chain(EMBEDDED_RAYTRACING_SHADERS.iter())
+        {
+            assert!(spv_to_words(code).is_ok(), "{} is not valid SPIR-V", name);
+        }
+    }
+
+    #[test]
+    fn queue_family_indices_start_empty() {
+        let indices = QueueFamilyIndices::new();
+        assert_eq!(indices.graphics_family, None);
+        assert_eq!(indices.present_family, None);
+        assert_eq!(indices.transfer_family, None);
+        assert_eq!(indices.compute_family, None);
+        assert!(!indices.is_complete());
+    }
+
+    #[test]
+    fn queue_family_indices_need_graphics_and_present() {
+        let mut indices = QueueFamilyIndices::new();
+        indices.graphics_family = Some(0);
+        assert!(!indices.is_complete());
+
+        let mut indices = QueueFamilyIndices::new();
+        indices.present_family = Some(0);
+        assert!(!indices.is_complete());
+
+        indices.graphics_family = Some(1);
+        assert!(indices.is_complete());
+    }
+
+    fn queue_family(queue_flags: vk::QueueFlags) -> vk::QueueFamilyProperties {
+        vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn queue_family_indices_present_on_the_graphics_family() {
+        let all = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER;
+        // Like most discrete GPUs, with compute and transfer families after
+        // the graphics one that can present as well
+        let queue_families = [
+            queue_family(all),
+            queue_family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER),
+            queue_family(vk::QueueFlags::TRANSFER),
+        ];
+        let indices = QueueFamilyIndices::pick(&queue_families, &[true, true, false]);
+        assert_eq!(indices.graphics_family, Some(0));
+        assert_eq!(indices.present_family, Some(0));
+        assert_eq!(indices.transfer_family, Some(1));
+
+        // A family that can do both wins over an earlier graphics family
+        // that can't present
+        let queue_families = [queue_family(all), queue_family(all)];
+        let indices = QueueFamilyIndices::pick(&queue_families, &[false, true]);
+        assert_eq!(indices.graphics_family, Some(1));
+        assert_eq!(indices.present_family, Some(1));
+    }
+
+    #[test]
+    fn queue_family_indices_split_without_a_family_for_both() {
+        let queue_families = [
+            queue_family(vk::QueueFlags::GRAPHICS),
+            queue_family(vk::QueueFlags::COMPUTE),
+        ];
+        let indices = QueueFamilyIndices::pick(&queue_families, &[false, true]);
+        assert_eq!(indices.graphics_family, Some(0));
+        assert_eq!(indices.present_family, Some(1));
+    }
+
+    #[test]
+    fn queue_family_indices_complete_without_transfer_family() {
+        let mut indices = QueueFamilyIndices::new();
+        indices.graphics_family = Some(0);
+        indices.present_family = Some(0);
+        assert!(indices.is_complete());
+        assert_eq!(indices.upload_family(), Some(0));
+
+        indices.transfer_family = Some(2);
+        assert_eq!(indices.upload_family(), Some(2));
+    }
+
+    #[test]
+    fn instance_grid_is_centered() {
+        let instances = InstanceData::grid(4);
+        let offsets: Vec<[f32; 3]> = instances.iter().map(|instance| instance.offset).collect();
+        assert_eq!(
+            offsets,
+            [
+                [-0.6, -0.6, 0.0],
+                [0.6, -0.6, 0.0],
+                [-0.6, 0.6, 0.0],
+                [0.6, 0.6, 0.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn single_instance_is_untinted() {
+        let instances = InstanceData::grid(1);
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].offset, [0.0; 3]);
+        assert_eq!(instances[0].color, [1.0; 3]);
+    }
+
+    #[test]
+    fn physical_extent_survives_fractional_scaling() {
+        let hidpi_factor = 1.25;
+        let logical = winit::dpi::PhysicalSize::new(1001.0, 751.0).to_logical(hidpi_factor);
+        let extent = physical_extent(logical, hidpi_factor);
+        assert_eq!((extent.width, extent.height), (1001, 751));
+    }
+
+    #[test]
+    fn projection_keeps_proportions() {
+        let proj = projection_matrix(vk::Extent2D {
+            width: 1600,
+            height: 900,
+        });
+        // A square in front of the camera covers as many pixels across as up
+        let corner = proj.project_point3(Vec3::new(1.0, 1.0, -5.0));
+        assert!((corner.x * 1600.0 - corner.y.abs() * 900.0).abs() < 1e-3);
+        // Up in view space is up on screen, towards -Y in Vulkan
+        assert!(corner.y < 0.0);
+    }
+
+    #[test]
+    fn uniform_buffer_object_matches_std140() {
+        assert_eq!(std::mem::offset_of!(UniformBufferObject, eye_views), 192);
+        assert_eq!(std::mem::offset_of!(UniformBufferObject, eye), 320);
+        assert_eq!(std::mem::offset_of!(UniformBufferObject, light), 336);
+        assert_eq!(std::mem::offset_of!(Light, ambient), 28);
+        assert_eq!(std::mem::size_of::<UniformBufferObject>(), 368);
+    }
+
+    #[test]
+    fn eyes_are_either_side_of_the_camera() {
+        let camera = Camera::new();
+        let [left, right] = camera.eye_view_matrices(0.2);
+        let center = camera.view_matrix().transform_point3(Vec3::ZERO);
+        let left = left.transform_point3(Vec3::ZERO);
+        let right = right.transform_point3(Vec3::ZERO);
+        assert!((left.x - center.x - 0.1).abs() < 1e-5);
+        assert!((right.x - center.x + 0.1).abs() < 1e-5);
+        assert!((left.z - center.z).abs() < 1e-5);
+    }
+
+    #[test]
+    fn choose_sample_count_takes_requested_count() {
+        let supported = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4
+            | vk::SampleCountFlags::TYPE_8;
+        assert_eq!(
+            choose_sample_count(4, supported),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            choose_sample_count(1, supported),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+
+    #[test]
+    fn choose_sample_count_falls_back_to_next_lower() {
+        let supported = vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4;
+        assert_eq!(
+            choose_sample_count(8, supported),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            choose_sample_count(2, supported),
+            vk::SampleCountFlags::TYPE_1
+        );
+        assert_eq!(
+            choose_sample_count(8, vk::SampleCountFlags::empty()),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Indexed meshes with their own vertex data, as opposed to the triangle
+//! that shader.vert makes up from `gl_VertexIndex`.
+
+use ash::vk;
+
+use crate::renderer::MeshBuffers;
+use crate::resources::Buffer;
+
+/// A vertex as cube.vert reads it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// Unit length, for lighting.
+    pub normal: [f32; 3],
+    /// Texture coordinates, with (0, 0) at the top left of the texture.
+    pub uv: [f32; 2],
+}
+
+impl Vertex {
+    pub(crate) fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub(crate) fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: std::mem::size_of::<[f32; 3]>() as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: 2 * std::mem::size_of::<[f32; 3]>() as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: 3 * std::mem::size_of::<[f32; 3]>() as u32,
+            },
+        ]
+    }
+}
+
+/// A unit square in the XY plane, facing +Z.
+pub(crate) const QUAD_VERTICES: [Vertex; 4] = [
+    Vertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.0, 1.0],
+    },
+    Vertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [1.0, 1.0],
+    },
+    Vertex {
+        position: [0.5, 0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [1.0, 0.0],
+    },
+    Vertex {
+        position: [-0.5, 0.5, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.0, 0.0],
+    },
+];
+
+/// Two triangles sharing the diagonal from vertex 0 to 2, counter-clockwise
+/// seen from +Z.
+pub(crate) const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+/// A unit cube around the origin, with four vertices per face so each
+/// face gets its own normal. Face `f` is perpendicular to axis `f / 2`, on
+/// its positive side for odd `f`. Vertices are colored by which side of
+/// X, Y and Z they're on, so opposite corners are black and white. Each
+/// face is covered by a whole texture.
+pub const CUBE_VERTICES: [Vertex; 24] = {
+    let mut vertices = [Vertex {
+        position: [0.0; 3],
+        color: [0.0; 3],
+        normal: [0.0; 3],
+        uv: [0.0; 2],
+    }; 24];
+    let mut i = 0;
+    while i < 24 {
+        let face = i / 4;
+        let axis = face / 2;
+        let side = (face % 2) as f32;
+        // The corner, spanning the face along the next two axes
+        let mut corner = [0.0; 3];
+        corner[axis] = side;
+        corner[(axis + 1) % 3] = (i & 1) as f32;
+        corner[(axis + 2) % 3] = ((i >> 1) & 1) as f32;
+        let mut normal = [0.0; 3];
+        normal[axis] = 2.0 * side - 1.0;
+        vertices[i] = Vertex {
+            position: [corner[0] - 0.5, corner[1] - 0.5, corner[2] - 0.5],
+            color: corner,
+            normal,
+            uv: [(i & 1) as f32, ((i >> 1) & 1) as f32],
+        };
+        i += 1;
+    }
+    vertices
+};
+
+/// Two triangles per face, counter-clockwise when seen from outside the
+/// cube, so back face culling leaves only the near faces.
+pub const CUBE_INDICES: [u32; 36] = {
+    let mut indices = [0; 36];
+    let mut face = 0;
+    while face < 6 {
+        let base = 4 * face as u32;
+        // The next two axes make a right-handed basis with the face's axis,
+        // so this order is counter-clockwise on the positive side
+        let triangles = if face % 2 == 1 {
+            [0, 1, 2, 1, 3, 2]
+        } else {
+            [0, 2, 1, 1, 2, 3]
+        };
+        let mut i = 0;
+        while i < 6 {
+            indices[6 * face + i] = base + triangles[i];
+            i += 1;
+        }
+        face += 1;
+    }
+    indices
+};
+
+/// 16-bit indices take half the memory and bandwidth, but can only reach
+/// the first 65536 vertices.
+pub(crate) fn index_type(vertex_count: usize) -> vk::IndexType {
+    if vertex_count <= usize::from(u16::MAX) + 1 {
+        vk::IndexType::UINT16
+    } else {
+        vk::IndexType::UINT32
+    }
+}
+
+/// Vertex and index buffers in device local memory, along with the
+/// indirect draw commands that draw them.
+pub(crate) struct Mesh {
+    pub(crate) vertex_buffer: Buffer,
+    /// Where `vertex_buffer` is for pulled.vert, with `--vertex-pulling`.
+    pub(crate) vertex_address: Option<vk::DeviceAddress>,
+    pub(crate) index_buffer: Buffer,
+    /// What `index_buffer` holds, see `index_type`.
+    pub(crate) index_type: vk::IndexType,
+    pub(crate) index_count: u32,
+    /// `vk::DrawIndexedIndirectCommand`s, `draw_count` of them.
+    pub(crate) indirect_buffer: Buffer,
+    pub(crate) draw_count: u32,
+}
+
+impl Mesh {
+    pub(crate) fn buffers(&self) -> MeshBuffers {
+        MeshBuffers {
+            vertex_buffer: self.vertex_buffer.buffer,
+            vertex_address: self.vertex_address,
+            index_buffer: self.index_buffer.buffer,
+            index_type: self.index_type,
+            index_count: self.index_count,
+            indirect_buffer: self.indirect_buffer.buffer,
+            draw_count: self.draw_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn cube_faces_point_outwards() {
+        for triangle in CUBE_INDICES.chunks(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vec3::from(CUBE_VERTICES[triangle[i] as usize].position));
+            let normal = (b - a).cross(c - a);
+            let center = (a + b + c) / 3.0;
+            assert!(
+                normal.dot(center) > 0.0,
+                "Triangle {:?} faces inwards",
+                triangle
+            );
+        }
+    }
+
+    #[test]
+    fn quad_faces_up() {
+        for triangle in QUAD_INDICES.chunks(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vec3::from(QUAD_VERTICES[triangle[i] as usize].position));
+            assert!(
+                (b - a).cross(c - a).z > 0.0,
+                "Triangle {:?} faces down",
+                triangle
+            );
+        }
+    }
+
+    #[test]
+    fn index_type_fits_vertex_count() {
+        assert_eq!(index_type(CUBE_VERTICES.len()), vk::IndexType::UINT16);
+        assert_eq!(index_type(65536), vk::IndexType::UINT16);
+        assert_eq!(index_type(65537), vk::IndexType::UINT32);
+    }
+
+    #[test]
+    fn cube_normals_match_faces() {
+        for triangle in CUBE_INDICES.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| CUBE_VERTICES[triangle[i] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|vertex| Vec3::from(vertex.position));
+            let face_normal = (pb - pa).cross(pc - pa).normalize();
+            for vertex in [a, b, c] {
+                assert_eq!(Vec3::from(vertex.normal), face_normal);
+            }
+        }
+    }
+}
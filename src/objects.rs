@@ -0,0 +1,148 @@
+//! Per-object model matrices in a dynamic uniform buffer. All objects share
+//! the frame's descriptor set, and each draw picks its object by a dynamic
+//! offset into the buffer, instead of every object needing a set of its own.
+
+use ash::vk;
+use glam::Mat4;
+
+use crate::allocator::{MemoryLocation, SharedAllocator};
+use crate::resources::Buffer;
+use crate::{VulkanApp, MAX_FRAMES_IN_FLIGHT};
+
+/// One object's slice of the buffer, matching the `ObjectUniforms` block in
+/// the scene's vertex shaders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ObjectUniforms {
+    model: [[f32; 4]; 4],
+}
+
+/// Rounds `size` up to the next multiple of `min_alignment`, which Vulkan
+/// guarantees to be a power of two. Dynamic offsets have to be multiples of
+/// it.
+pub(crate) fn aligned_stride(
+    size: vk::DeviceSize,
+    min_alignment: vk::DeviceSize,
+) -> vk::DeviceSize {
+    (size + min_alignment - 1) & !(min_alignment - 1)
+}
+
+pub(crate) struct Objects {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    transforms: Vec<Mat4>,
+    /// Bytes between consecutive objects in the buffers.
+    pub(crate) stride: vk::DeviceSize,
+    /// How many objects the buffers have room for.
+    capacity: usize,
+    /// One per frame in flight, as they're rewritten every frame.
+    buffers: Vec<Buffer>,
+}
+
+impl Objects {
+    /// Starts out with a single object that isn't moved.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        min_uniform_buffer_offset_alignment: vk::DeviceSize,
+    ) -> Self {
+        let stride = aligned_stride(
+            std::mem::size_of::<ObjectUniforms>() as vk::DeviceSize,
+            min_uniform_buffer_offset_alignment,
+        );
+        let mut objects = Objects {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            transforms: vec![Mat4::IDENTITY],
+            stride,
+            capacity: 0,
+            buffers: vec![],
+        };
+        objects.create_buffers(1);
+        objects
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub(crate) fn transforms(&self) -> &[Mat4] {
+        &self.transforms
+    }
+
+    /// Grows or shrinks to `count` objects, new ones start out unmoved.
+    /// Returns true if the buffers had to be replaced to make room, in which
+    /// case nothing may be using the old ones anymore, and the descriptor
+    /// sets have to be pointed at the new ones.
+    pub(crate) fn resize(&mut self, count: usize) -> bool {
+        self.transforms.resize(count, Mat4::IDENTITY);
+        if count <= self.capacity {
+            return false;
+        }
+        self.create_buffers(count.next_power_of_two());
+        true
+    }
+
+    pub(crate) fn set_transform(&mut self, index: usize, transform: Mat4) {
+        self.transforms[index] = transform;
+    }
+
+    /// The buffer `frame` reads from, for binding 1 of its descriptor set.
+    pub(crate) fn buffer(&self, frame: usize) -> vk::Buffer {
+        self.buffers[frame].buffer
+    }
+
+    /// How much of the buffer one object sees, which is the range of the
+    /// descriptor.
+    pub(crate) fn range() -> vk::DeviceSize {
+        std::mem::size_of::<ObjectUniforms>() as vk::DeviceSize
+    }
+
+    /// Copies the transforms into the buffer of `frame`.
+    pub(crate) fn write(&self, frame: usize) {
+        let mapped = self.buffers[frame]
+            .mapped_ptr()
+            .expect("Object Buffer memory isn't host visible!")
+            .cast::<u8>()
+            .as_ptr();
+        for (index, transform) in self.transforms.iter().enumerate() {
+            let uniforms = ObjectUniforms {
+                model: transform.to_cols_array_2d(),
+            };
+            unsafe {
+                mapped
+                    .add(index * self.stride as usize)
+                    .cast::<ObjectUniforms>()
+                    .write(uniforms);
+            }
+        }
+    }
+
+    fn create_buffers(&mut self, capacity: usize) {
+        self.buffers = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                VulkanApp::create_buffer(
+                    &self.device,
+                    &self.allocator,
+                    "object_buffer",
+                    self.stride * capacity as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect();
+        self.capacity = capacity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stride_is_rounded_up_to_the_alignment() {
+        assert_eq!(aligned_stride(64, 256), 256);
+        assert_eq!(aligned_stride(64, 64), 64);
+        assert_eq!(aligned_stride(80, 64), 128);
+    }
+}
@@ -0,0 +1,209 @@
+//! `--occlusion-culling`: scene objects hidden behind others are skipped.
+//! After everything is drawn, the box around each object the frustum kept
+//! is drawn without writing anything, inside an occlusion query that
+//! counts the samples passing the depth test. Objects whose box had none
+//! aren't drawn the next time their frame slot comes around, though their
+//! box still is, to tell when they come into view again.
+//!
+//! Results only come in once a frame slot is done, so objects show up a
+//! frame or two after they were uncovered. Until a slot has results,
+//! everything is drawn.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use ash::vk;
+use glam::Mat4;
+
+use crate::renderer::FrameContext;
+use crate::scene::ObjectId;
+use crate::PushConstants;
+
+/// How many vertices occlusion_box.vert makes the box out of.
+const BOX_VERTEX_COUNT: u32 = 36;
+
+/// A scene object's box, as occlusion_box.vert draws it.
+#[derive(Clone, Copy)]
+pub(crate) struct OcclusionBox {
+    pub(crate) id: ObjectId,
+    /// Maps the unit cube onto the box, with the object's transform.
+    pub(crate) model: Mat4,
+}
+
+/// A query per object for each frame in flight.
+pub(crate) struct OcclusionQueries {
+    device: ash::Device,
+    /// Null until there's an object.
+    query_pool: vk::QueryPool,
+    /// How many queries each frame slot has.
+    capacity: u32,
+    /// Per frame slot, the objects its recording queries, in query order.
+    queried: Vec<Vec<ObjectId>>,
+    /// Whether a frame slot's queries were written and can be read back.
+    written: Vec<Cell<bool>>,
+}
+
+impl OcclusionQueries {
+    pub(crate) fn new(device: &ash::Device, frame_count: usize) -> Self {
+        OcclusionQueries {
+            device: device.clone(),
+            query_pool: vk::QueryPool::null(),
+            capacity: 0,
+            queried: vec![vec![]; frame_count],
+            written: vec![Cell::new(false); frame_count],
+        }
+    }
+
+    /// Makes room for `count` objects in each frame slot. Returns true if
+    /// the query pool had to be replaced, which waits for the device, and
+    /// then every recording with the old one has to be recorded again.
+    pub(crate) fn reserve(&mut self, count: usize) -> bool {
+        if count as u32 <= self.capacity {
+            return false;
+        }
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .expect("Failed to wait device idle!");
+            self.device.destroy_query_pool(self.query_pool, None);
+        }
+        // Room to grow, so adding objects one by one doesn't wait each time
+        self.capacity = (count as u32).next_power_of_two();
+        let create_info = vk::QueryPoolCreateInfo {
+            query_type: vk::QueryType::OCCLUSION,
+            query_count: self.capacity * self.queried.len() as u32,
+            ..Default::default()
+        };
+        self.query_pool = unsafe {
+            self.device
+                .create_query_pool(&create_info, None)
+                .expect("Failed to create occlusion Query Pool!")
+        };
+        for written in &self.written {
+            written.set(false);
+        }
+        true
+    }
+
+    /// Notes which objects the recording of frame slot `frame` queries,
+    /// which `record` has to be given the boxes of in the same order.
+    pub(crate) fn set_queried(&mut self, frame: usize, ids: Vec<ObjectId>) {
+        debug_assert!(ids.len() as u32 <= self.capacity);
+        self.queried[frame] = ids;
+    }
+
+    /// Resets the queries of frame slot `frame`. Has to go before the
+    /// render pass, since queries can't be reset inside one.
+    pub(crate) fn reset(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let query_count = self.queried[frame].len() as u32;
+        if query_count == 0 {
+            return;
+        }
+        unsafe {
+            self.device.cmd_reset_query_pool(
+                command_buffer,
+                self.query_pool,
+                frame as u32 * self.capacity,
+                query_count,
+            );
+        }
+        self.written[frame].set(true);
+    }
+
+    /// Draws `boxes` with `pipeline`, each in its own query of frame slot
+    /// `frame`. Goes after everything that can hide them.
+    pub(crate) fn record(
+        &self,
+        frame_context: &FrameContext,
+        pipeline: vk::Pipeline,
+        frame: usize,
+        boxes: &[OcclusionBox],
+    ) {
+        if boxes.is_empty() {
+            return;
+        }
+        let device = frame_context.device;
+        let command_buffer = frame_context.command_buffer;
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        }
+        frame_context.bind_descriptor_set(0);
+        for (index, occlusion_box) in boxes.iter().enumerate() {
+            let query = frame as u32 * self.capacity + index as u32;
+            let push_constants = PushConstants {
+                model: occlusion_box.model.to_cols_array_2d(),
+            };
+            unsafe {
+                device.cmd_push_constants(
+                    command_buffer,
+                    frame_context.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    0,
+                    push_constants.as_bytes(),
+                );
+                // Not precise, any sample at all is enough
+                device.cmd_begin_query(
+                    command_buffer,
+                    self.query_pool,
+                    query,
+                    vk::QueryControlFlags::empty(),
+                );
+                device.cmd_draw(command_buffer, BOX_VERTEX_COUNT, 1, 0, 0);
+                device.cmd_end_query(command_buffer, self.query_pool, query);
+            }
+        }
+    }
+
+    /// The objects whose box had no samples pass in the last submission for
+    /// frame slot `frame`, or `None` if there are no results. Only call this
+    /// once the frame is done.
+    pub(crate) fn read_occluded(&self, frame: usize) -> Option<HashSet<ObjectId>> {
+        let queried = &self.queried[frame];
+        if !self.written[frame].get() || queried.is_empty() {
+            return None;
+        }
+        let mut samples = vec![0u64; queried.len()];
+        unsafe {
+            self.device
+                .get_query_pool_results(
+                    self.query_pool,
+                    frame as u32 * self.capacity,
+                    queried.len() as u32,
+                    &mut samples,
+                    vk::QueryResultFlags::TYPE_64,
+                )
+                .ok()?;
+        }
+        Some(occluded(queried, &samples))
+    }
+}
+
+impl Drop for OcclusionQueries {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_query_pool(self.query_pool, None);
+        }
+    }
+}
+
+/// The objects of `queried` that had no samples pass.
+fn occluded<Id: Copy + Eq + Hash>(queried: &[Id], samples: &[u64]) -> HashSet<Id> {
+    queried
+        .iter()
+        .zip(samples)
+        .filter(|&(_, &samples)| samples == 0)
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occluded_objects_had_no_samples() {
+        let occluded = occluded(&[3, 5, 8, 9], &[12, 0, 1, 0]);
+        assert_eq!(occluded, HashSet::from([5, 9]));
+    }
+}
@@ -0,0 +1,491 @@
+//! `--offscreen`: the scene is rendered into a color image of its own
+//! rather than the swapchain image, which is left in
+//! `SHADER_READ_ONLY_OPTIMAL` to be sampled like any texture. A second
+//! render pass then draws it onto the swapchain image with present.frag,
+//! which is where post-processing goes, see `--post`.
+
+use ash::vk;
+
+use crate::swapchain::Swapchain;
+
+/// What the scene is drawn into. Half floats keep what's brighter than
+/// white, for post-processing to do something with.
+pub(crate) const OFFSCREEN_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// What present.frag does to the offscreen image on its way to the
+/// swapchain. The shader tells them apart by their discriminant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PostEffect {
+    /// Copies it as it is.
+    #[default]
+    None,
+    Grayscale,
+    /// Darkens the corners.
+    Vignette,
+    /// Brightens the midtones, by a gamma of 2.2.
+    Gamma,
+}
+
+impl PostEffect {
+    /// In the order F5 cycles through them.
+    pub(crate) const ALL: [PostEffect; 4] = [
+        PostEffect::None,
+        PostEffect::Grayscale,
+        PostEffect::Vignette,
+        PostEffect::Gamma,
+    ];
+
+    /// The effect after this one in `ALL`.
+    pub(crate) fn next(self) -> PostEffect {
+        let index = PostEffect::ALL.iter().position(|&effect| effect == self);
+        PostEffect::ALL[(index.unwrap() + 1) % PostEffect::ALL.len()]
+    }
+
+    fn strength(self) -> f32 {
+        match self {
+            PostEffect::None => 0.0,
+            PostEffect::Grayscale => 1.0,
+            PostEffect::Vignette => 0.6,
+            PostEffect::Gamma => 2.2,
+        }
+    }
+}
+
+/// Exposure and gamma, which present.frag applies before and after the
+/// `PostEffect`. Both start out leaving the image as it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ToneMapping {
+    /// What the color is multiplied by.
+    pub(crate) exposure: f32,
+    /// What the color is raised to the inverse of.
+    pub(crate) gamma: f32,
+}
+
+impl ToneMapping {
+    /// Eight times darker or brighter, past that there's little to see.
+    const EXPOSURE_RANGE: (f32, f32) = (0.125, 8.0);
+    /// Each step of `adjust_exposure` is a quarter stop.
+    const EXPOSURE_STEP: f32 = 1.189_207_1;
+    const GAMMA_RANGE: (f32, f32) = (0.5, 3.0);
+    const GAMMA_STEP: f32 = 0.1;
+
+    /// Brighter by `steps`, or darker if they're negative, within
+    /// `EXPOSURE_RANGE`.
+    pub(crate) fn adjust_exposure(&mut self, steps: i32) {
+        let (min, max) = Self::EXPOSURE_RANGE;
+        self.exposure = (self.exposure * Self::EXPOSURE_STEP.powi(steps)).clamp(min, max);
+    }
+
+    /// Up by `steps` tenths, or down if they're negative, within
+    /// `GAMMA_RANGE`.
+    pub(crate) fn adjust_gamma(&mut self, steps: i32) {
+        let (min, max) = Self::GAMMA_RANGE;
+        // Rounded, so repeated steps don't drift off the tenths
+        let gamma = self.gamma + Self::GAMMA_STEP * steps as f32;
+        self.gamma = ((gamma * 10.0).round() / 10.0).clamp(min, max);
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// The push constants of present.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PostConstants {
+    /// The `PostEffect` discriminant.
+    effect: u32,
+    strength: f32,
+    exposure: f32,
+    gamma: f32,
+}
+
+impl PostConstants {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// The present pass, and everything it draws with besides its pipeline.
+pub(crate) struct Offscreen {
+    device: ash::Device,
+    /// Draws into the swapchain image, which it leaves ready to present.
+    pub(crate) render_pass: vk::RenderPass,
+    /// One per swapchain image, created again along with them.
+    framebuffers: Vec<vk::Framebuffer>,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// The offscreen image. All frames share it, like the depth image.
+    descriptor_set: vk::DescriptorSet,
+    pub(crate) pipeline_layout: vk::PipelineLayout,
+}
+
+impl Offscreen {
+    /// `swapchain` has to have been created with `OFFSCREEN_FORMAT`.
+    pub(crate) fn new(device: &ash::Device, swapchain: &Swapchain) -> Self {
+        let render_pass = Self::create_render_pass(device, swapchain.format);
+
+        // The offscreen image is the same size as the swapchain, so it's
+        // only ever read texel for texel
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: 0.0,
+            ..Default::default()
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .expect("Failed to create offscreen Sampler!")
+        };
+
+        // The image and the sampler are bound separately, as present.frag
+        // can't use a combined image sampler
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create offscreen Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create offscreen Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate offscreen Descriptor Set!")[0]
+        };
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<PostConstants>() as u32,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create present pipeline layout")
+        };
+
+        let mut offscreen = Offscreen {
+            device: device.clone(),
+            render_pass,
+            framebuffers: vec![],
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+        };
+        offscreen.recreate(swapchain);
+        offscreen
+    }
+
+    /// A single subpass that covers the swapchain image, whatever was in it
+    /// before.
+    fn create_render_pass(device: &ash::Device, format: vk::Format) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..Default::default()
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let subpasses = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: color_attachment_refs.len() as u32,
+            p_color_attachments: color_attachment_refs.as_ptr(),
+            ..Default::default()
+        }];
+        // Like the main render pass, the layout transition waits for the
+        // stage draw_frame waits for the image in. The main render pass
+        // makes the offscreen image readable on its own.
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        }];
+        let render_pass_info = vk::RenderPassCreateInfo {
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: subpasses.len() as u32,
+            p_subpasses: subpasses.as_ptr(),
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_render_pass(&render_pass_info, None)
+                .expect("Failed to create present render pass")
+        }
+    }
+
+    /// Creates the framebuffers for the swapchain images and points the
+    /// descriptor set at the offscreen image, e.g. after the swapchain
+    /// created them again. The GPU can't be using either at the time.
+    pub(crate) fn recreate(&mut self, swapchain: &Swapchain) {
+        self.destroy_framebuffers();
+        self.framebuffers = swapchain
+            .image_views
+            .iter()
+            .map(|image_view| {
+                let framebuffer_create_info = vk::FramebufferCreateInfo {
+                    render_pass: self.render_pass,
+                    attachment_count: 1,
+                    p_attachments: image_view,
+                    width: swapchain.extent.width,
+                    height: swapchain.extent.height,
+                    layers: 1,
+                    ..Default::default()
+                };
+                unsafe {
+                    self.device
+                        .create_framebuffer(&framebuffer_create_info, None)
+                        .expect("Failed to create present Framebuffer!")
+                }
+            })
+            .collect();
+
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: swapchain
+                .offscreen_view()
+                .expect("The swapchain has no offscreen image"),
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let sampler_info = vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            ..Default::default()
+        };
+        let descriptor_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: 0,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                p_image_info: &image_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: 1,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                p_image_info: &sampler_info,
+                ..Default::default()
+            },
+        ];
+        unsafe {
+            self.device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+    }
+
+    /// The framebuffers, one per swapchain image, for debug names.
+    pub(crate) fn framebuffers(&self) -> &[vk::Framebuffer] {
+        &self.framebuffers
+    }
+
+    /// Draws the offscreen image onto swapchain image `image_index` with
+    /// `pipeline`, `effect` and `tone_mapping`, in a render pass of its own.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        extent: vk::Extent2D,
+        pipeline: vk::Pipeline,
+        effect: PostEffect,
+        tone_mapping: ToneMapping,
+    ) {
+        let constants = PostConstants {
+            effect: effect as u32,
+            strength: effect.strength(),
+            exposure: tone_mapping.exposure,
+            gamma: tone_mapping.gamma,
+        };
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: self.framebuffers[image_index],
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            },
+            ..Default::default()
+        };
+        unsafe {
+            self.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+        }
+        crate::set_viewport(&self.device, command_buffer, extent);
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                constants.as_bytes(),
+            );
+            // A triangle made up in fullscreen.vert
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            self.device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    fn destroy_framebuffers(&mut self) {
+        for framebuffer in self.framebuffers.drain(..) {
+            unsafe {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+        }
+    }
+}
+
+impl Drop for Offscreen {
+    fn drop(&mut self) {
+        self.destroy_framebuffers();
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_post_effect_wraps_around() {
+        assert_eq!(PostEffect::None.next(), PostEffect::Grayscale);
+        assert_eq!(PostEffect::Gamma.next(), PostEffect::None);
+    }
+
+    #[test]
+    fn exposure_is_clamped() {
+        let mut tone_mapping = ToneMapping::default();
+        tone_mapping.adjust_exposure(4);
+        assert!((tone_mapping.exposure - 2.0).abs() < 1e-5);
+        tone_mapping.adjust_exposure(100);
+        assert_eq!(tone_mapping.exposure, 8.0);
+        tone_mapping.adjust_exposure(-100);
+        assert_eq!(tone_mapping.exposure, 0.125);
+    }
+
+    #[test]
+    fn gamma_steps_by_tenths_within_range() {
+        let mut tone_mapping = ToneMapping::default();
+        for _ in 0..12 {
+            tone_mapping.adjust_gamma(1);
+        }
+        assert_eq!(tone_mapping.gamma, 2.2);
+        tone_mapping.adjust_gamma(-30);
+        assert_eq!(tone_mapping.gamma, 0.5);
+    }
+}
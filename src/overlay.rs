@@ -0,0 +1,803 @@
+//! What the `gui` and `imgui` overlays draw with: a render pass of its own
+//! on the swapchain image, over the finished frame, so they look the same
+//! whatever the scene was rendered with. Both hand over textured, colored
+//! triangles in screen coordinates, with a scissor for each draw, and bind
+//! a single texture, their font atlas.
+//!
+//! The backends turn their UI library's output into `OverlayUpload`s, and
+//! only differ in their vertex layout, index size and blending, see
+//! `OverlayPipelineInfo`.
+
+use std::ffi::{c_void, CString};
+use std::path::Path;
+
+use ash::vk;
+
+use crate::allocator::{MemoryLocation, SharedAllocator};
+use crate::resources::{Buffer, Pipeline};
+use crate::swapchain::Swapchain;
+use crate::VulkanApp;
+
+/// The push constants of the overlay vertex shaders, mapping the UI's
+/// coordinates to clip space.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Transform {
+    scale: [f32; 2],
+    translate: [f32; 2],
+}
+
+impl Transform {
+    /// Maps the rectangle at `position` of `size` onto the whole screen.
+    pub(crate) fn new(position: [f32; 2], size: [f32; 2]) -> Self {
+        let scale = [2.0 / size[0], 2.0 / size[1]];
+        Transform {
+            scale,
+            translate: [-1.0 - position[0] * scale[0], -1.0 - position[1] * scale[1]],
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// What tells the overlays' pipelines apart.
+pub(crate) struct OverlayPipelineInfo {
+    /// Of the shaders, `<name>.vert` and `<name>.frag`, and of the buffers.
+    pub(crate) name: &'static str,
+    pub(crate) vertex_stride: usize,
+    /// Where the position, the texture coordinates and the RGBA8 color are
+    /// in a vertex.
+    pub(crate) attribute_offsets: [usize; 3],
+    pub(crate) blend: vk::PipelineColorBlendAttachmentState,
+    pub(crate) index_type: vk::IndexType,
+}
+
+/// One draw command, out of a frame slot's buffers.
+struct Draw {
+    scissor: vk::Rect2D,
+    index_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+}
+
+/// What was uploaded for a frame slot. The buffers only ever grow, and are
+/// written again each time the slot comes around.
+#[derive(Default)]
+struct FrameGeometry {
+    vertex_buffer: Option<Buffer>,
+    index_buffer: Option<Buffer>,
+    draws: Vec<Draw>,
+    transform: Transform,
+}
+
+/// Writes a frame's vertices and indices, `V`s and `I`s, into a frame
+/// slot's buffers, one mesh after the other.
+pub(crate) struct OverlayUpload<'a, V, I> {
+    geometry: &'a mut FrameGeometry,
+    vertices: *mut V,
+    indices: *mut I,
+    vertex_count: usize,
+    index_count: usize,
+}
+
+impl<V: Copy, I: Copy> OverlayUpload<'_, V, I> {
+    /// Appends a mesh, returning where its vertices and indices start.
+    pub(crate) fn push_mesh(&mut self, vertices: &[V], indices: &[I]) -> (usize, usize) {
+        let start = (self.vertex_count, self.index_count);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                vertices.as_ptr(),
+                self.vertices.add(self.vertex_count),
+                vertices.len(),
+            );
+            std::ptr::copy_nonoverlapping(
+                indices.as_ptr(),
+                self.indices.add(self.index_count),
+                indices.len(),
+            );
+        }
+        self.vertex_count += vertices.len();
+        self.index_count += indices.len();
+        start
+    }
+
+    /// Draws `index_count` of the indices from `first_index` on, which
+    /// count from `vertex_offset`, within `scissor`.
+    pub(crate) fn push_draw(
+        &mut self,
+        scissor: vk::Rect2D,
+        index_count: usize,
+        first_index: usize,
+        vertex_offset: usize,
+    ) {
+        self.geometry.draws.push(Draw {
+            scissor,
+            index_count: index_count as u32,
+            first_index: first_index as u32,
+            vertex_offset: vertex_offset as i32,
+        });
+    }
+}
+
+/// The render pass, pipeline and buffers an overlay is drawn with.
+pub(crate) struct OverlayRenderer {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    name: &'static str,
+    index_type: vk::IndexType,
+    /// Draws over the swapchain image, which it leaves ready to present.
+    render_pass: vk::RenderPass,
+    /// One per swapchain image, created again along with them.
+    framebuffers: Vec<vk::Framebuffer>,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// The font atlas, the only texture.
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: Option<Pipeline>,
+    /// Per frame in flight.
+    frames: Vec<FrameGeometry>,
+}
+
+impl OverlayRenderer {
+    /// Creates what's needed to draw on the images of `swapchain`, with the
+    /// pipeline described by `info`. Fails if the shaders in `shader_dir`
+    /// are broken. There's no texture until `set_image`.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        swapchain: &Swapchain,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        info: &OverlayPipelineInfo,
+    ) -> Result<Self, String> {
+        let render_pass = Self::create_render_pass(device, swapchain.format);
+
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: 0.0,
+            ..Default::default()
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .expect("Failed to create overlay Sampler!")
+        };
+
+        // The image and the sampler are bound separately, as the fragment
+        // shaders declare them
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create overlay Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create overlay Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate overlay Descriptor Set!")[0]
+        };
+        // The image is written by set_image
+        let sampler_image_info = vk::DescriptorImageInfo {
+            sampler,
+            ..Default::default()
+        };
+        let descriptor_writes = [vk::WriteDescriptorSet {
+            dst_set: descriptor_set,
+            dst_binding: 1,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::SAMPLER,
+            p_image_info: &sampler_image_info,
+            ..Default::default()
+        }];
+        unsafe {
+            device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<Transform>() as u32,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create overlay pipeline layout")
+        };
+
+        // Destroys everything above if the pipeline can't be created
+        let mut renderer = OverlayRenderer {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            name: info.name,
+            index_type: info.index_type,
+            render_pass,
+            framebuffers: vec![],
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline: None,
+            frames: (0..crate::MAX_FRAMES_IN_FLIGHT)
+                .map(|_| FrameGeometry::default())
+                .collect(),
+        };
+        renderer.pipeline = Some(renderer.create_pipeline(pipeline_cache, shader_dir, info)?);
+        renderer.recreate(swapchain);
+        Ok(renderer)
+    }
+
+    /// A single subpass that draws over what's in the swapchain image.
+    fn create_render_pass(device: &ash::Device, format: vk::Format) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..Default::default()
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let subpasses = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: color_attachment_refs.len() as u32,
+            p_color_attachments: color_attachment_refs.as_ptr(),
+            ..Default::default()
+        }];
+        // Blends onto whatever the pass before wrote
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dependency_flags: vk::DependencyFlags::empty(),
+        }];
+        let render_pass_info = vk::RenderPassCreateInfo {
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+            subpass_count: subpasses.len() as u32,
+            p_subpasses: subpasses.as_ptr(),
+            dependency_count: dependencies.len() as u32,
+            p_dependencies: dependencies.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_render_pass(&render_pass_info, None)
+                .expect("Failed to create overlay render pass")
+        }
+    }
+
+    /// Blended triangles, without depth or culling.
+    fn create_pipeline(
+        &self,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        info: &OverlayPipelineInfo,
+    ) -> Result<Pipeline, String> {
+        let device = &self.device;
+        let vert_shader_code =
+            VulkanApp::load_shader_code(shader_dir, &format!("{}.vert", info.name))?;
+        let frag_shader_code =
+            VulkanApp::load_shader_code(shader_dir, &format!("{}.frag", info.name))?;
+        let vert_shader_module = VulkanApp::create_shader_module(device, &vert_shader_code)?;
+        let frag_shader_module = match VulkanApp::create_shader_module(device, &frag_shader_code) {
+            Ok(frag_shader_module) => frag_shader_module,
+            Err(error) => {
+                unsafe {
+                    device.destroy_shader_module(vert_shader_module, None);
+                }
+                return Err(error);
+            }
+        };
+
+        let shader_entrypoint = CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::VERTEX,
+                module: vert_shader_module,
+                p_name: shader_entrypoint.as_ptr(),
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                module: frag_shader_module,
+                p_name: shader_entrypoint.as_ptr(),
+                ..Default::default()
+            },
+        ];
+
+        let binding_descriptions = [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: info.vertex_stride as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let [position_offset, uv_offset, color_offset] = info.attribute_offsets;
+        let attribute_descriptions = [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: position_offset as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: uv_offset as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R8G8B8A8_UNORM,
+                offset: color_offset as u32,
+            },
+        ];
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo {
+            vertex_binding_description_count: binding_descriptions.len() as u32,
+            p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
+            vertex_attribute_description_count: attribute_descriptions.len() as u32,
+            p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
+            ..Default::default()
+        };
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart_enable: vk::FALSE,
+            ..Default::default()
+        };
+        // The scissor changes with every draw
+        let viewport_state = vk::PipelineViewportStateCreateInfo {
+            viewport_count: 1,
+            scissor_count: 1,
+            ..Default::default()
+        };
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+            dynamic_state_count: dynamic_states.len() as u32,
+            p_dynamic_states: dynamic_states.as_ptr(),
+            ..Default::default()
+        };
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo {
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            ..Default::default()
+        };
+        let multisampling = vk::PipelineMultisampleStateCreateInfo {
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            ..Default::default()
+        };
+        let color_blend_attachments = [info.blend];
+        let color_blending = vk::PipelineColorBlendStateCreateInfo {
+            attachment_count: color_blend_attachments.len() as u32,
+            p_attachments: color_blend_attachments.as_ptr(),
+            ..Default::default()
+        };
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default();
+
+        let pipeline_infos = [vk::GraphicsPipelineCreateInfo {
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &vertex_input_info,
+            p_input_assembly_state: &input_assembly,
+            p_viewport_state: &viewport_state,
+            p_rasterization_state: &rasterizer,
+            p_multisample_state: &multisampling,
+            p_depth_stencil_state: &depth_stencil,
+            p_color_blend_state: &color_blending,
+            p_dynamic_state: &dynamic_state,
+            layout: self.pipeline_layout,
+            render_pass: self.render_pass,
+            subpass: 0,
+            ..Default::default()
+        }];
+        let pipelines =
+            unsafe { device.create_graphics_pipelines(pipeline_cache, &pipeline_infos, None) };
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+        pipelines
+            .map(|pipelines| Pipeline::new(device, pipelines[0]))
+            .map_err(|(_, error)| format!("Failed to create {} pipeline: {:?}", info.name, error))
+    }
+
+    pub(crate) fn render_pass(&self) -> vk::RenderPass {
+        self.render_pass
+    }
+
+    /// Creates the framebuffers for the swapchain images, e.g. after the
+    /// swapchain created them again. The GPU can't be using them.
+    pub(crate) fn recreate(&mut self, swapchain: &Swapchain) {
+        self.destroy_framebuffers();
+        self.framebuffers = swapchain
+            .image_views
+            .iter()
+            .map(|image_view| {
+                let framebuffer_create_info = vk::FramebufferCreateInfo {
+                    render_pass: self.render_pass,
+                    attachment_count: 1,
+                    p_attachments: image_view,
+                    width: swapchain.extent.width,
+                    height: swapchain.extent.height,
+                    layers: 1,
+                    ..Default::default()
+                };
+                unsafe {
+                    self.device
+                        .create_framebuffer(&framebuffer_create_info, None)
+                        .expect("Failed to create overlay Framebuffer!")
+                }
+            })
+            .collect();
+    }
+
+    /// The framebuffers, one per swapchain image, for debug names.
+    pub(crate) fn framebuffers(&self) -> &[vk::Framebuffer] {
+        &self.framebuffers
+    }
+
+    /// Samples `image_view` from now on. The GPU can't be using the
+    /// previous one.
+    pub(crate) fn set_image(&self, image_view: vk::ImageView) {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let descriptor_writes = [vk::WriteDescriptorSet {
+            dst_set: self.descriptor_set,
+            dst_binding: 0,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+            p_image_info: &image_info,
+            ..Default::default()
+        }];
+        unsafe {
+            self.device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+    }
+
+    /// Starts over on frame slot `frame`, whose previous frame has to be
+    /// done, drawing with `transform`. Returns where to write the
+    /// `vertex_count` vertices and `index_count` indices, `None` if there
+    /// are none.
+    pub(crate) fn upload<V: Copy, I: Copy>(
+        &mut self,
+        frame: usize,
+        transform: Transform,
+        vertex_count: usize,
+        index_count: usize,
+    ) -> Option<OverlayUpload<'_, V, I>> {
+        let geometry = &mut self.frames[frame];
+        geometry.draws.clear();
+        geometry.transform = transform;
+        if vertex_count == 0 {
+            return None;
+        }
+        let vertices = Self::reserve(
+            &self.device,
+            &self.allocator,
+            &mut geometry.vertex_buffer,
+            &format!("{}_vertex_buffer", self.name),
+            vertex_count * std::mem::size_of::<V>(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let indices = Self::reserve(
+            &self.device,
+            &self.allocator,
+            &mut geometry.index_buffer,
+            &format!("{}_index_buffer", self.name),
+            index_count * std::mem::size_of::<I>(),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+        Some(OverlayUpload {
+            geometry,
+            vertices: vertices.cast(),
+            indices: indices.cast(),
+            vertex_count: 0,
+            index_count: 0,
+        })
+    }
+
+    /// Where the host visible `buffer` is mapped, after making it at least
+    /// `size` bytes. A bigger one replaces it, at least twice as big so it
+    /// doesn't have to grow every frame.
+    fn reserve(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        buffer: &mut Option<Buffer>,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> *mut c_void {
+        let capacity = buffer
+            .as_ref()
+            .and_then(Buffer::mapped_slice)
+            .map_or(0, <[u8]>::len);
+        if capacity < size {
+            // The slot's previous frame is done with the old one
+            *buffer = Some(VulkanApp::create_buffer(
+                device,
+                allocator,
+                name,
+                (size.max(capacity * 2)) as vk::DeviceSize,
+                usage,
+                MemoryLocation::CpuToGpu,
+            ));
+        }
+        buffer
+            .as_ref()
+            .and_then(Buffer::mapped_ptr)
+            .expect("overlay Buffer memory isn't host visible!")
+            .as_ptr()
+    }
+
+    /// Draws what was uploaded to frame slot `frame` over swapchain image
+    /// `image_index`, in a render pass of its own.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        image_index: usize,
+        extent: vk::Extent2D,
+    ) {
+        let geometry = &self.frames[frame];
+        let (vertex_buffer, index_buffer) = match (&geometry.vertex_buffer, &geometry.index_buffer)
+        {
+            (Some(vertex_buffer), Some(index_buffer)) if !geometry.draws.is_empty() => {
+                (vertex_buffer.buffer, index_buffer.buffer)
+            }
+            _ => return,
+        };
+        let pipeline = match &self.pipeline {
+            Some(pipeline) => pipeline.pipeline,
+            None => return,
+        };
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: self.framebuffers[image_index],
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            },
+            ..Default::default()
+        };
+        unsafe {
+            self.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+        }
+        crate::set_viewport(&self.device, command_buffer, extent);
+        unsafe {
+            let device = &self.device;
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                geometry.transform.as_bytes(),
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, self.index_type);
+            for draw in &geometry.draws {
+                device.cmd_set_scissor(command_buffer, 0, &[draw.scissor]);
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    draw.index_count,
+                    1,
+                    draw.first_index,
+                    draw.vertex_offset,
+                    0,
+                );
+            }
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
+    fn destroy_framebuffers(&mut self) {
+        for framebuffer in self.framebuffers.drain(..) {
+            unsafe {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+        }
+    }
+}
+
+impl Drop for OverlayRenderer {
+    fn drop(&mut self) {
+        self.destroy_framebuffers();
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+            self.device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+/// The scissor in physical pixels for a `clip_rect`, left, top, right and
+/// bottom in the UI's coordinates, which start at `origin` and are `scale`
+/// physical pixels each. `None` if none of it is on screen.
+pub(crate) fn clip_to_scissor(
+    clip_rect: [f32; 4],
+    origin: [f32; 2],
+    scale: [f32; 2],
+    extent: vk::Extent2D,
+) -> Option<vk::Rect2D> {
+    let left = ((clip_rect[0] - origin[0]) * scale[0]).round().max(0.0);
+    let top = ((clip_rect[1] - origin[1]) * scale[1]).round().max(0.0);
+    let right = ((clip_rect[2] - origin[0]) * scale[0])
+        .round()
+        .min(extent.width as f32);
+    let bottom = ((clip_rect[3] - origin[1]) * scale[1])
+        .round()
+        .min(extent.height as f32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(vk::Rect2D {
+        offset: vk::Offset2D {
+            x: left as i32,
+            y: top as i32,
+        },
+        extent: vk::Extent2D {
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn clip_to_scissor_scales_to_physical_pixels() {
+        let scissor = clip_to_scissor(
+            [10.0, 20.0, 110.0, 70.0],
+            [0.0, 0.0],
+            [2.0, 2.0],
+            extent(800, 600),
+        )
+        .unwrap();
+        assert_eq!((scissor.offset.x, scissor.offset.y), (20, 40));
+        assert_eq!((scissor.extent.width, scissor.extent.height), (200, 100));
+    }
+
+    #[test]
+    fn clip_to_scissor_stays_on_screen() {
+        let scissor = clip_to_scissor(
+            [-10.0, -10.0, 900.0, 50.0],
+            [0.0, 0.0],
+            [1.0, 1.0],
+            extent(800, 600),
+        )
+        .unwrap();
+        assert_eq!((scissor.offset.x, scissor.offset.y), (0, 0));
+        assert_eq!((scissor.extent.width, scissor.extent.height), (800, 50));
+        assert!(clip_to_scissor(
+            [900.0, 0.0, 1000.0, 50.0],
+            [0.0, 0.0],
+            [1.0, 1.0],
+            extent(800, 600)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn transform_maps_the_display_to_clip_space() {
+        let transform = Transform::new([64.0, 32.0], [512.0, 256.0]);
+        let to_clip = |point: [f32; 2]| {
+            [
+                point[0] * transform.scale[0] + transform.translate[0],
+                point[1] * transform.scale[1] + transform.translate[1],
+            ]
+        };
+        assert_eq!(to_clip([64.0, 32.0]), [-1.0, -1.0]);
+        assert_eq!(to_clip([576.0, 288.0]), [1.0, 1.0]);
+    }
+}
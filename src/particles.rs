@@ -0,0 +1,401 @@
+//! `--particles`: a fountain of points that particles.comp moves each frame.
+//! They're drawn straight from the storage buffer the shader writes to, as
+//! its vertex buffer, so they never go through the CPU after the first
+//! frame.
+//!
+//! Unlike the `--compute` wave, the simulation carries over from frame to
+//! frame, so there's a single buffer that's always dispatched on the
+//! graphics queue, with barriers keeping the dispatch and the draws in
+//! order.
+
+use std::ffi::CString;
+use std::path::Path;
+
+use ash::vk;
+
+use crate::resources::{Buffer, Pipeline};
+use crate::VulkanApp;
+
+/// Invocations per workgroup, `local_size_x` in particles.comp.
+const WORKGROUP_SIZE: u32 = 64;
+
+// The fountain, as in particles.comp
+const GRAVITY: f32 = -9.81;
+const EMITTER: [f32; 3] = [0.0, 0.0, 0.5];
+const FLOOR: f32 = -0.5;
+const SPREAD: f32 = 1.0;
+const MIN_LAUNCH_SPEED: f32 = 4.0;
+const MAX_LAUNCH_SPEED: f32 = 5.0;
+
+/// The longest step the simulation takes, so a hitch doesn't send every
+/// particle through the floor at once.
+const MAX_STEP: f32 = 0.1;
+
+/// A particle in the storage buffer, and the vertex attributes of
+/// particle.vert.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Particle {
+    /// W is unused, it's only there for the std430 layout.
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+impl Particle {
+    pub(crate) fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub(crate) fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: std::mem::size_of::<[f32; 4]>() as u32,
+            },
+        ]
+    }
+
+    /// `count` particles spread out along their arcs, as if the fountain
+    /// had been running for a while already.
+    pub(crate) fn fountain(count: u32) -> Vec<Particle> {
+        // xorshift, there's no need for anything better here
+        let mut state = 0x9E37_79B9_u32;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32
+        };
+        (0..count)
+            .map(|_| {
+                let angle = std::f32::consts::TAU * random();
+                let spread = SPREAD * random();
+                let speed = MIN_LAUNCH_SPEED + (MAX_LAUNCH_SPEED - MIN_LAUNCH_SPEED) * random();
+                // How long until it falls through the floor
+                let drop = EMITTER[2] - FLOOR;
+                let flight_time =
+                    (speed + (speed * speed + 2.0 * -GRAVITY * drop).sqrt()) / -GRAVITY;
+                let time = flight_time * random();
+                let [vx, vy] = [spread * angle.cos(), spread * angle.sin()];
+                Particle {
+                    position: [
+                        EMITTER[0] + vx * time,
+                        EMITTER[1] + vy * time,
+                        EMITTER[2] + speed * time + 0.5 * GRAVITY * time * time,
+                        1.0,
+                    ],
+                    velocity: [vx, vy, speed + GRAVITY * time, 0.0],
+                }
+            })
+            .collect()
+    }
+}
+
+/// Matches the `push_constant` block in particles.comp.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ParticlePushConstants {
+    dt: f32,
+    count: u32,
+    seed: u32,
+}
+
+/// The particle buffer and the compute pipeline that moves its particles.
+pub(crate) struct Particles {
+    device: ash::Device,
+    buffer: Buffer,
+    count: u32,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    pub(crate) pipeline: Pipeline,
+    /// Seconds the next dispatch moves the particles along.
+    dt: f32,
+    seed: u32,
+}
+
+impl Particles {
+    /// Moves the `count` particles in `buffer`, which needs to be usable as
+    /// both a storage and a vertex buffer. Panics if particles.comp can't
+    /// be loaded.
+    pub(crate) fn new(
+        device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        buffer: Buffer,
+        count: u32,
+    ) -> Self {
+        let bindings = [vk::DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            ..Default::default()
+        }];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create particle Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+        }];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create particle Descriptor Pool!")
+        };
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate particle Descriptor Set!")[0]
+        };
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer: buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        };
+        let descriptor_write = vk::WriteDescriptorSet {
+            dst_set: descriptor_set,
+            dst_binding: 0,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            p_buffer_info: &buffer_info,
+            ..Default::default()
+        };
+        unsafe {
+            device.update_descriptor_sets(&[descriptor_write], &[]);
+        }
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<ParticlePushConstants>() as u32,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create particle pipeline layout")
+        };
+
+        let pipeline = Self::create_pipeline(device, pipeline_cache, shader_dir, pipeline_layout)
+            .unwrap_or_else(|error| panic!("{}", error));
+
+        Particles {
+            device: device.clone(),
+            buffer,
+            count,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            dt: 0.0,
+            seed: 0,
+        }
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<&Path>,
+        layout: vk::PipelineLayout,
+    ) -> Result<Pipeline, String> {
+        let code = VulkanApp::load_shader_code(shader_dir, "particles.comp")?;
+        let shader_module = VulkanApp::create_shader_module(device, &code)?;
+        let main_function_name = CString::new("main").unwrap();
+        let pipeline_infos = [vk::ComputePipelineCreateInfo {
+            stage: vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::COMPUTE,
+                module: shader_module,
+                p_name: main_function_name.as_ptr(),
+                ..Default::default()
+            },
+            layout,
+            ..Default::default()
+        }];
+        let result =
+            unsafe { device.create_compute_pipelines(pipeline_cache, &pipeline_infos, None) };
+        unsafe {
+            device.destroy_shader_module(shader_module, None);
+        }
+        result
+            .map(|pipelines| Pipeline::new(device, pipelines[0]))
+            .map_err(|(_, error)| format!("Failed to create particle Pipeline: {:?}", error))
+    }
+
+    /// Has the next dispatch move the particles `dt` seconds along.
+    pub(crate) fn advance(&mut self, dt: f32) {
+        self.dt = dt.min(MAX_STEP);
+        self.seed = self.seed.wrapping_add(1);
+    }
+
+    /// Records the dispatch, outside of any render pass, between barriers
+    /// that keep it from overwriting particles earlier frames still draw,
+    /// and keep the draws after it from reading them before it's done.
+    pub(crate) fn record(&self, command_buffer: vk::CommandBuffer) {
+        let device = &self.device;
+        let push_constants = ParticlePushConstants {
+            dt: self.dt,
+            count: self.count,
+            seed: self.seed,
+        };
+        // The previous dispatch's writes have to be visible to this one as
+        // well, not just to the draws
+        let before_dispatch = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.buffer.buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        let before_draws = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            ..before_dispatch
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[before_dispatch],
+                &[],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const ParticlePushConstants as *const u8,
+                    std::mem::size_of::<ParticlePushConstants>(),
+                ),
+            );
+            device.cmd_dispatch(command_buffer, self.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[before_draws],
+                &[],
+            );
+        }
+    }
+
+    /// Draws the particles as points with `pipeline`, which is made for
+    /// particle.vert. `descriptor_set` has the camera.
+    pub(crate) fn draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        pipeline_layout: vk::PipelineLayout,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let device = &self.device;
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                0,
+                &[descriptor_set],
+                // The particles don't use the object transforms
+                &[0],
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.buffer.buffer], &[0]);
+            device.cmd_draw(command_buffer, self.count, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for Particles {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fountain_particles_are_in_flight() {
+        let particles = Particle::fountain(1000);
+        assert_eq!(particles.len(), 1000);
+        for particle in particles {
+            assert!(particle.position[2] >= FLOOR);
+            let [vx, vy, _, _] = particle.velocity;
+            assert!((vx * vx + vy * vy).sqrt() <= SPREAD + f32::EPSILON);
+        }
+    }
+}
@@ -0,0 +1,700 @@
+//! Graphics pipelines, created the first time something is drawn with them
+//! and cached from then on. Features like the wireframe mode or the skybox
+//! only ask for the pipeline they need, by what sets it apart from others.
+
+use std::collections::HashMap;
+use std::ffi::{c_void, CString};
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+use crate::deferred::GBUFFER_FORMATS;
+use crate::mesh::Vertex;
+use crate::particles::Particle;
+use crate::resources::Pipeline;
+use crate::{Demo, InstanceData, VulkanApp};
+
+/// How many pixels wide lines are with the `wide_lines` feature. Devices
+/// that have it can draw lines at least 8 wide.
+const WIDE_LINE_WIDTH: f32 = 2.0;
+
+/// The least fraction of a pixel's samples that sample shading shades
+/// separately, e.g. 2 of 8 at 8x MSAA. Drivers are free to shade more.
+const MIN_SAMPLE_SHADING: f32 = 0.2;
+
+/// The kinds of graphics pipelines, each with its own shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Material {
+    /// The demo's model, drawn by the [`Renderer`](crate::Renderer).
+    Scene(Demo),
+    /// The quad or the cube with `--vertex-pulling`, which reads its
+    /// vertices through the address the renderer pushes instead of a
+    /// vertex buffer.
+    Pulled,
+    /// The floor grid of `--grid`, which makes up its own vertices.
+    Grid,
+    /// The `--skybox` cube, drawn on the far plane.
+    Skybox,
+    /// The `--deferred` lighting pass over the whole screen, which reads the
+    /// G-buffer the scene was drawn into.
+    Lighting,
+    /// Scene objects once there's a texture array, drawn along with the
+    /// demo's model.
+    Textured,
+    /// `Textured` with descriptor indexing, where each texture is an image
+    /// of its own.
+    Bindless,
+    /// The `--particles`, drawn as points from the buffer the compute
+    /// shader moves them in.
+    Particles,
+    /// The `--outline` around the demo's model, drawn by the renderer again
+    /// scaled up.
+    Outline,
+    /// The `--offscreen` image drawn onto the swapchain image, in a render
+    /// pass of its own.
+    Present,
+    /// The boxes around scene objects of `--occlusion-culling`, only depth
+    /// tested, without writing anything.
+    OcclusionBox,
+}
+
+impl Material {
+    /// Whether this is lit, which `--deferred` does in a later subpass.
+    fn is_lit(self) -> bool {
+        matches!(
+            self,
+            Material::Scene(_) | Material::Pulled | Material::Textured | Material::Bindless
+        )
+    }
+
+    /// What it's drawn as unless its key says otherwise.
+    fn topology(self) -> Topology {
+        match self {
+            Material::Particles => Topology::Points,
+            _ => Topology::Triangles,
+        }
+    }
+
+    fn vertex_shader(self) -> &'static str {
+        match self {
+            Material::Scene(demo) => demo.vertex_shader(),
+            Material::Pulled => "pulled.vert",
+            Material::Grid => "grid.vert",
+            Material::Skybox => "skybox.vert",
+            Material::Lighting | Material::Present => "fullscreen.vert",
+            Material::Textured | Material::Bindless => "textured.vert",
+            Material::Particles => "particle.vert",
+            Material::Outline => "outline.vert",
+            Material::OcclusionBox => "occlusion_box.vert",
+        }
+    }
+
+    fn fragment_shader(self, deferred: bool) -> &'static str {
+        match self {
+            Material::Scene(_) | Material::Pulled if deferred => "gbuffer.frag",
+            Material::Scene(_) | Material::Pulled => "shader.frag",
+            Material::Grid => "grid.frag",
+            Material::Skybox => "skybox.frag",
+            Material::Lighting => "lighting.frag",
+            Material::Textured if deferred => "textured_gbuffer.frag",
+            Material::Textured => "textured.frag",
+            Material::Bindless if deferred => "bindless_gbuffer.frag",
+            Material::Bindless => "bindless.frag",
+            Material::Particles => "particle.frag",
+            Material::Outline => "outline.frag",
+            Material::Present => "present.frag",
+            Material::OcclusionBox => "occlusion_box.frag",
+        }
+    }
+}
+
+/// How the vertices of a draw make up its primitives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// Each three vertices are a triangle.
+    #[default]
+    Triangles,
+    /// Each two vertices are a line. They're a pixel wide, or a little
+    /// wider where the device has the `wide_lines` feature.
+    Lines,
+    /// Each vertex is a point, which the vertex shader has to give a size
+    /// with `gl_PointSize`. Only a size of 1 works everywhere.
+    Points,
+}
+
+impl Topology {
+    pub(crate) const ALL: [Topology; 3] = [Topology::Triangles, Topology::Lines, Topology::Points];
+
+    fn to_vk(self) -> vk::PrimitiveTopology {
+        match self {
+            Topology::Triangles => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Topology::Lines => vk::PrimitiveTopology::LINE_LIST,
+            Topology::Points => vk::PrimitiveTopology::POINT_LIST,
+        }
+    }
+}
+
+/// What marked pixels are set to in the stencil buffer.
+const STENCIL_MARK: u32 = 1;
+
+/// What a draw does with the stencil buffer, which is cleared to 0 every
+/// frame. There's only a stencil buffer if the depth format has a stencil
+/// aspect, without one nothing is masked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Stencil {
+    /// Leaves it alone.
+    #[default]
+    Off,
+    /// Marks the pixels it draws, wherever it passes the depth test.
+    Write,
+    /// Only draws over marked pixels, e.g. through a portal.
+    Inside,
+    /// Only draws where no pixel is marked, e.g. an outline around what is.
+    Outside,
+}
+
+impl Stencil {
+    pub(crate) const ALL: [Stencil; 4] = [
+        Stencil::Off,
+        Stencil::Write,
+        Stencil::Inside,
+        Stencil::Outside,
+    ];
+
+    /// Whether the stencil test is on, and what it does to front and back
+    /// faces alike.
+    fn state(self) -> (vk::Bool32, vk::StencilOpState) {
+        let (compare_op, pass_op) = match self {
+            Stencil::Off => return (vk::FALSE, vk::StencilOpState::default()),
+            Stencil::Write => (vk::CompareOp::ALWAYS, vk::StencilOp::REPLACE),
+            Stencil::Inside => (vk::CompareOp::EQUAL, vk::StencilOp::KEEP),
+            Stencil::Outside => (vk::CompareOp::NOT_EQUAL, vk::StencilOp::KEEP),
+        };
+        let state = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op,
+            compare_mask: STENCIL_MARK,
+            write_mask: STENCIL_MARK,
+            reference: STENCIL_MARK,
+        };
+        (vk::TRUE, state)
+    }
+}
+
+/// What a pipeline is looked up by. Settings that apply to all pipelines
+/// alike, like the brightness, live in the [`PipelineManager`] instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    /// Picks the shaders and their vertex input.
+    pub(crate) material: Material,
+    pub(crate) topology: Topology,
+    pub(crate) stencil: Stencil,
+    /// Only draws polygon edges, which needs `fill_mode_non_solid`.
+    pub(crate) wireframe: bool,
+    /// Blends with what's already drawn by the fragment alpha, instead of
+    /// overwriting it.
+    pub(crate) alpha_blending: bool,
+}
+
+impl PipelineKey {
+    /// A solid, opaque pipeline for `material`, drawn as whatever it's
+    /// usually drawn as.
+    pub(crate) fn new(material: Material) -> Self {
+        PipelineKey {
+            material,
+            topology: material.topology(),
+            stencil: Stencil::Off,
+            wireframe: false,
+            alpha_blending: false,
+        }
+    }
+}
+
+/// What pipelines draw into.
+#[derive(Clone, Copy)]
+pub(crate) enum RenderTarget {
+    RenderPass(vk::RenderPass),
+    /// Attachments of these formats, given to `vkCmdBeginRenderingKHR` with
+    /// `--dynamic-rendering`.
+    Dynamic {
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        /// `UNDEFINED` if there's no stencil attachment.
+        stencil_format: vk::Format,
+    },
+}
+
+/// Creates pipelines on demand, all from the same pipeline cache and render
+/// target, and keeps them until it's dropped.
+pub(crate) struct PipelineManager {
+    device: ash::Device,
+    render_target: RenderTarget,
+    pipeline_cache: vk::PipelineCache,
+    /// Where to load compiled shaders from instead of the embedded ones.
+    shader_dir: Option<PathBuf>,
+    /// The `constant_id = 0` specialization constant of shader.frag.
+    brightness: f32,
+    /// Whether the render pass has the G-buffer and lighting subpasses of
+    /// `--deferred`, with the scene in the first one and everything else in
+    /// the second.
+    deferred: bool,
+    /// Whether lines can be wider than a pixel.
+    wide_lines: bool,
+    /// Whether the render pass draws each of the `--multiview` eyes, which
+    /// takes the `_multiview` variants of the vertex shaders.
+    multiview: bool,
+    /// How many samples per pixel the render target has, see `--msaa`.
+    samples: vk::SampleCountFlags,
+    /// Whether fragments are shaded for each sample rather than once per
+    /// pixel, see `--sample-shading`. Only matters with more than one of
+    /// `samples`.
+    sample_shading: bool,
+    /// What `Material::Present` draws in, null without `--offscreen`.
+    present_render_pass: vk::RenderPass,
+    // Along with the layout each was created with, to create it again
+    pipelines: HashMap<PipelineKey, (vk::PipelineLayout, Pipeline)>,
+    /// Counts the rebuilds, each of which destroys all the pipelines handed
+    /// out before it.
+    generation: u64,
+}
+
+impl PipelineManager {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        device: &ash::Device,
+        render_target: RenderTarget,
+        pipeline_cache: vk::PipelineCache,
+        shader_dir: Option<PathBuf>,
+        brightness: f32,
+        deferred: bool,
+        wide_lines: bool,
+        multiview: bool,
+        samples: vk::SampleCountFlags,
+    ) -> Self {
+        PipelineManager {
+            device: device.clone(),
+            render_target,
+            pipeline_cache,
+            shader_dir,
+            brightness,
+            deferred,
+            wide_lines,
+            multiview,
+            samples,
+            sample_shading: false,
+            present_render_pass: vk::RenderPass::null(),
+            pipelines: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Has `Material::Present` draw in `render_pass`, before any pipeline
+    /// of it is created.
+    pub(crate) fn set_present_render_pass(&mut self, render_pass: vk::RenderPass) {
+        self.present_render_pass = render_pass;
+    }
+
+    pub(crate) fn shader_dir(&self) -> Option<&Path> {
+        self.shader_dir.as_deref()
+    }
+
+    pub(crate) fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    pub(crate) fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+
+    pub(crate) fn sample_shading(&self) -> bool {
+        self.sample_shading
+    }
+
+    /// Changes whenever the pipelines handed out so far are destroyed.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn contains(&self, key: &PipelineKey) -> bool {
+        self.pipelines.contains_key(key)
+    }
+
+    /// The pipeline for `key`, which is created with `layout` unless it's
+    /// cached already. A material always has to come with the same layout.
+    pub(crate) fn get(
+        &mut self,
+        key: PipelineKey,
+        layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, String> {
+        if let Some((_, pipeline)) = self.pipelines.get(&key) {
+            return Ok(pipeline.pipeline);
+        }
+        let pipeline = self.create(key, layout)?;
+        let handle = pipeline.pipeline;
+        self.pipelines.insert(key, (layout, pipeline));
+        Ok(handle)
+    }
+
+    /// All cached pipelines.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&PipelineKey, vk::Pipeline)> {
+        self.pipelines
+            .iter()
+            .map(|(key, (_, pipeline))| (key, pipeline.pipeline))
+    }
+
+    /// Creates every cached pipeline again with `brightness`, e.g. after the
+    /// shaders changed. The old ones are only replaced if all of them could
+    /// be created, so a broken shader doesn't take the app down.
+    pub(crate) fn rebuild(&mut self, brightness: f32) -> Result<(), String> {
+        self.rebuild_with(|manager| manager.brightness = brightness)
+    }
+
+    /// Like `rebuild`, but for drawing into `render_target` with `samples`
+    /// per pixel from now on, e.g. after the render pass was created again
+    /// for another `--msaa`. Nothing changes if that fails.
+    pub(crate) fn retarget(
+        &mut self,
+        render_target: RenderTarget,
+        samples: vk::SampleCountFlags,
+    ) -> Result<(), String> {
+        self.rebuild_with(|manager| {
+            manager.render_target = render_target;
+            manager.samples = samples;
+        })
+    }
+
+    /// Like `rebuild`, but with sample shading on or off from now on. That
+    /// needs the `sample_rate_shading` feature.
+    pub(crate) fn set_sample_shading(&mut self, sample_shading: bool) -> Result<(), String> {
+        self.rebuild_with(|manager| manager.sample_shading = sample_shading)
+    }
+
+    /// Creates every cached pipeline again after `update` changed the
+    /// settings, which are put back if any of them fails.
+    fn rebuild_with(&mut self, update: impl FnOnce(&mut Self)) -> Result<(), String> {
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .expect("Failed to wait device idle!");
+        }
+        let previous = (
+            self.brightness,
+            self.render_target,
+            self.samples,
+            self.sample_shading,
+        );
+        update(self);
+        let rebuilt: Result<HashMap<_, _>, String> = self
+            .pipelines
+            .iter()
+            .map(|(&key, &(layout, _))| Ok((key, (layout, self.create(key, layout)?))))
+            .collect();
+        match rebuilt {
+            // The old pipelines are destroyed as they're replaced
+            Ok(rebuilt) => {
+                self.pipelines = rebuilt;
+                self.generation += 1;
+            }
+            Err(error) => {
+                (
+                    self.brightness,
+                    self.render_target,
+                    self.samples,
+                    self.sample_shading,
+                ) = previous;
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    fn create(
+        &self,
+        key: PipelineKey,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<Pipeline, String> {
+        let device = &self.device;
+        // The present pass isn't multiview, it shows the eyes side by side
+        let vertex_shader = match key.material.vertex_shader() {
+            name if self.multiview && key.material != Material::Present => {
+                name.replace(".vert", "_multiview.vert")
+            }
+            name => name.to_owned(),
+        };
+        let vert_shader_code =
+            VulkanApp::load_shader_code(self.shader_dir.as_deref(), &vertex_shader)?;
+        let frag_shader_code = VulkanApp::load_shader_code(
+            self.shader_dir.as_deref(),
+            key.material.fragment_shader(self.deferred),
+        )?;
+
+        let vert_shader_module = VulkanApp::create_shader_module(device, &vert_shader_code)?;
+        let frag_shader_module = match VulkanApp::create_shader_module(device, &frag_shader_code) {
+            Ok(frag_shader_module) => frag_shader_module,
+            Err(error) => {
+                unsafe {
+                    device.destroy_shader_module(vert_shader_module, None);
+                }
+                return Err(error);
+            }
+        };
+
+        let shader_entrypoint = CString::new("main").unwrap();
+
+        // Fills in the `constant_id = 0` constant of shader.frag
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<f32>(),
+        }];
+        let specialization_info = vk::SpecializationInfo {
+            map_entry_count: specialization_entries.len() as u32,
+            p_map_entries: specialization_entries.as_ptr(),
+            data_size: std::mem::size_of::<f32>(),
+            p_data: &self.brightness as *const f32 as *const c_void,
+        };
+
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::VERTEX,
+                module: vert_shader_module,
+                p_name: shader_entrypoint.as_ptr(),
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                module: frag_shader_module,
+                p_name: shader_entrypoint.as_ptr(),
+                p_specialization_info: &specialization_info,
+                ..Default::default()
+            },
+        ];
+
+        // The triangle's vertices are hardcoded in the vertex shader, so its
+        // only vertex data comes per instance.
+        // The grid makes up its vertices the same way, and has no instances.
+        // Pulled vertices are read from a buffer, but not as vertex input.
+        let (binding_descriptions, attribute_descriptions) = match key.material {
+            Material::Scene(Demo::Triangle) => (
+                InstanceData::binding_descriptions().to_vec(),
+                InstanceData::attribute_descriptions().to_vec(),
+            ),
+            Material::Scene(Demo::Quad | Demo::Cube)
+            | Material::Textured
+            | Material::Bindless
+            | Material::Outline => (
+                Vertex::binding_descriptions().to_vec(),
+                Vertex::attribute_descriptions().to_vec(),
+            ),
+            Material::Particles => (
+                Particle::binding_descriptions().to_vec(),
+                Particle::attribute_descriptions().to_vec(),
+            ),
+            Material::Pulled
+            | Material::Grid
+            | Material::Skybox
+            | Material::Lighting
+            | Material::Present
+            | Material::OcclusionBox => (vec![], vec![]),
+        };
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo {
+            vertex_binding_description_count: binding_descriptions.len() as u32,
+            p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
+            vertex_attribute_description_count: attribute_descriptions.len() as u32,
+            p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
+            ..Default::default()
+        };
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: key.topology.to_vk(),
+            primitive_restart_enable: vk::FALSE,
+            ..Default::default()
+        };
+
+        // Viewport and scissor are set while recording the command buffer,
+        // so the pipeline survives swapchain recreation on resize.
+        let viewport_state = vk::PipelineViewportStateCreateInfo {
+            viewport_count: 1,
+            scissor_count: 1,
+            ..Default::default()
+        };
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+            dynamic_state_count: dynamic_states.len() as u32,
+            p_dynamic_states: dynamic_states.as_ptr(),
+            ..Default::default()
+        };
+
+        let rasterizer = vk::PipelineRasterizationStateCreateInfo {
+            depth_clamp_enable: vk::FALSE,
+            depth_bias_enable: vk::FALSE,
+            rasterizer_discard_enable: vk::FALSE,
+            polygon_mode: if key.wireframe {
+                vk::PolygonMode::LINE
+            } else {
+                vk::PolygonMode::FILL
+            },
+            line_width: if key.topology == Topology::Lines && self.wide_lines {
+                WIDE_LINE_WIDTH
+            } else {
+                1.0
+            },
+            // The grid can be seen from below when the camera orbits there
+            cull_mode: match key.material {
+                Material::Scene(_)
+                | Material::Pulled
+                | Material::Textured
+                | Material::Bindless
+                | Material::Outline => vk::CullModeFlags::BACK,
+                Material::Grid
+                | Material::Skybox
+                | Material::Lighting
+                | Material::Particles
+                | Material::Present
+                | Material::OcclusionBox => vk::CullModeFlags::NONE,
+            },
+            // The Y flip in the projection matrix mirrors the winding order
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            ..Default::default()
+        };
+
+        // The present pass samples the offscreen image the render pass
+        // resolved into, and draws into the single sampled swapchain image
+        let rasterization_samples = match key.material {
+            Material::Present => vk::SampleCountFlags::TYPE_1,
+            _ => self.samples,
+        };
+        let sample_shading =
+            self.sample_shading && rasterization_samples != vk::SampleCountFlags::TYPE_1;
+        let multisampling = vk::PipelineMultisampleStateCreateInfo {
+            sample_shading_enable: if sample_shading { vk::TRUE } else { vk::FALSE },
+            rasterization_samples,
+            min_sample_shading: MIN_SAMPLE_SHADING,
+            ..Default::default()
+        };
+
+        // The deferred scene writes the G-buffer, which only makes sense
+        // without blending
+        let writes_gbuffer = self.deferred && key.material.is_lit();
+        let (subpass, color_attachment_count) = match key.material {
+            Material::Present => (0, 1),
+            _ if writes_gbuffer => (0, GBUFFER_FORMATS.len()),
+            _ if self.deferred => (1, 1),
+            _ => (0, 1),
+        };
+        // Standard "over" blending: color = src * src.a + dst * (1 - src.a)
+        let color_blend_attachment = vk::PipelineColorBlendAttachmentState {
+            color_write_mask: match key.material {
+                Material::OcclusionBox => vk::ColorComponentFlags::empty(),
+                _ => {
+                    vk::ColorComponentFlags::R
+                        | vk::ColorComponentFlags::G
+                        | vk::ColorComponentFlags::B
+                        | vk::ColorComponentFlags::A
+                }
+            },
+            blend_enable: if key.alpha_blending && !writes_gbuffer {
+                vk::TRUE
+            } else {
+                vk::FALSE
+            },
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+            alpha_blend_op: vk::BlendOp::ADD,
+        };
+        let color_blend_attachments = vec![color_blend_attachment; color_attachment_count];
+
+        let color_blending = vk::PipelineColorBlendStateCreateInfo {
+            logic_op_enable: vk::FALSE,
+            logic_op: vk::LogicOp::COPY,
+            attachment_count: color_blend_attachments.len() as u32,
+            p_attachments: color_blend_attachments.as_ptr(),
+            ..Default::default()
+        };
+
+        // The skybox sits exactly on the far plane, which the depth buffer is
+        // cleared to, and there's nothing behind it to hide. The lighting
+        // pass covers the whole screen regardless of depth, and the present
+        // pass has no depth at all. Occlusion boxes are only tested, as the
+        // object inside is in the depth buffer already if it was drawn.
+        let (depth_test_enable, depth_write_enable, depth_compare_op) = match key.material {
+            Material::Skybox | Material::OcclusionBox => {
+                (vk::TRUE, vk::FALSE, vk::CompareOp::LESS_OR_EQUAL)
+            }
+            Material::Lighting | Material::Present => (vk::FALSE, vk::FALSE, vk::CompareOp::ALWAYS),
+            _ => (vk::TRUE, vk::TRUE, vk::CompareOp::LESS),
+        };
+        let (stencil_test_enable, stencil_op_state) = key.stencil.state();
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo {
+            depth_test_enable,
+            depth_write_enable,
+            depth_compare_op,
+            depth_bounds_test_enable: vk::FALSE,
+            stencil_test_enable,
+            front: stencil_op_state,
+            back: stencil_op_state,
+            ..Default::default()
+        };
+
+        let color_formats;
+        let rendering_create_info;
+        let (p_next, render_pass) = match self.render_target {
+            _ if key.material == Material::Present => (std::ptr::null(), self.present_render_pass),
+            RenderTarget::RenderPass(render_pass) => (std::ptr::null(), render_pass),
+            RenderTarget::Dynamic {
+                color_format,
+                depth_format,
+                stencil_format,
+            } => {
+                color_formats = [color_format];
+                rendering_create_info = vk::PipelineRenderingCreateInfo {
+                    color_attachment_count: color_formats.len() as u32,
+                    p_color_attachment_formats: color_formats.as_ptr(),
+                    depth_attachment_format: depth_format,
+                    stencil_attachment_format: stencil_format,
+                    ..Default::default()
+                };
+                (
+                    &rendering_create_info as *const _ as *const c_void,
+                    vk::RenderPass::null(),
+                )
+            }
+        };
+        let graphic_pipeline_infos = [vk::GraphicsPipelineCreateInfo {
+            p_next,
+            stage_count: shader_stages.len() as u32,
+            p_stages: shader_stages.as_ptr(),
+            p_vertex_input_state: &vertex_input_info,
+            p_input_assembly_state: &input_assembly,
+            p_viewport_state: &viewport_state,
+            p_rasterization_state: &rasterizer,
+            p_multisample_state: &multisampling,
+            p_depth_stencil_state: &depth_stencil,
+            p_color_blend_state: &color_blending,
+            p_dynamic_state: &dynamic_state,
+            layout: pipeline_layout,
+            render_pass,
+            subpass,
+            ..Default::default()
+        }];
+
+        let graphics_pipelines = unsafe {
+            device.create_graphics_pipelines(self.pipeline_cache, &graphic_pipeline_infos, None)
+        };
+
+        unsafe {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_shader_module(frag_shader_module, None);
+        }
+
+        graphics_pipelines
+            .map(|pipelines| Pipeline::new(device, pipelines[0]))
+            .map_err(|(_, error)| format!("Failed to create graphics pipeline: {:?}", error))
+    }
+}
@@ -0,0 +1,111 @@
+//! `vkGetPhysicalDeviceProperties2` and its siblings, which fill in the
+//! structs chained onto theirs. That's how everything past Vulkan 1.0's
+//! limits and features is read, like those of descriptor indexing or ray
+//! tracing. They're core in Vulkan 1.1, and come from
+//! `VK_KHR_get_physical_device_properties2` on a 1.0 instance, which it's
+//! created with where the loader has it.
+
+use ash::extensions::khr::GetPhysicalDeviceProperties2;
+use ash::vk;
+
+pub(crate) enum Properties2 {
+    /// Vulkan 1.1 and later.
+    Core(ash::Instance),
+    /// The extension, on Vulkan 1.0.
+    Khr(GetPhysicalDeviceProperties2),
+}
+
+impl Properties2 {
+    /// The queries of `instance`, created for `api_version`, and with the
+    /// extension if `extension_enabled`. `None` on Vulkan 1.0 without it.
+    pub(crate) fn load(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        api_version: u32,
+        extension_enabled: bool,
+    ) -> Option<Self> {
+        if api_version >= vk::make_api_version(0, 1, 1, 0) {
+            return Some(Properties2::Core(instance.clone()));
+        }
+        if !extension_enabled {
+            return None;
+        }
+        Some(Properties2::Khr(GetPhysicalDeviceProperties2::new(
+            entry, instance,
+        )))
+    }
+
+    /// Fills in `properties` and everything chained onto it, which has to be
+    /// valid for the query.
+    pub(crate) unsafe fn get_properties2(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        properties: &mut vk::PhysicalDeviceProperties2,
+    ) {
+        match self {
+            Properties2::Core(instance) => {
+                instance.get_physical_device_properties2(physical_device, properties);
+            }
+            Properties2::Khr(loader) => {
+                loader.get_physical_device_properties2(physical_device, properties);
+            }
+        }
+    }
+
+    /// Like `get_properties2`, for features.
+    pub(crate) unsafe fn get_features2(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        features: &mut vk::PhysicalDeviceFeatures2,
+    ) {
+        match self {
+            Properties2::Core(instance) => {
+                instance.get_physical_device_features2(physical_device, features);
+            }
+            Properties2::Khr(loader) => {
+                loader.get_physical_device_features2(physical_device, features);
+            }
+        }
+    }
+
+    /// Like `get_properties2`, for memory heaps and types.
+    pub(crate) unsafe fn get_memory_properties2(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        properties: &mut vk::PhysicalDeviceMemoryProperties2,
+    ) {
+        match self {
+            Properties2::Core(instance) => {
+                instance.get_physical_device_memory_properties2(physical_device, properties);
+            }
+            Properties2::Khr(loader) => {
+                loader.get_physical_device_memory_properties2(physical_device, properties);
+            }
+        }
+    }
+
+    /// Reads property struct `T` on its own, like
+    /// `vk::PhysicalDeviceDescriptorIndexingPropertiesEXT`. ash only lets
+    /// structs that extend `vk::PhysicalDeviceProperties2` be chained onto
+    /// it, and their `Default` fills in the `s_type`.
+    pub(crate) unsafe fn properties<T>(&self, physical_device: vk::PhysicalDevice) -> T
+    where
+        T: vk::ExtendsPhysicalDeviceProperties2 + Default,
+    {
+        let mut chained = T::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut chained);
+        self.get_properties2(physical_device, &mut properties);
+        chained
+    }
+    /// Like `properties`, for feature struct `T`, like
+    /// `vk::PhysicalDeviceTimelineSemaphoreFeatures`.
+    pub(crate) unsafe fn features<T>(&self, physical_device: vk::PhysicalDevice) -> T
+    where
+        T: vk::ExtendsPhysicalDeviceFeatures2 + Default,
+    {
+        let mut chained = T::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut chained);
+        self.get_features2(physical_device, &mut features);
+        chained
+    }
+}
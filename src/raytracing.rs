@@ -0,0 +1,1035 @@
+//! `--raytracing`: the demo's mesh is traced rather than rasterized, with
+//! `VK_KHR_acceleration_structure` and `VK_KHR_ray_tracing_pipeline`. A
+//! bottom level acceleration structure is built from the mesh once, and a
+//! top level one around a single instance of it. raytrace.rgen writes each
+//! pixel into a storage image, which is then blitted onto the swapchain
+//! image in place of the main pass.
+//!
+//! All of this is behind the `raytracing` feature, which also has build.rs
+//! compile the ray tracing stages.
+
+use std::ffi::CString;
+
+use ash::extensions::khr::{
+    AccelerationStructure as AccelerationStructureLoader,
+    BufferDeviceAddress as BufferDeviceAddressLoader,
+    RayTracingPipeline as RayTracingPipelineLoader,
+};
+use ash::vk;
+use glam::Mat4;
+
+use crate::allocator::{self, MemoryLocation, SharedAllocator};
+use crate::device_address::{self, DeviceAddresses};
+use crate::mesh::Vertex;
+use crate::properties2::Properties2;
+use crate::resources::{Buffer, Image, ImageMemory};
+use crate::VulkanApp;
+
+/// What raytrace.rgen writes, which every device can store to and blit
+/// from.
+const IMAGE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// What the buffers of a [`TracedMesh`] have to be created with, on top of
+/// `device_address::USAGE`.
+pub(crate) fn mesh_usage() -> vk::BufferUsageFlags {
+    vk::BufferUsageFlags::STORAGE_BUFFER
+        | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+}
+
+/// `value` rounded up to a multiple of `alignment`.
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Where the records of the shader binding table go, from its start. There's
+/// one record for each of raygen, miss and closest_hit, in that order.
+#[derive(Debug, PartialEq, Eq)]
+struct TableLayout {
+    /// The size of each record, its shader group handle padded out to the
+    /// handle alignment.
+    stride: u64,
+    /// Each record starts a region of its own, at the base alignment.
+    offsets: [u64; 3],
+    size: u64,
+}
+
+fn table_layout(handle_size: u32, handle_alignment: u32, base_alignment: u32) -> TableLayout {
+    let stride = align_up(u64::from(handle_size), u64::from(handle_alignment));
+    let region = align_up(stride, u64::from(base_alignment));
+    TableLayout {
+        stride,
+        offsets: [0, region, 2 * region],
+        size: 2 * region + stride,
+    }
+}
+
+/// The push constants of raytrace.rgen, which takes the camera apart
+/// again to shoot rays through each pixel.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CameraConstants {
+    inverse_proj: [[f32; 4]; 4],
+    inverse_view_model: [[f32; 4]; 4],
+}
+
+impl CameraConstants {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+/// The demo's mesh, in buffers the acceleration structure is built from
+/// and closest_hit fetches triangles from.
+pub(crate) struct TracedMesh {
+    /// `Vertex`es, `vertex_count` of them.
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) vertex_count: u32,
+    /// 32-bit indices, whatever the mesh is drawn with, since that's all
+    /// raytrace.rchit reads.
+    pub(crate) index_buffer: Buffer,
+    pub(crate) triangle_count: u32,
+}
+
+struct AccelerationStructure {
+    handle: vk::AccelerationStructureKHR,
+    /// What the top level instance refers to the bottom level one by.
+    address: vk::DeviceAddress,
+    _buffer: Buffer,
+}
+
+pub(crate) struct RayTracer {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    acceleration_structures: AccelerationStructureLoader,
+    ray_tracing: RayTracingPipelineLoader,
+    /// Read by closest_hit, and by the builds.
+    mesh: TracedMesh,
+    _instance_buffer: Buffer,
+    bottom_level: AccelerationStructure,
+    top_level: AccelerationStructure,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    _shader_binding_table: Buffer,
+    /// Where the raygen, miss and hit group records are in the table.
+    regions: [vk::StridedDeviceAddressRegionKHR; 3],
+    /// Made again along with the swapchain, at its size.
+    image: Image,
+    extent: vk::Extent2D,
+}
+
+impl RayTracer {
+    /// `device` has to be created with the ray tracing extensions, and
+    /// `allocator` has to give buffers addresses. `code` is raytrace.rgen,
+    /// raytrace.rmiss and raytrace.rchit, in that order. Building the acceleration
+    /// structures is recorded into `command_buffer`, with the scratch buffer
+    /// that's returned. It has to be kept until the command buffer is done.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        properties2: &Properties2,
+        physical_device: vk::PhysicalDevice,
+        pipeline_cache: vk::PipelineCache,
+        code: &[Vec<u32>; 3],
+        mesh: TracedMesh,
+        extent: vk::Extent2D,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(Self, Buffer), String> {
+        let acceleration_structures = AccelerationStructureLoader::new(instance, device);
+        let ray_tracing = RayTracingPipelineLoader::new(instance, device);
+        let addresses = DeviceAddresses::new(BufferDeviceAddressLoader::new(instance, device));
+
+        let descriptor_set_layout = Self::create_descriptor_set_layout(device);
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+            offset: 0,
+            size: std::mem::size_of::<CameraConstants>() as u32,
+        }];
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_set_layout,
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&layout_info, None)
+                .expect("Failed to create ray tracing Pipeline Layout!")
+        };
+        let pipeline = match Self::create_pipeline(
+            device,
+            &ray_tracing,
+            pipeline_cache,
+            code,
+            pipeline_layout,
+        ) {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                unsafe {
+                    device.destroy_pipeline_layout(pipeline_layout, None);
+                    device.destroy_descriptor_set_layout(descriptor_set_layout, None);
+                }
+                return Err(error);
+            }
+        };
+        let properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR =
+            unsafe { properties2.properties(physical_device) };
+        let (shader_binding_table, regions) = Self::create_shader_binding_table(
+            device,
+            allocator,
+            &ray_tracing,
+            &addresses,
+            pipeline,
+            &properties,
+        );
+
+        let vertex_address = addresses.of(mesh.vertex_buffer.buffer);
+        let index_address = addresses.of(mesh.index_buffer.buffer);
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR {
+            vertex_format: vk::Format::R32G32B32_SFLOAT,
+            // The position comes first in a vertex
+            vertex_data: vk::DeviceOrHostAddressConstKHR {
+                device_address: vertex_address,
+            },
+            vertex_stride: std::mem::size_of::<Vertex>() as vk::DeviceSize,
+            max_vertex: mesh.vertex_count - 1,
+            index_type: vk::IndexType::UINT32,
+            index_data: vk::DeviceOrHostAddressConstKHR {
+                device_address: index_address,
+            },
+            ..Default::default()
+        };
+        let triangle_geometry = vk::AccelerationStructureGeometryKHR {
+            geometry_type: vk::GeometryTypeKHR::TRIANGLES,
+            geometry: vk::AccelerationStructureGeometryDataKHR { triangles },
+            // No any-hit shader to skip triangles with
+            flags: vk::GeometryFlagsKHR::OPAQUE,
+            ..Default::default()
+        };
+        let mut bottom_level_info = vk::AccelerationStructureBuildGeometryInfoKHR {
+            ty: vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
+            geometry_count: 1,
+            p_geometries: &triangle_geometry,
+            ..Default::default()
+        };
+        let bottom_level_sizes = unsafe {
+            acceleration_structures.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &bottom_level_info,
+                &[mesh.triangle_count],
+            )
+        };
+        let bottom_level = Self::create_acceleration_structure(
+            device,
+            allocator,
+            &acceleration_structures,
+            "bottom_level_acceleration_structure",
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            bottom_level_sizes.acceleration_structure_size,
+        );
+
+        // The mesh as it is, traced in its own space
+        let instances = [vk::AccelerationStructureInstanceKHR {
+            transform: vk::TransformMatrixKHR {
+                matrix: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            },
+            instance_custom_index_and_mask: vk::Packed24_8::new(0, 0xff),
+            // The quad is seen from both sides
+            instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                0,
+                vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+            ),
+            acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                device_handle: bottom_level.address,
+            },
+        }];
+        let instance_buffer = Self::create_host_buffer(
+            device,
+            allocator,
+            "acceleration_structure_instances",
+            &instances,
+            mesh_usage(),
+        );
+        let instance_geometry = vk::AccelerationStructureGeometryKHR {
+            geometry_type: vk::GeometryTypeKHR::INSTANCES,
+            geometry: vk::AccelerationStructureGeometryDataKHR {
+                instances: vk::AccelerationStructureGeometryInstancesDataKHR {
+                    data: vk::DeviceOrHostAddressConstKHR {
+                        device_address: addresses.of(instance_buffer.buffer),
+                    },
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        };
+        let mut top_level_info = vk::AccelerationStructureBuildGeometryInfoKHR {
+            ty: vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
+            geometry_count: 1,
+            p_geometries: &instance_geometry,
+            ..Default::default()
+        };
+        let top_level_sizes = unsafe {
+            acceleration_structures.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &top_level_info,
+                &[instances.len() as u32],
+            )
+        };
+        let top_level = Self::create_acceleration_structure(
+            device,
+            allocator,
+            &acceleration_structures,
+            "top_level_acceleration_structure",
+            vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            top_level_sizes.acceleration_structure_size,
+        );
+
+        // One scratch buffer is enough, the builds run one after the other
+        let scratch_alignment = unsafe {
+            properties2
+                .properties::<vk::PhysicalDeviceAccelerationStructurePropertiesKHR>(physical_device)
+        }
+        .min_acceleration_structure_scratch_offset_alignment;
+        let scratch_size = bottom_level_sizes
+            .build_scratch_size
+            .max(top_level_sizes.build_scratch_size);
+        let scratch_buffer = VulkanApp::create_buffer(
+            device,
+            allocator,
+            "acceleration_structure_scratch",
+            scratch_size + u64::from(scratch_alignment),
+            vk::BufferUsageFlags::STORAGE_BUFFER | device_address::USAGE,
+            MemoryLocation::GpuOnly,
+        );
+        let scratch_address = align_up(
+            addresses.of(scratch_buffer.buffer),
+            u64::from(scratch_alignment),
+        );
+        bottom_level_info.dst_acceleration_structure = bottom_level.handle;
+        bottom_level_info.scratch_data = vk::DeviceOrHostAddressKHR {
+            device_address: scratch_address,
+        };
+        top_level_info.dst_acceleration_structure = top_level.handle;
+        top_level_info.scratch_data = vk::DeviceOrHostAddressKHR {
+            device_address: scratch_address,
+        };
+        let bottom_level_ranges = [vk::AccelerationStructureBuildRangeInfoKHR {
+            primitive_count: mesh.triangle_count,
+            ..Default::default()
+        }];
+        let top_level_ranges = [vk::AccelerationStructureBuildRangeInfoKHR {
+            primitive_count: instances.len() as u32,
+            ..Default::default()
+        }];
+        // The top level build reads the bottom level structure, and reuses
+        // the scratch memory its build wrote
+        let build_barrier = vk::MemoryBarrier {
+            src_access_mask: vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
+            dst_access_mask: vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR
+                | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
+            ..Default::default()
+        };
+        let build_stage = vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR;
+        unsafe {
+            acceleration_structures.cmd_build_acceleration_structures(
+                command_buffer,
+                &[bottom_level_info],
+                &[&bottom_level_ranges],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                build_stage,
+                build_stage,
+                vk::DependencyFlags::empty(),
+                &[build_barrier],
+                &[],
+                &[],
+            );
+            acceleration_structures.cmd_build_acceleration_structures(
+                command_buffer,
+                &[top_level_info],
+                &[&top_level_ranges],
+            );
+        }
+
+        let (descriptor_pool, descriptor_set) =
+            Self::create_descriptor_set(device, descriptor_set_layout);
+        let image = Self::create_image(device, allocator, extent);
+        let ray_tracer = RayTracer {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            acceleration_structures,
+            ray_tracing,
+            mesh,
+            _instance_buffer: instance_buffer,
+            bottom_level,
+            top_level,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            _shader_binding_table: shader_binding_table,
+            regions,
+            image,
+            extent,
+        };
+        ray_tracer.write_descriptor_set();
+        Ok((ray_tracer, scratch_buffer))
+    }
+
+    fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
+        let binding = |binding, descriptor_type, stage_flags| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type,
+            descriptor_count: 1,
+            stage_flags,
+            ..Default::default()
+        };
+        let bindings = [
+            binding(
+                0,
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+            ),
+            binding(
+                1,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+            ),
+            binding(
+                2,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            ),
+            binding(
+                3,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            ),
+        ];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create ray tracing Descriptor Set Layout!")
+        }
+    }
+
+    fn create_descriptor_set(
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> (vk::DescriptorPool, vk::DescriptorSet) {
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 2,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create ray tracing Descriptor Pool!")
+        };
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool: pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &layout,
+            ..Default::default()
+        };
+        let sets = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate ray tracing Descriptor Set!")
+        };
+        (pool, sets[0])
+    }
+
+    /// Points the descriptor set at everything, and at the image again
+    /// after `recreate`.
+    fn write_descriptor_set(&self) {
+        let mut scene_info = vk::WriteDescriptorSetAccelerationStructureKHR {
+            acceleration_structure_count: 1,
+            p_acceleration_structures: &self.top_level.handle,
+            ..Default::default()
+        };
+        let image_info = vk::DescriptorImageInfo {
+            image_view: self.image.view,
+            image_layout: vk::ImageLayout::GENERAL,
+            ..Default::default()
+        };
+        let buffer_infos = [&self.mesh.vertex_buffer, &self.mesh.index_buffer].map(|buffer| {
+            vk::DescriptorBufferInfo {
+                buffer: buffer.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }
+        });
+        let writes = [
+            vk::WriteDescriptorSet {
+                // The acceleration structure goes in the chained struct
+                p_next: &mut scene_info as *mut _ as *const std::ffi::c_void,
+                dst_set: self.descriptor_set,
+                dst_binding: 0,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: 1,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                p_image_info: &image_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: 2,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                p_buffer_info: &buffer_infos[0],
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: self.descriptor_set,
+                dst_binding: 3,
+                descriptor_count: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                p_buffer_info: &buffer_infos[1],
+                ..Default::default()
+            },
+        ];
+        unsafe {
+            self.device.update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        ray_tracing: &RayTracingPipelineLoader,
+        pipeline_cache: vk::PipelineCache,
+        code: &[Vec<u32>; 3],
+        layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline, String> {
+        let mut shader_modules = Vec::with_capacity(code.len());
+        for code in code {
+            match VulkanApp::create_shader_module(device, code) {
+                Ok(shader_module) => shader_modules.push(shader_module),
+                Err(error) => {
+                    for &shader_module in &shader_modules {
+                        unsafe {
+                            device.destroy_shader_module(shader_module, None);
+                        }
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        let main_function_name = CString::new("main").unwrap();
+        let stage_flags = [
+            vk::ShaderStageFlags::RAYGEN_KHR,
+            vk::ShaderStageFlags::MISS_KHR,
+            vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        ];
+        let stages: Vec<_> = stage_flags
+            .iter()
+            .zip(&shader_modules)
+            .map(|(&stage, &module)| vk::PipelineShaderStageCreateInfo {
+                stage,
+                module,
+                p_name: main_function_name.as_ptr(),
+                ..Default::default()
+            })
+            .collect();
+        let group =
+            |ty, general_shader, closest_hit_shader| vk::RayTracingShaderGroupCreateInfoKHR {
+                ty,
+                general_shader,
+                closest_hit_shader,
+                any_hit_shader: vk::SHADER_UNUSED_KHR,
+                intersection_shader: vk::SHADER_UNUSED_KHR,
+                ..Default::default()
+            };
+        // In the order of the shader binding table
+        let groups = [
+            group(
+                vk::RayTracingShaderGroupTypeKHR::GENERAL,
+                0,
+                vk::SHADER_UNUSED_KHR,
+            ),
+            group(
+                vk::RayTracingShaderGroupTypeKHR::GENERAL,
+                1,
+                vk::SHADER_UNUSED_KHR,
+            ),
+            group(
+                vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP,
+                vk::SHADER_UNUSED_KHR,
+                2,
+            ),
+        ];
+        let pipeline_info = vk::RayTracingPipelineCreateInfoKHR {
+            stage_count: stages.len() as u32,
+            p_stages: stages.as_ptr(),
+            group_count: groups.len() as u32,
+            p_groups: groups.as_ptr(),
+            // Hits don't trace rays of their own
+            max_pipeline_ray_recursion_depth: 1,
+            layout,
+            ..Default::default()
+        };
+        let result = unsafe {
+            ray_tracing.create_ray_tracing_pipelines(
+                vk::DeferredOperationKHR::null(),
+                pipeline_cache,
+                &[pipeline_info],
+                None,
+            )
+        };
+        for shader_module in shader_modules {
+            unsafe {
+                device.destroy_shader_module(shader_module, None);
+            }
+        }
+        result
+            .map(|pipelines| pipelines[0])
+            .map_err(|error| format!("Failed to create ray tracing Pipeline: {:?}", error))
+    }
+
+    /// Copies the shader group handles of `pipeline` into a table laid out
+    /// by `table_layout`, returning it with the regions to trace rays with.
+    fn create_shader_binding_table(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        ray_tracing: &RayTracingPipelineLoader,
+        addresses: &DeviceAddresses,
+        pipeline: vk::Pipeline,
+        properties: &vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
+    ) -> (Buffer, [vk::StridedDeviceAddressRegionKHR; 3]) {
+        let handle_size = properties.shader_group_handle_size as usize;
+        let base_alignment = u64::from(properties.shader_group_base_alignment);
+        let layout = table_layout(
+            properties.shader_group_handle_size,
+            properties.shader_group_handle_alignment,
+            properties.shader_group_base_alignment,
+        );
+        let handles = unsafe {
+            ray_tracing
+                .get_ray_tracing_shader_group_handles(pipeline, 0, 3, 3 * handle_size)
+                .expect("Failed to get ray tracing shader group handles!")
+        };
+        // Buffers are only aligned as far as their memory requirements say,
+        // so leave room to start the table at the base alignment
+        let table = VulkanApp::create_buffer(
+            device,
+            allocator,
+            "shader_binding_table",
+            layout.size + base_alignment,
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | device_address::USAGE,
+            MemoryLocation::CpuToGpu,
+        );
+        let address = addresses.of(table.buffer);
+        let start = align_up(address, base_alignment);
+        let mapped = table
+            .mapped_ptr()
+            .expect("Shader binding table memory isn't host visible!")
+            .as_ptr() as *mut u8;
+        for (group, &offset) in layout.offsets.iter().enumerate() {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    handles[group * handle_size..].as_ptr(),
+                    mapped.add((start - address + offset) as usize),
+                    handle_size,
+                );
+            }
+        }
+        let regions = layout
+            .offsets
+            .map(|offset| vk::StridedDeviceAddressRegionKHR {
+                device_address: start + offset,
+                stride: layout.stride,
+                size: layout.stride,
+            });
+        (table, regions)
+    }
+
+    /// Makes room for an acceleration structure of `size` bytes, which still
+    /// has to be built.
+    fn create_acceleration_structure(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        loader: &AccelerationStructureLoader,
+        name: &str,
+        ty: vk::AccelerationStructureTypeKHR,
+        size: vk::DeviceSize,
+    ) -> AccelerationStructure {
+        let buffer = VulkanApp::create_buffer(
+            device,
+            allocator,
+            name,
+            size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR,
+            MemoryLocation::GpuOnly,
+        );
+        let create_info = vk::AccelerationStructureCreateInfoKHR {
+            buffer: buffer.buffer,
+            size,
+            ty,
+            ..Default::default()
+        };
+        let handle = unsafe {
+            loader
+                .create_acceleration_structure(&create_info, None)
+                .expect("Failed to create Acceleration Structure!")
+        };
+        let address_info = vk::AccelerationStructureDeviceAddressInfoKHR {
+            acceleration_structure: handle,
+            ..Default::default()
+        };
+        AccelerationStructure {
+            handle,
+            address: unsafe { loader.get_acceleration_structure_device_address(&address_info) },
+            _buffer: buffer,
+        }
+    }
+
+    /// A host visible buffer with `data` in it, which has an address.
+    fn create_host_buffer<T: Copy>(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        name: &str,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> Buffer {
+        let buffer = VulkanApp::create_buffer(
+            device,
+            allocator,
+            name,
+            std::mem::size_of_val(data) as vk::DeviceSize,
+            usage | device_address::USAGE,
+            MemoryLocation::CpuToGpu,
+        );
+        let mapped = buffer
+            .mapped_ptr()
+            .unwrap_or_else(|| panic!("{} memory isn't host visible!", name));
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped.as_ptr() as *mut T, data.len());
+        }
+        buffer
+    }
+
+    /// The image raytrace.rgen writes, `extent` in size.
+    fn create_image(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        extent: vk::Extent2D,
+    ) -> Image {
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format: IMAGE_FORMAT,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+        let image = unsafe {
+            device
+                .create_image(&image_info, None)
+                .expect("Failed to create ray tracing Image!")
+        };
+        let requirements = unsafe { device.get_image_memory_requirements(image) };
+        let allocation = allocator::allocate(
+            allocator,
+            "ray_tracing_image",
+            requirements,
+            MemoryLocation::GpuOnly,
+            false,
+        );
+        unsafe {
+            device
+                .bind_image_memory(image, allocator::memory(&allocation), allocation.offset())
+                .expect("Failed to bind ray tracing Image memory!");
+        }
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type: vk::ImageViewType::TYPE_2D,
+            format: IMAGE_FORMAT,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()
+        };
+        let view = unsafe {
+            device
+                .create_image_view(&view_info, None)
+                .expect("Failed to create ray tracing Image View!")
+        };
+        Image::new(
+            device,
+            allocator,
+            image,
+            view,
+            ImageMemory::Allocation(allocation),
+        )
+    }
+
+    /// Makes the image again at `extent`, once the swapchain changed size.
+    /// Nothing may be using the old one anymore.
+    pub(crate) fn recreate(&mut self, extent: vk::Extent2D) {
+        self.image = Self::create_image(&self.device, &self.allocator, extent);
+        self.extent = extent;
+        self.write_descriptor_set();
+    }
+
+    /// Traces the mesh as `proj` and `view_model` would draw it, and blits
+    /// the result onto swapchain image `target`. That's left in
+    /// `PRESENT_SRC_KHR`, ready for the passes that go after the main pass,
+    /// which this replaces.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: vk::Image,
+        proj: Mat4,
+        view_model: Mat4,
+    ) {
+        let device = &self.device;
+        let camera = CameraConstants {
+            inverse_proj: proj.inverse().to_cols_array_2d(),
+            inverse_view_model: view_model.inverse().to_cols_array_2d(),
+        };
+        let image = self.image.image;
+        let barrier = |image, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier {
+                src_access_mask,
+                dst_access_mask,
+                old_layout,
+                new_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            }
+        };
+        // What's in the image is thrown away, once the previous frame's blit
+        // is done reading it
+        let trace_barrier = barrier(
+            image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        // The swapchain image waits for the stage draw_frame waits for it in,
+        // like the render pass dependency
+        let blit_barriers = [
+            barrier(
+                image,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::SHADER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            barrier(
+                target,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+        ];
+        // The passes after this one start at the color attachment output
+        // stage too, or copy the image for a screenshot
+        let present_barrier = barrier(
+            target,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::empty(),
+        );
+        let corner = vk::Offset3D {
+            x: self.extent.width as i32,
+            y: self.extent.height as i32,
+            z: 1,
+        };
+        let layers = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let blit = vk::ImageBlit {
+            src_subresource: layers,
+            src_offsets: [vk::Offset3D::default(), corner],
+            dst_subresource: layers,
+            dst_offsets: [vk::Offset3D::default(), corner],
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[trace_barrier],
+            );
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::RAYGEN_KHR,
+                0,
+                camera.as_bytes(),
+            );
+            self.ray_tracing.cmd_trace_rays(
+                command_buffer,
+                &self.regions[0],
+                &self.regions[1],
+                &self.regions[2],
+                &vk::StridedDeviceAddressRegionKHR::default(),
+                self.extent.width,
+                self.extent.height,
+                1,
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &blit_barriers,
+            );
+            // Both are the same size, so nothing's filtered
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                target,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::NEAREST,
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[present_barrier],
+            );
+        }
+    }
+}
+
+impl Drop for RayTracer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.acceleration_structures
+                .destroy_acceleration_structure(self.top_level.handle, None);
+            self.acceleration_structures
+                .destroy_acceleration_structure(self.bottom_level.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_up_rounds_to_multiples() {
+        assert_eq!(align_up(0, 64), 0);
+        assert_eq!(align_up(1, 64), 64);
+        assert_eq!(align_up(64, 64), 64);
+        assert_eq!(align_up(65, 64), 128);
+    }
+
+    #[test]
+    fn table_regions_start_at_the_base_alignment() {
+        // Common on desktop GPUs
+        let layout = table_layout(32, 32, 64);
+        assert_eq!(layout.stride, 32);
+        assert_eq!(layout.offsets, [0, 64, 128]);
+        assert_eq!(layout.size, 160);
+        // Handles padded out, with regions already aligned by their stride
+        let layout = table_layout(24, 32, 32);
+        assert_eq!(layout.stride, 32);
+        assert_eq!(layout.offsets, [0, 32, 64]);
+        assert_eq!(layout.size, 96);
+    }
+}
@@ -0,0 +1,224 @@
+//! What gets drawn each frame, kept apart from how Vulkan is set up.
+
+use ash::vk;
+use glam::Mat4;
+
+use crate::{PushConstants, VERTEX_ADDRESS_OFFSET};
+
+/// Everything a [`Renderer`] gets to record a frame with. `command_buffer`
+/// is a secondary command buffer continuing the render pass, with the
+/// viewport and scissor covering `extent`. It's executed for every
+/// swapchain image, so `framebuffer` is null.
+pub struct FrameContext<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    /// The scene pipeline, or the wireframe one while that's toggled on.
+    /// With `--outline`, the renderer records its draws once more with the
+    /// outline pipeline, which only reads the positions of [`Vertex`](crate::Vertex)es.
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// This frame's uniform buffer with the camera matrices, and the
+    /// dynamic one with the object transforms, for set 0. Bind it with
+    /// [`FrameContext::bind_descriptor_set`].
+    pub descriptor_set: vk::DescriptorSet,
+    /// Per-instance offsets and colors the pipeline reads from binding 0.
+    pub instance_buffer: vk::Buffer,
+    pub instance_count: u32,
+    /// How many objects `VulkanApp::register_objects` made room for.
+    pub object_count: u32,
+    /// Bytes between the transforms of consecutive objects.
+    pub object_stride: u32,
+    /// The demo's mesh, `None` for the triangle that lives in shader.vert.
+    pub mesh: Option<MeshBuffers>,
+}
+
+impl FrameContext<'_> {
+    /// Binds `descriptor_set` at set 0, with the transform of `object` for
+    /// the draws that follow.
+    pub fn bind_descriptor_set(&self, object: u32) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[self.descriptor_set],
+                &[object * self.object_stride],
+            );
+        }
+    }
+}
+
+/// Vertex and index buffers of a mesh, and the buffer with the
+/// `vk::DrawIndexedIndirectCommand`s to draw it with.
+#[derive(Clone, Copy)]
+pub struct MeshBuffers {
+    pub vertex_buffer: vk::Buffer,
+    /// Where `vertex_buffer` is in device memory with `--vertex-pulling`,
+    /// for the scene pipeline to read the vertices from.
+    pub vertex_address: Option<vk::DeviceAddress>,
+    pub index_buffer: vk::Buffer,
+    /// 16-bit unless the mesh has too many vertices for them.
+    pub index_type: vk::IndexType,
+    pub index_count: u32,
+    pub indirect_buffer: vk::Buffer,
+    pub draw_count: u32,
+}
+
+impl MeshBuffers {
+    /// Binds the vertex and index buffers, or with `--vertex-pulling` only
+    /// the index buffer, and pushes the address of the vertices instead.
+    pub fn bind(&self, frame: &FrameContext) {
+        unsafe {
+            match self.vertex_address {
+                Some(address) => frame.device.cmd_push_constants(
+                    frame.command_buffer,
+                    frame.pipeline_layout,
+                    vk::ShaderStageFlags::VERTEX,
+                    VERTEX_ADDRESS_OFFSET,
+                    &address.to_ne_bytes(),
+                ),
+                None => frame.device.cmd_bind_vertex_buffers(
+                    frame.command_buffer,
+                    0,
+                    &[self.vertex_buffer],
+                    &[0],
+                ),
+            }
+            frame.device.cmd_bind_index_buffer(
+                frame.command_buffer,
+                self.index_buffer,
+                0,
+                self.index_type,
+            );
+        }
+    }
+}
+
+/// Records the draw commands of a frame. Swap it out with
+/// `VulkanApp::set_renderer` to draw something else.
+///
+/// What it records is reused by the frames that follow, until the scene
+/// changes or `VulkanApp::invalidate_recording` is called.
+pub trait Renderer {
+    fn record(&self, frame: &FrameContext);
+}
+
+/// Draws the triangle whose vertices are hardcoded in shader.vert, once per
+/// instance of each object.
+pub struct TriangleRenderer;
+
+impl Renderer for TriangleRenderer {
+    fn record(&self, frame: &FrameContext) {
+        // There's only the one triangle, so it sits at the scene origin
+        let push_constants = PushConstants {
+            model: Mat4::IDENTITY.to_cols_array_2d(),
+        };
+        unsafe {
+            frame.device.cmd_bind_pipeline(
+                frame.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                frame.pipeline,
+            );
+            frame.device.cmd_push_constants(
+                frame.command_buffer,
+                frame.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_constants.as_bytes(),
+            );
+            frame.device.cmd_bind_vertex_buffers(
+                frame.command_buffer,
+                0,
+                &[frame.instance_buffer],
+                &[0],
+            );
+            for object in 0..frame.object_count {
+                frame.bind_descriptor_set(object);
+                frame
+                    .device
+                    .cmd_draw(frame.command_buffer, 3, frame.instance_count, 0, 0);
+            }
+        }
+    }
+}
+
+/// Draws the mesh of `--demo quad` with its index buffer, the plain way:
+/// the index count goes straight into `cmd_draw_indexed`.
+pub struct QuadRenderer;
+
+impl Renderer for QuadRenderer {
+    fn record(&self, frame: &FrameContext) {
+        let mesh = match frame.mesh {
+            Some(mesh) => mesh,
+            None => return,
+        };
+        let push_constants = PushConstants {
+            model: Mat4::IDENTITY.to_cols_array_2d(),
+        };
+        unsafe {
+            frame.device.cmd_bind_pipeline(
+                frame.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                frame.pipeline,
+            );
+            frame.device.cmd_push_constants(
+                frame.command_buffer,
+                frame.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_constants.as_bytes(),
+            );
+            mesh.bind(frame);
+            for object in 0..frame.object_count {
+                frame.bind_descriptor_set(object);
+                frame
+                    .device
+                    .cmd_draw_indexed(frame.command_buffer, mesh.index_count, 1, 0, 0, 0);
+            }
+        }
+    }
+}
+
+/// Draws the mesh of `--demo cube` with its index buffer, reading the
+/// draw parameters from its indirect buffer.
+pub struct CubeRenderer;
+
+impl Renderer for CubeRenderer {
+    fn record(&self, frame: &FrameContext) {
+        let mesh = match frame.mesh {
+            Some(mesh) => mesh,
+            None => return,
+        };
+        let push_constants = PushConstants {
+            model: Mat4::IDENTITY.to_cols_array_2d(),
+        };
+        unsafe {
+            frame.device.cmd_bind_pipeline(
+                frame.command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                frame.pipeline,
+            );
+            frame.device.cmd_push_constants(
+                frame.command_buffer,
+                frame.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_constants.as_bytes(),
+            );
+            mesh.bind(frame);
+            for object in 0..frame.object_count {
+                frame.bind_descriptor_set(object);
+                frame.device.cmd_draw_indexed_indirect(
+                    frame.command_buffer,
+                    mesh.indirect_buffer,
+                    0,
+                    mesh.draw_count,
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                );
+            }
+        }
+    }
+}
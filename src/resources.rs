@@ -0,0 +1,146 @@
+//! Owning wrappers around Vulkan objects. Each one keeps a handle to the
+//! device it was created from, and to the allocator if it has memory, and
+//! destroys its object when dropped, so a new resource doesn't have to be
+//! slotted into `VulkanApp`'s `Drop` by hand.
+//!
+//! The allocator and the device have to outlive all of them, which is what
+//! `allocator` and `core` being the last fields of `VulkanApp` takes care of.
+
+use std::ffi::c_void;
+use std::ptr::NonNull;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+
+use crate::allocator::SharedAllocator;
+
+/// A buffer together with the memory bound to it.
+pub(crate) struct Buffer {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    pub(crate) buffer: vk::Buffer,
+    // Only `None` while being dropped, as freeing it takes it by value
+    allocation: Option<Allocation>,
+}
+
+impl Buffer {
+    /// Takes ownership of `buffer`, created from `device`, and `allocation`,
+    /// made by `allocator`.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        buffer: vk::Buffer,
+        allocation: Allocation,
+    ) -> Self {
+        Buffer {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            buffer,
+            allocation: Some(allocation),
+        }
+    }
+
+    /// Where the memory is mapped, if it's host visible. It stays mapped
+    /// for as long as the buffer lives.
+    pub(crate) fn mapped_ptr(&self) -> Option<NonNull<c_void>> {
+        self.allocation.as_ref()?.mapped_ptr()
+    }
+
+    pub(crate) fn mapped_slice(&self) -> Option<&[u8]> {
+        self.allocation.as_ref()?.mapped_slice()
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_buffer(self.buffer, None);
+        }
+        if let Some(allocation) = self.allocation.take() {
+            if let Err(error) = self.allocator.borrow_mut().free(allocation) {
+                eprintln!("Failed to free Buffer memory: {}", error);
+            }
+        }
+    }
+}
+
+/// Where the memory bound to an [`Image`] came from.
+pub(crate) enum ImageMemory {
+    Allocation(Allocation),
+    /// Allocated with `vkAllocateMemory` for this image alone, for memory
+    /// types the allocator doesn't hand out.
+    Dedicated(vk::DeviceMemory),
+}
+
+/// An image with the memory bound to it, and a view of the whole image.
+pub(crate) struct Image {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    pub(crate) image: vk::Image,
+    pub(crate) view: vk::ImageView,
+    // Only `None` while being dropped, as freeing it takes it by value
+    memory: Option<ImageMemory>,
+}
+
+impl Image {
+    /// Takes ownership of `image` and `view`, both created from `device`, and
+    /// `memory`, made by `allocator` unless it's dedicated.
+    pub(crate) fn new(
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        image: vk::Image,
+        view: vk::ImageView,
+        memory: ImageMemory,
+    ) -> Self {
+        Image {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            image,
+            view,
+            memory: Some(memory),
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+            self.device.destroy_image(self.image, None);
+        }
+        match self.memory.take() {
+            Some(ImageMemory::Allocation(allocation)) => {
+                if let Err(error) = self.allocator.borrow_mut().free(allocation) {
+                    eprintln!("Failed to free Image memory: {}", error);
+                }
+            }
+            Some(ImageMemory::Dedicated(memory)) => unsafe {
+                self.device.free_memory(memory, None);
+            },
+            None => {}
+        }
+    }
+}
+
+pub(crate) struct Pipeline {
+    device: ash::Device,
+    pub(crate) pipeline: vk::Pipeline,
+}
+
+impl Pipeline {
+    /// Takes ownership of `pipeline`, created from `device`.
+    pub(crate) fn new(device: &ash::Device, pipeline: vk::Pipeline) -> Self {
+        Pipeline {
+            device: device.clone(),
+            pipeline,
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
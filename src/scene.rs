@@ -0,0 +1,380 @@
+//! Meshes added and removed at runtime with `VulkanApp::add_object`, each
+//! with buffers and a transform of its own. They're drawn after whatever
+//! the [`Renderer`](crate::Renderer) draws.
+
+use std::collections::HashSet;
+
+use ash::vk;
+use glam::{Mat4, Vec3};
+
+use crate::frustum::{BoundingSphere, CullStats, Frustum};
+use crate::mesh::{Mesh, Vertex};
+use crate::occlusion::OcclusionBox;
+use crate::pipelines::{Stencil, Topology};
+use crate::renderer::{FrameContext, MeshBuffers};
+use crate::texture_array::{TextureArray, TextureId, TexturedPushConstants};
+use crate::PushConstants;
+
+/// Names an object from `VulkanApp::add_object`. Never reused, so a stale
+/// id doesn't end up moving or removing some other object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(u64);
+
+pub(crate) struct RenderObject {
+    pub(crate) id: ObjectId,
+    /// Kept to upload again if the device is lost.
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) transform: Mat4,
+    /// `None` for the vertex colors.
+    pub(crate) texture: Option<TextureId>,
+    /// What `indices` make up.
+    pub(crate) topology: Topology,
+    pub(crate) stencil: Stencil,
+    pub(crate) mesh: Mesh,
+    /// Around `vertices`, for frustum culling.
+    pub(crate) bounds: BoundingSphere,
+}
+
+pub(crate) struct Scene {
+    objects: Vec<RenderObject>,
+    next_id: u64,
+    /// The objects `cull` last found outside the frustum, left out of
+    /// `draws`.
+    culled: HashSet<ObjectId>,
+    /// The objects `--occlusion-culling` last found hidden, also left out
+    /// of `draws`.
+    occluded: HashSet<ObjectId>,
+}
+
+impl Scene {
+    pub(crate) fn new() -> Self {
+        Scene {
+            objects: vec![],
+            next_id: 0,
+            culled: HashSet::new(),
+            occluded: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub(crate) fn add(
+        &mut self,
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+        transform: Mat4,
+        mesh: Mesh,
+    ) -> ObjectId {
+        let id = ObjectId(self.next_id);
+        self.next_id += 1;
+        self.insert(RenderObject {
+            id,
+            bounds: BoundingSphere::around(&vertices),
+            vertices,
+            indices,
+            transform,
+            texture: None,
+            topology: Topology::Triangles,
+            stencil: Stencil::Off,
+            mesh,
+        });
+        id
+    }
+
+    /// Puts back an object taken out with `drain`.
+    pub(crate) fn insert(&mut self, object: RenderObject) {
+        self.next_id = self.next_id.max(object.id.0 + 1);
+        self.objects.push(object);
+    }
+
+    /// Takes all objects out.
+    pub(crate) fn drain(&mut self) -> Vec<RenderObject> {
+        self.culled.clear();
+        self.occluded.clear();
+        std::mem::take(&mut self.objects)
+    }
+
+    /// Returns false if there's no object `id`.
+    pub(crate) fn set_transform(&mut self, id: ObjectId, transform: Mat4) -> bool {
+        match self.objects.iter_mut().find(|object| object.id == id) {
+            Some(object) => {
+                object.transform = transform;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there's no object `id`.
+    pub(crate) fn set_texture(&mut self, id: ObjectId, texture: Option<TextureId>) -> bool {
+        match self.objects.iter_mut().find(|object| object.id == id) {
+            Some(object) => {
+                object.texture = texture;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there's no object `id`.
+    pub(crate) fn set_topology(&mut self, id: ObjectId, topology: Topology) -> bool {
+        match self.objects.iter_mut().find(|object| object.id == id) {
+            Some(object) => {
+                object.topology = topology;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there's no object `id`.
+    pub(crate) fn set_stencil(&mut self, id: ObjectId, stencil: Stencil) -> bool {
+        match self.objects.iter_mut().find(|object| object.id == id) {
+            Some(object) => {
+                object.stencil = stencil;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether any object is drawn as `topology` with `stencil`.
+    pub(crate) fn uses(&self, topology: Topology, stencil: Stencil) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.topology == topology && object.stencil == stencil)
+    }
+
+    /// Stops drawing object `id`, handing it back so its buffers can be
+    /// freed once the frames in flight are done with them.
+    pub(crate) fn remove(&mut self, id: ObjectId) -> Option<RenderObject> {
+        let index = self.objects.iter().position(|object| object.id == id)?;
+        self.culled.remove(&id);
+        self.occluded.remove(&id);
+        Some(self.objects.remove(index))
+    }
+
+    /// Finds the objects that are outside all of `frustums` once they're
+    /// moved by `parent` along with their own transform. Returns whether
+    /// that changed which objects `draws` has.
+    pub(crate) fn cull(&mut self, frustums: &[Frustum], parent: Mat4) -> bool {
+        let culled: HashSet<ObjectId> = self
+            .objects
+            .iter()
+            .filter(|object| {
+                let bounds = object.bounds.transformed(parent * object.transform);
+                !frustums.iter().any(|frustum| frustum.intersects(&bounds))
+            })
+            .map(|object| object.id)
+            .collect();
+        let changed = culled != self.culled;
+        self.culled = culled;
+        changed
+    }
+
+    /// Has `draws` leave out `occluded` as well as what `cull` did. Returns
+    /// whether that changed which objects it has.
+    pub(crate) fn set_occluded(&mut self, occluded: HashSet<ObjectId>) -> bool {
+        let changed = occluded != self.occluded;
+        self.occluded = occluded;
+        changed
+    }
+
+    /// The boxes around the objects `cull` kept, to tell which are
+    /// occluded, hidden or not.
+    pub(crate) fn occlusion_boxes(&self) -> Vec<OcclusionBox> {
+        self.objects
+            .iter()
+            .filter(|object| !self.culled.contains(&object.id))
+            .map(|object| OcclusionBox {
+                id: object.id,
+                model: object.transform
+                    * Mat4::from_translation(object.bounds.center)
+                    * Mat4::from_scale(Vec3::splat(object.bounds.radius)),
+            })
+            .collect()
+    }
+
+    /// How many objects `draws` has, and how many `cull` and occlusion
+    /// culling left out.
+    pub(crate) fn cull_stats(&self) -> CullStats {
+        let occluded = self
+            .occluded
+            .iter()
+            .filter(|&id| !self.culled.contains(id))
+            .count();
+        CullStats {
+            drawn: self.objects.len() - self.culled.len() - occluded,
+            culled: self.culled.len(),
+            occluded,
+        }
+    }
+
+    fn is_drawn(&self, id: ObjectId) -> bool {
+        !self.culled.contains(&id) && !self.occluded.contains(&id)
+    }
+
+    /// What each object that's neither culled nor occluded is drawn with,
+    /// to hand to [`record`].
+    pub(crate) fn draws(&self) -> Vec<ObjectDraw> {
+        self.objects
+            .iter()
+            .filter(|object| self.is_drawn(object.id))
+            .map(|object| ObjectDraw {
+                mesh: object.mesh.buffers(),
+                index_count: object.indices.len() as u32,
+                model: object.transform.to_cols_array_2d(),
+                texture: object.texture.map_or(-1, |texture| texture.0 as i32),
+                topology: object.topology,
+                stencil: object.stencil,
+            })
+            .collect()
+    }
+}
+
+/// An object's buffers and push constants. It's plain handles and values,
+/// so objects can be recorded on other threads.
+#[derive(Clone, Copy)]
+pub(crate) struct ObjectDraw {
+    mesh: MeshBuffers,
+    index_count: u32,
+    model: [[f32; 4]; 4],
+    /// The `TextureId`, or -1 for the vertex colors.
+    texture: i32,
+    topology: Topology,
+    stencil: Stencil,
+}
+
+/// The pipelines objects are drawn with, one for each topology and stencil
+/// mode. Only the ones some object is drawn with are set, the others are
+/// null.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) struct ObjectPipelines([vk::Pipeline; Topology::ALL.len() * Stencil::ALL.len()]);
+
+impl ObjectPipelines {
+    pub(crate) fn set(&mut self, topology: Topology, stencil: Stencil, pipeline: vk::Pipeline) {
+        self.0[Self::index(topology, stencil)] = pipeline;
+    }
+
+    fn get(&self, topology: Topology, stencil: Stencil) -> vk::Pipeline {
+        self.0[Self::index(topology, stencil)]
+    }
+
+    fn index(topology: Topology, stencil: Stencil) -> usize {
+        topology as usize * Stencil::ALL.len() + stencil as usize
+    }
+}
+
+/// The pipelines objects are drawn with, and what they're bound with.
+#[derive(Clone, Copy)]
+pub(crate) struct ObjectBindings {
+    pipelines: ObjectPipelines,
+    /// The layout of the texture array, its descriptor set and the stages
+    /// its push constants are for, if there are any textures.
+    textures: Option<(vk::PipelineLayout, vk::DescriptorSet, vk::ShaderStageFlags)>,
+}
+
+impl ObjectBindings {
+    /// `pipelines` read [`Vertex`]es. They're textured.vert pipelines with
+    /// the layout of `textures` if there are any, or ones with the scene's
+    /// layout otherwise.
+    pub(crate) fn new(pipelines: ObjectPipelines, textures: Option<&TextureArray>) -> Self {
+        ObjectBindings {
+            pipelines,
+            textures: textures.map(|textures| {
+                (
+                    textures.pipeline_layout,
+                    textures.descriptor_set,
+                    textures.push_constant_stages,
+                )
+            }),
+        }
+    }
+}
+
+/// Draws `draws` with `bindings`.
+pub(crate) fn record(frame: &FrameContext, bindings: ObjectBindings, draws: &[ObjectDraw]) {
+    if draws.is_empty() {
+        return;
+    }
+    // Placed by the push constants alone, on top of the scene rotation.
+    // Object 0 of the dynamic uniform buffer stays put unless moved.
+    let (pipeline_layout, push_constant_stages) = match bindings.textures {
+        Some((pipeline_layout, descriptor_set, push_constant_stages)) => {
+            unsafe {
+                frame.device.cmd_bind_descriptor_sets(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    0,
+                    &[frame.descriptor_set, descriptor_set],
+                    &[0],
+                );
+            }
+            (pipeline_layout, push_constant_stages)
+        }
+        None => {
+            frame.bind_descriptor_set(0);
+            (frame.pipeline_layout, vk::ShaderStageFlags::VERTEX)
+        }
+    };
+    // The pipelines share a layout, so what's bound stays bound when they're
+    // switched between
+    let mut bound_pipeline = vk::Pipeline::null();
+    for draw in draws {
+        let pipeline = bindings.pipelines.get(draw.topology, draw.stencil);
+        if pipeline != bound_pipeline {
+            unsafe {
+                frame.device.cmd_bind_pipeline(
+                    frame.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+            }
+            bound_pipeline = pipeline;
+        }
+        let textured_push_constants;
+        let push_constants = PushConstants { model: draw.model };
+        let push_constant_bytes = if bindings.textures.is_some() {
+            textured_push_constants = TexturedPushConstants {
+                model: draw.model,
+                texture: draw.texture,
+            };
+            textured_push_constants.as_bytes()
+        } else {
+            push_constants.as_bytes()
+        };
+        let mesh = draw.mesh;
+        unsafe {
+            frame.device.cmd_push_constants(
+                frame.command_buffer,
+                pipeline_layout,
+                push_constant_stages,
+                0,
+                push_constant_bytes,
+            );
+            frame.device.cmd_bind_vertex_buffers(
+                frame.command_buffer,
+                0,
+                &[mesh.vertex_buffer],
+                &[0],
+            );
+            frame.device.cmd_bind_index_buffer(
+                frame.command_buffer,
+                mesh.index_buffer,
+                0,
+                mesh.index_type,
+            );
+            frame
+                .device
+                .cmd_draw_indexed(frame.command_buffer, draw.index_count, 1, 0, 0, 0);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! The scene's draws, recorded once into secondary command buffers per
+//! frame in flight and executed by each frame's primary command buffer,
+//! instead of being recorded all over again every frame.
+//!
+//! A frame slot's draws are split into lanes, each with a command buffer
+//! of its own, executed in order. Every lane has its own command pool, as
+//! pools can't be used from more than one thread at a time, so lanes can
+//! be recorded on different threads, see `--threads`.
+//!
+//! A recording is kept for as long as what it was recorded with stays the
+//! same, which is what its key describes, and until it's invalidated by a
+//! change the key doesn't cover, like an object being added.
+
+use std::cell::Cell;
+
+use ash::vk;
+
+pub(crate) struct SecondaryCommands<K> {
+    device: ash::Device,
+    command_pools: Vec<vk::CommandPool>,
+    /// Per frame slot, the command buffer of each lane.
+    command_buffers: Vec<Vec<vk::CommandBuffer>>,
+    /// What each frame slot's command buffers were last recorded for,
+    /// `None` if they have to be recorded again.
+    recorded: Vec<Cell<Option<K>>>,
+}
+
+impl<K: Copy + PartialEq> SecondaryCommands<K> {
+    /// Allocates `lane_count` command buffers for each of `frame_count`
+    /// frame slots, to be executed on queues of `queue_family`.
+    pub(crate) fn new(
+        device: &ash::Device,
+        queue_family: u32,
+        frame_count: usize,
+        lane_count: usize,
+    ) -> Self {
+        let pool_create_info = vk::CommandPoolCreateInfo {
+            // Each is reset on its own when it's recorded again
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: queue_family,
+            ..Default::default()
+        };
+        let command_pools: Vec<vk::CommandPool> = (0..lane_count)
+            .map(|_| unsafe {
+                device
+                    .create_command_pool(&pool_create_info, None)
+                    .expect("Failed to create secondary Command Pool!")
+            })
+            .collect();
+        let lane_buffers: Vec<Vec<vk::CommandBuffer>> = command_pools
+            .iter()
+            .map(|&command_pool| {
+                let allocate_info = vk::CommandBufferAllocateInfo {
+                    command_pool,
+                    level: vk::CommandBufferLevel::SECONDARY,
+                    command_buffer_count: frame_count as u32,
+                    ..Default::default()
+                };
+                unsafe {
+                    device
+                        .allocate_command_buffers(&allocate_info)
+                        .expect("Failed to allocate secondary Command Buffers!")
+                }
+            })
+            .collect();
+        let command_buffers = (0..frame_count)
+            .map(|frame| lane_buffers.iter().map(|buffers| buffers[frame]).collect())
+            .collect();
+        SecondaryCommands {
+            device: device.clone(),
+            command_pools,
+            command_buffers,
+            recorded: (0..frame_count).map(|_| Cell::new(None)).collect(),
+        }
+    }
+
+    /// Has every command buffer recorded again the next time it's used.
+    pub(crate) fn invalidate(&self) {
+        for recorded in &self.recorded {
+            recorded.set(None);
+        }
+    }
+
+    /// The command buffers of frame slot `frame`, one per lane.
+    pub(crate) fn command_buffers(&self, frame: usize) -> &[vk::CommandBuffer] {
+        &self.command_buffers[frame]
+    }
+
+    /// Begins recording the command buffers of frame slot `frame` for
+    /// `key`, unless they're recorded for it already, in which case this
+    /// returns false. The slot's previous frame has to be done.
+    /// `inheritance` is the rendering they're executed within.
+    ///
+    /// Every one of them has to be recorded and ended after this, each on
+    /// one thread at a time.
+    pub(crate) fn begin(
+        &self,
+        frame: usize,
+        key: K,
+        inheritance: &vk::CommandBufferInheritanceInfo,
+    ) -> bool {
+        if self.recorded[frame].get() == Some(key) {
+            return false;
+        }
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            p_inheritance_info: inheritance,
+            ..Default::default()
+        };
+        for &command_buffer in &self.command_buffers[frame] {
+            unsafe {
+                self.device
+                    .begin_command_buffer(command_buffer, &begin_info)
+                    .expect("Failed to begin recording secondary Command Buffer!");
+            }
+        }
+        self.recorded[frame].set(Some(key));
+        true
+    }
+}
+
+impl<K> Drop for SecondaryCommands<K> {
+    fn drop(&mut self) {
+        unsafe {
+            for &command_pool in &self.command_pools {
+                self.device.destroy_command_pool(command_pool, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! The `--skybox` cubemap, drawn behind everything else. It has its own
+//! descriptor set for the cubemap, on top of the scene's camera set.
+
+use std::path::Path;
+
+use ash::vk;
+
+use crate::resources::Image;
+use crate::texture::{self, BlockFormat};
+
+/// The files a skybox directory has to contain, in cubemap layer order,
+/// each either a .dds or a .png.
+pub(crate) const FACE_NAMES: [&str; 6] = ["posx", "negx", "posy", "negy", "posz", "negz"];
+
+/// The six square faces of a cubemap, one after the other.
+pub(crate) struct CubemapFaces {
+    pub(crate) size: u32,
+    /// `R8G8B8A8_SRGB` for PNGs, or the block-compressed format of the
+    /// DDS files.
+    pub(crate) format: vk::Format,
+    pub(crate) pixels: Vec<u8>,
+}
+
+impl CubemapFaces {
+    /// Loads `FACE_NAMES` from `dir`, preferring DDS files over PNGs. They
+    /// all have to be squares of the same size and format.
+    pub(crate) fn load(dir: &Path) -> Result<CubemapFaces, String> {
+        let mut size_and_format = None;
+        let mut pixels = vec![];
+        for name in FACE_NAMES.iter() {
+            let dds_path = dir.join(format!("{}.dds", name));
+            let (path, face_size, face_format, face_pixels) = if dds_path.exists() {
+                let bytes = std::fs::read(&dds_path)
+                    .map_err(|error| format!("Failed to read {:?}: {}", dds_path, error))?;
+                let dds = texture::parse_dds(&bytes)
+                    .map_err(|error| format!("Failed to load {:?}: {}", dds_path, error))?;
+                let format = dds.format.vk_format(dds.srgb);
+                (dds_path, (dds.width, dds.height), format, dds.data)
+            } else {
+                let path = dir.join(format!("{}.png", name));
+                let face = image::open(&path)
+                    .map_err(|error| format!("Failed to load skybox face {:?}: {}", path, error))?
+                    .into_rgba8();
+                let face_size = (face.width(), face.height());
+                (path, face_size, vk::Format::R8G8B8A8_SRGB, face.into_raw())
+            };
+            let (width, height) = face_size;
+            if width != height {
+                return Err(format!(
+                    "Skybox face {:?} is {}x{}, but has to be square",
+                    path, width, height
+                ));
+            }
+            let (size, format) = *size_and_format.get_or_insert((width, face_format));
+            if width != size {
+                return Err(format!(
+                    "Skybox face {:?} is {} pixels across, the others are {}",
+                    path, width, size
+                ));
+            }
+            if face_format != format {
+                return Err(format!(
+                    "Skybox face {:?} is {:?}, the others are {:?}",
+                    path, face_format, format
+                ));
+            }
+            pixels.extend_from_slice(&face_pixels);
+        }
+        let (size, format) = size_and_format.unwrap();
+        Ok(CubemapFaces {
+            size,
+            format,
+            pixels,
+        })
+    }
+
+    /// Decodes block-compressed faces to RGBA8, for devices that can't
+    /// sample their format.
+    pub(crate) fn decompress(&mut self) -> Result<(), String> {
+        let block_format = match BlockFormat::from_vk_format(self.format) {
+            Some(block_format) => block_format,
+            None => return Ok(()),
+        };
+        let face_bytes = block_format.image_bytes(self.size, self.size);
+        let mut pixels = vec![];
+        for face in self.pixels.chunks_exact(face_bytes) {
+            pixels.extend(texture::decode_to_rgba8(
+                block_format,
+                self.size,
+                self.size,
+                face,
+            )?);
+        }
+        self.pixels = pixels;
+        self.format = if matches!(
+            self.format,
+            vk::Format::BC1_RGBA_UNORM_BLOCK
+                | vk::Format::BC3_UNORM_BLOCK
+                | vk::Format::BC7_UNORM_BLOCK
+        ) {
+            vk::Format::R8G8B8A8_UNORM
+        } else {
+            vk::Format::R8G8B8A8_SRGB
+        };
+        Ok(())
+    }
+}
+
+/// Everything the skybox draws with, besides its pipeline.
+pub(crate) struct Skybox {
+    device: ash::Device,
+    // Only kept alive for the descriptor set
+    _cubemap: Image,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Set 1, with the cubemap. It never changes, so all frames share it.
+    pub(crate) descriptor_set: vk::DescriptorSet,
+    /// The scene's camera set at 0, the cubemap at 1.
+    pub(crate) pipeline_layout: vk::PipelineLayout,
+}
+
+impl Skybox {
+    /// Takes ownership of `cubemap`, which has to be in
+    /// `SHADER_READ_ONLY_OPTIMAL` layout already.
+    pub(crate) fn new(
+        device: &ash::Device,
+        cubemap: Image,
+        scene_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: 0.0,
+            ..Default::default()
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .expect("Failed to create skybox Sampler!")
+        };
+
+        // The image and the sampler are bound separately, as skybox.frag
+        // can't use a combined image sampler.
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create skybox Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create skybox Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate skybox Descriptor Set!")[0]
+        };
+        let image_infos = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: cubemap.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let sampler_infos = [vk::DescriptorImageInfo {
+            sampler,
+            ..Default::default()
+        }];
+        let descriptor_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 0,
+                dst_array_element: 0,
+                descriptor_count: image_infos.len() as u32,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                p_image_info: image_infos.as_ptr(),
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 1,
+                dst_array_element: 0,
+                descriptor_count: sampler_infos.len() as u32,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                p_image_info: sampler_infos.as_ptr(),
+                ..Default::default()
+            },
+        ];
+        unsafe {
+            device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+
+        let set_layouts = [scene_set_layout, descriptor_set_layout];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create skybox pipeline layout")
+        };
+
+        Skybox {
+            device: device.clone(),
+            _cubemap: cubemap,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+        }
+    }
+
+    /// Draws the skybox with `pipeline`, after everything else so the depth
+    /// test leaves only the background to shade.
+    pub(crate) fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        scene_descriptor_set: vk::DescriptorSet,
+    ) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[scene_descriptor_set, self.descriptor_set],
+                // The skybox doesn't use the object transforms
+                &[0],
+            );
+            // A cube made up in skybox.vert
+            self.device.cmd_draw(command_buffer, 36, 1, 0, 0);
+        }
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_faces_are_an_error() {
+        let error = CubemapFaces::load(Path::new("no/such/skybox"))
+            .err()
+            .unwrap();
+        assert!(error.contains("posx.png"), "{}", error);
+    }
+}
@@ -0,0 +1,883 @@
+//! The swapchain, along with the image views, depth buffer and framebuffers
+//! that have to be rebuilt whenever it is.
+
+use ash::extensions::khr;
+use ash::vk;
+
+use crate::allocator::{self, MemoryLocation, SharedAllocator};
+use crate::resources::{Image, ImageMemory};
+use crate::{QueueFamilyIndices, SurfaceStuff};
+
+/// Depth formats we can render with, from most to least preferred. The
+/// ones with a stencil aspect come first, for draws masked by the stencil
+/// buffer.
+const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT,
+];
+
+/// Picks the first of `DEPTH_FORMATS` the device can use as an optimally
+/// tiled depth attachment. Every device supports at least one of them.
+fn choose_depth_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::Format {
+    DEPTH_FORMATS
+        .iter()
+        .copied()
+        .find(|&format| {
+            let properties =
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .expect("No supported depth format")
+}
+
+fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D32_SFLOAT_S8_UINT | vk::Format::D24_UNORM_S8_UINT
+    )
+}
+
+pub(crate) struct SwapChainSupportDetails {
+    pub(crate) capabilities: vk::SurfaceCapabilitiesKHR,
+    pub(crate) formats: Vec<vk::SurfaceFormatKHR>,
+    pub(crate) present_modes: Vec<vk::PresentModeKHR>,
+}
+
+impl SwapChainSupportDetails {
+    pub(crate) fn query(
+        physical_device: vk::PhysicalDevice,
+        surface_stuff: &SurfaceStuff,
+    ) -> SwapChainSupportDetails {
+        unsafe {
+            let capabilities = surface_stuff
+                .surface_loader
+                .get_physical_device_surface_capabilities(physical_device, surface_stuff.surface)
+                .expect("Failed to query for surface capabilities");
+            let formats = surface_stuff
+                .surface_loader
+                .get_physical_device_surface_formats(physical_device, surface_stuff.surface)
+                .expect("Failed to query for surface formats");
+            let present_modes = surface_stuff
+                .surface_loader
+                .get_physical_device_surface_present_modes(physical_device, surface_stuff.surface)
+                .expect("Failed to query for surface present modes");
+            SwapChainSupportDetails {
+                capabilities,
+                formats,
+                present_modes,
+            }
+        }
+    }
+}
+
+/// Owns the swapchain and everything created from its images. Destroys them
+/// when dropped, and rebuilds them all at once in `recreate`.
+///
+/// The render pass isn't owned here: it only depends on the image format,
+/// which doesn't change between recreations.
+pub(crate) struct Swapchain {
+    device: ash::Device,
+    allocator: SharedAllocator,
+    pub(crate) loader: khr::Swapchain,
+    physical_device: vk::PhysicalDevice,
+    /// To look for lazily allocated memory for the depth image.
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    surface_stuff: SurfaceStuff,
+    graphics_family: u32,
+    present_family: u32,
+    pub(crate) swapchain: vk::SwapchainKHR,
+    /// What the driver gave us, which can be more than we asked for.
+    /// Everything kept per image is sized by this, see `image_count`.
+    pub(crate) images: Vec<vk::Image>,
+    pub(crate) image_views: Vec<vk::ImageView>,
+    /// `--swapchain-images`, one more than the minimum if `None`.
+    requested_image_count: Option<u32>,
+    /// Shared by all framebuffers, since only one frame renders at a time.
+    depth_image: Option<Image>,
+    /// `--deferred` only, in `gbuffer_formats`. Shared like the depth image.
+    gbuffer: Vec<Image>,
+    gbuffer_formats: Vec<vk::Format>,
+    /// `--offscreen` only, in `offscreen_format`. The framebuffers draw into
+    /// it instead of the swapchain images. Shared like the depth image.
+    offscreen_image: Option<Image>,
+    offscreen_format: Option<vk::Format>,
+    /// `--msaa` only, drawn into with `samples` per pixel and resolved into
+    /// the swapchain or offscreen image at the end of the render pass.
+    /// Shared like the depth image.
+    color_image: Option<Image>,
+    /// How many samples per pixel the color and depth images have.
+    samples: vk::SampleCountFlags,
+    /// How many layers the images drawn into besides the swapchain images
+    /// have, one per view of `--multiview`.
+    view_count: u32,
+    pub(crate) framebuffers: Vec<vk::Framebuffer>,
+    pub(crate) format: vk::Format,
+    pub(crate) depth_format: vk::Format,
+    pub(crate) extent: vk::Extent2D,
+    pub(crate) image_usage: vk::ImageUsageFlags,
+    pub(crate) present_mode: vk::PresentModeKHR,
+    /// What `present_mode` was chosen for.
+    preferred_present_mode: vk::PresentModeKHR,
+}
+
+impl Swapchain {
+    /// Creates the swapchain and its image views. Framebuffers need a render
+    /// pass, which in turn needs to know `format`, so they're created
+    /// separately with `create_framebuffers`. A G-buffer is only created if
+    /// there are `gbuffer_formats`, an offscreen color image only with an
+    /// `offscreen_format`. Those and the depth image have `view_count`
+    /// layers, which takes an offscreen image to present more than one.
+    /// `requested_image_count` is clamped to what the surface allows. With
+    /// more than one of `samples`, there's also a multisampled color image.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        allocator: &SharedAllocator,
+        physical_device: vk::PhysicalDevice,
+        surface_stuff: SurfaceStuff,
+        indices: &QueueFamilyIndices,
+        preferred_present_mode: vk::PresentModeKHR,
+        window_extent: vk::Extent2D,
+        gbuffer_formats: &[vk::Format],
+        offscreen_format: Option<vk::Format>,
+        view_count: u32,
+        requested_image_count: Option<u32>,
+        samples: vk::SampleCountFlags,
+    ) -> Self {
+        let mut swapchain = Swapchain {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            loader: khr::Swapchain::new(instance, device),
+            physical_device,
+            memory_properties: unsafe {
+                instance.get_physical_device_memory_properties(physical_device)
+            },
+            surface_stuff,
+            graphics_family: indices.graphics_family.unwrap(),
+            present_family: indices.present_family.unwrap(),
+            swapchain: vk::SwapchainKHR::null(),
+            images: vec![],
+            image_views: vec![],
+            requested_image_count,
+            depth_image: None,
+            gbuffer: vec![],
+            gbuffer_formats: gbuffer_formats.to_vec(),
+            offscreen_image: None,
+            offscreen_format,
+            color_image: None,
+            samples,
+            view_count,
+            framebuffers: vec![],
+            format: vk::Format::UNDEFINED,
+            depth_format: choose_depth_format(instance, physical_device),
+            extent: vk::Extent2D::default(),
+            image_usage: vk::ImageUsageFlags::empty(),
+            present_mode: vk::PresentModeKHR::FIFO,
+            preferred_present_mode,
+        };
+        swapchain.create(
+            preferred_present_mode,
+            window_extent,
+            vk::SwapchainKHR::null(),
+        );
+        swapchain
+    }
+
+    /// Has the color and depth images take `samples` per pixel from the
+    /// next `recreate` on, whose render pass has to have that many too.
+    pub(crate) fn set_samples(&mut self, samples: vk::SampleCountFlags) {
+        self.samples = samples;
+    }
+
+    /// Throws away the swapchain and everything built on it, and creates
+    /// them again for the current surface size.
+    pub(crate) fn recreate(
+        &mut self,
+        preferred_present_mode: vk::PresentModeKHR,
+        window_extent: vk::Extent2D,
+        render_pass: Option<vk::RenderPass>,
+    ) {
+        self.destroy_image_objects();
+        // Handing the old swapchain over lets the driver reuse its resources
+        // and finish presenting its images, it can only go once it's retired
+        let old_swapchain = self.swapchain;
+        self.create(preferred_present_mode, window_extent, old_swapchain);
+        unsafe {
+            self.loader.destroy_swapchain(old_swapchain, None);
+        }
+        // Dynamic rendering doesn't need any
+        if let Some(render_pass) = render_pass {
+            self.create_framebuffers(render_pass);
+        }
+    }
+
+    fn create(
+        &mut self,
+        preferred_present_mode: vk::PresentModeKHR,
+        window_extent: vk::Extent2D,
+        old_swapchain: vk::SwapchainKHR,
+    ) {
+        let swapchain_support =
+            SwapChainSupportDetails::query(self.physical_device, &self.surface_stuff);
+        // is_device_suitable rejects devices without any surface formats
+        let surface_format = Self::choose_swapchain_format(&swapchain_support.formats)
+            .expect("Surface has no formats");
+        let present_mode = Self::choose_swapchain_present_mode(
+            &swapchain_support.present_modes,
+            preferred_present_mode,
+        );
+        let extent = Self::choose_swap_extent(&swapchain_support.capabilities, window_extent);
+        let image_count =
+            Self::choose_image_count(&swapchain_support.capabilities, self.requested_image_count);
+
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (swapchain_support.capabilities.supported_usage_flags
+                & (vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST));
+
+        let mut create_info = vk::SwapchainCreateInfoKHR {
+            surface: self.surface_stuff.surface,
+            min_image_count: image_count,
+            image_format: surface_format.format,
+            image_color_space: surface_format.color_space,
+            image_extent: extent,
+            // This is always 1 unless you are developing a stereoscopic 3D app.
+            image_array_layers: 1,
+            // We render into images in the swapchain, so they're used as color
+            // attachment. Copying out of them is only needed for screenshots,
+            // and blitting into them for --raytracing.
+            image_usage,
+            pre_transform: swapchain_support.capabilities.current_transform,
+            composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
+            present_mode,
+            clipped: vk::TRUE,
+            old_swapchain,
+            ..Default::default()
+        };
+
+        let queue_family_indices = [self.graphics_family, self.present_family];
+        if self.graphics_family != self.present_family {
+            create_info.image_sharing_mode = vk::SharingMode::CONCURRENT;
+            create_info.queue_family_index_count = queue_family_indices.len() as u32;
+            create_info.p_queue_family_indices = queue_family_indices.as_ptr();
+        } else {
+            create_info.image_sharing_mode = vk::SharingMode::EXCLUSIVE;
+        }
+
+        self.swapchain = unsafe {
+            self.loader
+                .create_swapchain(&create_info, None)
+                .expect("Failed to create Swapchain")
+        };
+        let previous_image_count = self.images.len();
+        self.images = unsafe {
+            self.loader
+                .get_swapchain_images(self.swapchain)
+                .expect("Failed to get Swapchain Images.")
+        };
+        if self.images.len() != previous_image_count {
+            println!(
+                "Swapchain images: {} (asked for {})",
+                self.images.len(),
+                image_count
+            );
+        }
+        self.format = surface_format.format;
+        self.extent = extent;
+        self.image_usage = image_usage;
+        // Resizes recreate the swapchain all the time, only say something
+        // when the mode or the one we asked for changes
+        if old_swapchain == vk::SwapchainKHR::null()
+            || present_mode != self.present_mode
+            || preferred_present_mode != self.preferred_present_mode
+        {
+            if present_mode == preferred_present_mode {
+                println!("Present mode: {:?}", present_mode);
+            } else {
+                println!(
+                    "Present mode {:?} is not supported, using {:?}",
+                    preferred_present_mode, present_mode
+                );
+            }
+        }
+        self.present_mode = present_mode;
+        self.preferred_present_mode = preferred_present_mode;
+        self.image_views = self.create_image_views();
+        self.depth_image = Some(self.create_depth_image());
+        self.gbuffer = self
+            .gbuffer_formats
+            .iter()
+            .map(|&format| {
+                self.create_transient_image(
+                    "gbuffer_image",
+                    format,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+                    vk::ImageAspectFlags::COLOR,
+                    vk::SampleCountFlags::TYPE_1,
+                )
+            })
+            .collect();
+        // Sampled after the render pass, so it has to be kept. present.frag
+        // reads it as an array whether there's more than one view or not.
+        self.offscreen_image = self.offscreen_format.map(|format| {
+            self.create_image(
+                "offscreen_image",
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageViewType::TYPE_2D_ARRAY,
+                vk::SampleCountFlags::TYPE_1,
+            )
+        });
+        // Only the resolved image outlives the render pass
+        self.color_image = (self.samples != vk::SampleCountFlags::TYPE_1).then(|| {
+            self.create_transient_image(
+                "color_image",
+                self.offscreen_format.unwrap_or(self.format),
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+                self.samples,
+            )
+        });
+    }
+
+    /// How many images the swapchain has. Anything kept per image, like
+    /// the framebuffers, has to have exactly this many, and be made again
+    /// when the swapchain is, as the count can change with it.
+    pub(crate) fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Views of the G-buffer images, empty unless deferred.
+    pub(crate) fn gbuffer_views(&self) -> Vec<vk::ImageView> {
+        self.gbuffer.iter().map(|image| image.view).collect()
+    }
+
+    /// View of the offscreen color image, `None` unless there's an
+    /// `offscreen_format`. It's an array view, with `view_count` layers.
+    pub(crate) fn offscreen_view(&self) -> Option<vk::ImageView> {
+        self.offscreen_image.as_ref().map(|image| image.view)
+    }
+
+    /// Prefers RGBA8 in the sRGB color space, otherwise takes whatever the
+    /// surface lists first. Returns `None` if there's nothing to choose from.
+    fn choose_swapchain_format(
+        available_formats: &[vk::SurfaceFormatKHR],
+    ) -> Option<vk::SurfaceFormatKHR> {
+        for &available_format in available_formats {
+            if available_format.format == vk::Format::R8G8B8A8_UNORM
+                && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+            {
+                return Some(available_format);
+            }
+        }
+
+        available_formats.first().copied()
+    }
+
+    /// Takes `preferred_present_mode` if the surface supports it. That
+    /// includes FIFO_RELAXED, which is FIFO that doesn't wait for the next
+    /// vblank when a frame comes in late, trading a tear for less stutter.
+    /// Otherwise IMMEDIATE falls back to MAILBOX, and anything to FIFO.
+    fn choose_swapchain_present_mode(
+        available_present_modes: &[vk::PresentModeKHR],
+        preferred_present_mode: vk::PresentModeKHR,
+    ) -> vk::PresentModeKHR {
+        if available_present_modes.contains(&preferred_present_mode) {
+            return preferred_present_mode;
+        }
+        // Without tearing, but still not waiting for vblank
+        if preferred_present_mode == vk::PresentModeKHR::IMMEDIATE
+            && available_present_modes.contains(&vk::PresentModeKHR::MAILBOX)
+        {
+            return vk::PresentModeKHR::MAILBOX;
+        }
+        // FIFO is the only mode the spec guarantees to be available, and the
+        // closest one to FIFO_RELAXED
+        vk::PresentModeKHR::FIFO
+    }
+
+    /// How many images to ask for, `requested` or else one more than the
+    /// minimum, within what the surface allows. The driver may still create
+    /// more.
+    fn choose_image_count(
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        requested: Option<u32>,
+    ) -> u32 {
+        // Sometimes we may have to wait on the driver to complete its stuff before
+        // we can acquire another image to render to. Therefore it's recommended to
+        // request at least one more image than the minimum
+        let image_count = requested
+            .unwrap_or(capabilities.min_image_count + 1)
+            .max(capabilities.min_image_count);
+        // No maximum is 0
+        if capabilities.max_image_count > 0 {
+            image_count.min(capabilities.max_image_count)
+        } else {
+            image_count
+        }
+    }
+
+    /// Picks the swapchain size. Most platforms dictate it through
+    /// `current_extent`, otherwise we go with the window's size.
+    fn choose_swap_extent(
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            capabilities.current_extent
+        } else {
+            vk::Extent2D {
+                width: capabilities
+                    .min_image_extent
+                    .width
+                    .max(window_extent.width)
+                    .min(capabilities.max_image_extent.width),
+                height: capabilities
+                    .min_image_extent
+                    .height
+                    .max(window_extent.height)
+                    .min(capabilities.max_image_extent.height),
+            }
+        }
+    }
+
+    fn create_image_views(&self) -> Vec<vk::ImageView> {
+        let mut image_views = vec![];
+
+        for &image in self.images.iter() {
+            let imageview_create_info = vk::ImageViewCreateInfo {
+                view_type: vk::ImageViewType::TYPE_2D,
+                format: self.format,
+                components: vk::ComponentMapping {
+                    r: vk::ComponentSwizzle::IDENTITY,
+                    g: vk::ComponentSwizzle::IDENTITY,
+                    b: vk::ComponentSwizzle::IDENTITY,
+                    a: vk::ComponentSwizzle::IDENTITY,
+                },
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image,
+                ..Default::default()
+            };
+            let imageview = unsafe {
+                self.device
+                    .create_image_view(&imageview_create_info, None)
+                    .expect("Failed to create Image View!")
+            };
+            image_views.push(imageview);
+        }
+
+        image_views
+    }
+
+    /// The depth image, shared by all swapchain images.
+    pub(crate) fn depth_image(&self) -> &Image {
+        self.depth_image
+            .as_ref()
+            .expect("The swapchain has no depth image")
+    }
+
+    /// The depth format if it has a stencil aspect, `UNDEFINED` otherwise.
+    pub(crate) fn stencil_format(&self) -> vk::Format {
+        if has_stencil(self.depth_format) {
+            self.depth_format
+        } else {
+            vk::Format::UNDEFINED
+        }
+    }
+
+    /// What the depth image has, stencil included if its format does.
+    pub(crate) fn depth_aspect_mask(&self) -> vk::ImageAspectFlags {
+        if has_stencil(self.depth_format) {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        } else {
+            vk::ImageAspectFlags::DEPTH
+        }
+    }
+
+    fn create_depth_image(&self) -> Image {
+        self.create_transient_image(
+            "depth_image",
+            self.depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            self.depth_aspect_mask(),
+            self.samples,
+        )
+    }
+
+    /// The depth image, the G-buffer and the multisampled color image are
+    /// only used within the render pass, so they're transient. On tile-based GPUs they can then live in
+    /// lazily allocated memory, which never gets backed by main memory.
+    /// Elsewhere they take regular device local memory.
+    fn create_transient_image(
+        &self,
+        name: &str,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Image {
+        let view_type = if self.view_count > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        self.create_image(
+            name,
+            format,
+            usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            aspect_mask,
+            view_type,
+            samples,
+        )
+    }
+
+    /// An image the size of the swapchain with `view_count` layers and
+    /// `samples` per pixel, in
+    /// lazily allocated memory if `usage` makes it a transient attachment
+    /// and the device has any.
+    fn create_image(
+        &self,
+        name: &str,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        view_type: vk::ImageViewType,
+        samples: vk::SampleCountFlags,
+    ) -> Image {
+        let image_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: self.view_count,
+            samples,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        };
+        let image = unsafe {
+            self.device
+                .create_image(&image_info, None)
+                .unwrap_or_else(|error| panic!("Failed to create {}: {:?}", name, error))
+        };
+        let requirements = unsafe { self.device.get_image_memory_requirements(image) };
+        let lazy_memory_type = if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+            allocator::find_memory_type(
+                &self.memory_properties,
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::LAZILY_ALLOCATED,
+            )
+        } else {
+            None
+        };
+        let (memory, memory_offset) = match lazy_memory_type {
+            // gpu-allocator never picks lazily allocated memory, and there's
+            // little point in sub-allocating it anyway
+            Some(memory_type_index) => {
+                let allocate_info = vk::MemoryAllocateInfo {
+                    allocation_size: requirements.size,
+                    memory_type_index,
+                    ..Default::default()
+                };
+                let memory = unsafe {
+                    self.device
+                        .allocate_memory(&allocate_info, None)
+                        .unwrap_or_else(|error| {
+                            panic!("Failed to allocate memory for {}: {:?}", name, error)
+                        })
+                };
+                (ImageMemory::Dedicated(memory), 0)
+            }
+            None => {
+                let allocation = allocator::allocate(
+                    &self.allocator,
+                    name,
+                    requirements,
+                    MemoryLocation::GpuOnly,
+                    false,
+                );
+                let offset = allocation.offset();
+                (ImageMemory::Allocation(allocation), offset)
+            }
+        };
+        let device_memory = match &memory {
+            ImageMemory::Allocation(allocation) => allocator::memory(allocation),
+            ImageMemory::Dedicated(memory) => *memory,
+        };
+        unsafe {
+            self.device
+                .bind_image_memory(image, device_memory, memory_offset)
+                .unwrap_or_else(|error| panic!("Failed to bind {} memory: {:?}", name, error));
+        }
+
+        let view_info = vk::ImageViewCreateInfo {
+            image,
+            view_type,
+            format,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: self.view_count,
+            },
+            ..Default::default()
+        };
+        let view = unsafe {
+            self.device
+                .create_image_view(&view_info, None)
+                .unwrap_or_else(|error| panic!("Failed to create {} view: {:?}", name, error))
+        };
+        Image::new(&self.device, &self.allocator, image, view, memory)
+    }
+
+    pub(crate) fn create_framebuffers(&mut self, render_pass: vk::RenderPass) {
+        let depth_view = self
+            .depth_image
+            .as_ref()
+            .expect("Framebuffers need the depth image")
+            .view;
+        let gbuffer_views = self.gbuffer_views();
+        let offscreen_view = self.offscreen_view();
+        let multisampled_view = self.color_image.as_ref().map(|image| image.view);
+        let mut framebuffers = vec![];
+        for &image_view in self.image_views.iter() {
+            let color_view = offscreen_view.unwrap_or(image_view);
+            // When multisampling, what's drawn is resolved into the color
+            // view, which comes last
+            let mut attachments = vec![multisampled_view.unwrap_or(color_view), depth_view];
+            attachments.extend(&gbuffer_views);
+            if multisampled_view.is_some() {
+                attachments.push(color_view);
+            }
+            let framebuffer_create_info = vk::FramebufferCreateInfo {
+                render_pass,
+                attachment_count: attachments.len() as u32,
+                p_attachments: attachments.as_ptr(),
+                width: self.extent.width,
+                height: self.extent.height,
+                // Our swapchain images are single images, so the number of
+                // layers is 1. Multiview picks the layers itself, and needs
+                // it to be 1 as well.
+                layers: 1,
+                ..Default::default()
+            };
+
+            let framebuffer = unsafe {
+                self.device
+                    .create_framebuffer(&framebuffer_create_info, None)
+                    .expect("Failed to create Framebuffer!")
+            };
+            framebuffers.push(framebuffer);
+        }
+
+        debug_assert_eq!(framebuffers.len(), self.image_count());
+        self.framebuffers = framebuffers;
+    }
+
+    /// Destroys the framebuffers, image views and depth image, but not the
+    /// swapchain. Its images are kept, to tell if a new one has as many.
+    fn destroy_image_objects(&mut self) {
+        unsafe {
+            for framebuffer in self.framebuffers.drain(..) {
+                self.device.destroy_framebuffer(framebuffer, None);
+            }
+            for image_view in self.image_views.drain(..) {
+                self.device.destroy_image_view(image_view, None);
+            }
+        }
+        self.depth_image = None;
+        self.gbuffer.clear();
+        self.offscreen_image = None;
+        self.color_image = None;
+    }
+
+    fn destroy(&mut self) {
+        self.destroy_image_objects();
+        self.images.clear();
+        unsafe {
+            self.loader.destroy_swapchain(self.swapchain, None);
+        }
+        self.swapchain = vk::SwapchainKHR::null();
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(current_extent: vk::Extent2D) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            current_extent,
+            min_image_extent: vk::Extent2D {
+                width: 100,
+                height: 50,
+            },
+            max_image_extent: vk::Extent2D {
+                width: 1000,
+                height: 500,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    // ash's structs don't implement PartialEq
+    fn size(extent: vk::Extent2D) -> (u32, u32) {
+        (extent.width, extent.height)
+    }
+
+    fn image_counts(min_image_count: u32, max_image_count: u32) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            min_image_count,
+            max_image_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn choose_image_count_defaults_to_one_more_than_min() {
+        assert_eq!(Swapchain::choose_image_count(&image_counts(2, 8), None), 3);
+        assert_eq!(Swapchain::choose_image_count(&image_counts(3, 3), None), 3);
+    }
+
+    #[test]
+    fn choose_image_count_clamps_requested_count() {
+        let capabilities = image_counts(2, 4);
+        assert_eq!(Swapchain::choose_image_count(&capabilities, Some(1)), 2);
+        assert_eq!(Swapchain::choose_image_count(&capabilities, Some(3)), 3);
+        assert_eq!(Swapchain::choose_image_count(&capabilities, Some(6)), 4);
+    }
+
+    #[test]
+    fn choose_image_count_without_max() {
+        let capabilities = image_counts(2, 0);
+        assert_eq!(Swapchain::choose_image_count(&capabilities, Some(16)), 16);
+    }
+
+    #[test]
+    fn choose_swap_extent_uses_current_extent() {
+        let capabilities = capabilities(extent(640, 480));
+        let chosen = Swapchain::choose_swap_extent(&capabilities, extent(800, 600));
+        assert_eq!(size(chosen), (640, 480));
+    }
+
+    #[test]
+    fn choose_swap_extent_uses_window_extent_within_limits() {
+        let capabilities = capabilities(extent(u32::MAX, u32::MAX));
+        let chosen = Swapchain::choose_swap_extent(&capabilities, extent(800, 300));
+        assert_eq!(size(chosen), (800, 300));
+    }
+
+    #[test]
+    fn choose_swap_extent_clamps_to_min_extent() {
+        let capabilities = capabilities(extent(u32::MAX, u32::MAX));
+        let chosen = Swapchain::choose_swap_extent(&capabilities, extent(10, 0));
+        assert_eq!(size(chosen), (100, 50));
+    }
+
+    #[test]
+    fn choose_swap_extent_clamps_to_max_extent() {
+        let capabilities = capabilities(extent(u32::MAX, u32::MAX));
+        let chosen = Swapchain::choose_swap_extent(&capabilities, extent(4000, 300));
+        assert_eq!(size(chosen), (1000, 300));
+    }
+
+    fn surface_format(format: vk::Format) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }
+    }
+
+    #[test]
+    fn choose_swapchain_format_prefers_rgba8() {
+        let formats = [
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+            surface_format(vk::Format::R8G8B8A8_UNORM),
+        ];
+        let chosen = Swapchain::choose_swapchain_format(&formats).unwrap();
+        assert_eq!(chosen.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(chosen.color_space, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+    }
+
+    #[test]
+    fn choose_swapchain_format_needs_srgb_color_space() {
+        let formats = [
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+            vk::SurfaceFormatKHR {
+                format: vk::Format::R8G8B8A8_UNORM,
+                color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            },
+        ];
+        let chosen = Swapchain::choose_swapchain_format(&formats).unwrap();
+        assert_eq!(chosen.format, vk::Format::B8G8R8A8_UNORM);
+    }
+
+    #[test]
+    fn choose_swapchain_format_falls_back_to_first() {
+        let formats = [
+            surface_format(vk::Format::B8G8R8A8_UNORM),
+            surface_format(vk::Format::A2B10G10R10_UNORM_PACK32),
+        ];
+        let chosen = Swapchain::choose_swapchain_format(&formats).unwrap();
+        assert_eq!(chosen.format, vk::Format::B8G8R8A8_UNORM);
+    }
+
+    #[test]
+    fn choose_swapchain_format_handles_no_formats() {
+        assert!(Swapchain::choose_swapchain_format(&[]).is_none());
+    }
+
+    #[test]
+    fn choose_swapchain_present_mode_takes_fifo_relaxed() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::FIFO_RELAXED];
+        let chosen =
+            Swapchain::choose_swapchain_present_mode(&modes, vk::PresentModeKHR::FIFO_RELAXED);
+        assert_eq!(chosen, vk::PresentModeKHR::FIFO_RELAXED);
+    }
+
+    #[test]
+    fn choose_swapchain_present_mode_falls_back_from_immediate_to_mailbox() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+        let chosen =
+            Swapchain::choose_swapchain_present_mode(&modes, vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(chosen, vk::PresentModeKHR::MAILBOX);
+    }
+
+    #[test]
+    fn choose_swapchain_present_mode_falls_back_to_fifo() {
+        let modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+        let chosen =
+            Swapchain::choose_swapchain_present_mode(&modes, vk::PresentModeKHR::FIFO_RELAXED);
+        assert_eq!(chosen, vk::PresentModeKHR::FIFO);
+    }
+}
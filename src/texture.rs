@@ -0,0 +1,320 @@
+//! Block-compressed textures from DDS files. They're uploaded as they are
+//! where the device can sample their format, which takes a quarter of the
+//! memory of RGBA8 or less, and decoded to RGBA8 where it can't.
+
+use ash::vk;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 4 + 124;
+/// The extended header that follows when the FourCC is "DX10".
+const DX10_HEADER_SIZE: usize = 20;
+
+/// 4x4 blocks of pixels, each packed into 8 or 16 bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BlockFormat {
+    /// Opaque color, or color with 1-bit alpha. Also known as DXT1.
+    Bc1,
+    /// BC1 color with separate smooth alpha. Also known as DXT5.
+    Bc3,
+    /// High quality color and alpha.
+    Bc7,
+}
+
+impl BlockFormat {
+    fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 => 8,
+            BlockFormat::Bc3 | BlockFormat::Bc7 => 16,
+        }
+    }
+
+    pub(crate) fn vk_format(self, srgb: bool) -> vk::Format {
+        match (self, srgb) {
+            (BlockFormat::Bc1, false) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            (BlockFormat::Bc1, true) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            (BlockFormat::Bc3, false) => vk::Format::BC3_UNORM_BLOCK,
+            (BlockFormat::Bc3, true) => vk::Format::BC3_SRGB_BLOCK,
+            (BlockFormat::Bc7, false) => vk::Format::BC7_UNORM_BLOCK,
+            (BlockFormat::Bc7, true) => vk::Format::BC7_SRGB_BLOCK,
+        }
+    }
+
+    pub(crate) fn from_vk_format(format: vk::Format) -> Option<Self> {
+        match format {
+            vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+                Some(BlockFormat::Bc1)
+            }
+            vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => Some(BlockFormat::Bc3),
+            vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => Some(BlockFormat::Bc7),
+            _ => None,
+        }
+    }
+
+    /// Bytes in a `width` by `height` image, counting partial blocks at the
+    /// edges as whole ones.
+    pub(crate) fn image_bytes(self, width: u32, height: u32) -> usize {
+        let blocks_across = (width as usize).div_ceil(4);
+        let blocks_down = (height as usize).div_ceil(4);
+        blocks_across * blocks_down * self.block_bytes()
+    }
+}
+
+/// The top mip level of a DDS file.
+pub(crate) struct Dds {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) format: BlockFormat,
+    /// Whether the colors are sRGB encoded. Files without a DX10 header
+    /// don't say, and are taken to be.
+    pub(crate) srgb: bool,
+    pub(crate) data: Vec<u8>,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Reads a 2D DDS file in one of the `BlockFormat`s, skipping any mip
+/// levels below the top one.
+pub(crate) fn parse_dds(bytes: &[u8]) -> Result<Dds, String> {
+    if bytes.len() < HEADER_SIZE || &bytes[..4] != DDS_MAGIC {
+        return Err("not a DDS file".to_string());
+    }
+    let height = read_u32(bytes, 12);
+    let width = read_u32(bytes, 16);
+    let four_cc = &bytes[84..88];
+    let (format, srgb, data_offset) = match four_cc {
+        b"DXT1" => (BlockFormat::Bc1, true, HEADER_SIZE),
+        b"DXT5" => (BlockFormat::Bc3, true, HEADER_SIZE),
+        b"DX10" => {
+            if bytes.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+                return Err("DX10 header is cut short".to_string());
+            }
+            // DXGI_FORMAT values
+            let (format, srgb) = match read_u32(bytes, HEADER_SIZE) {
+                71 => (BlockFormat::Bc1, false),
+                72 => (BlockFormat::Bc1, true),
+                77 => (BlockFormat::Bc3, false),
+                78 => (BlockFormat::Bc3, true),
+                98 => (BlockFormat::Bc7, false),
+                99 => (BlockFormat::Bc7, true),
+                dxgi_format => {
+                    return Err(format!("unsupported DXGI format {}", dxgi_format));
+                }
+            };
+            (format, srgb, HEADER_SIZE + DX10_HEADER_SIZE)
+        }
+        _ => {
+            return Err(format!(
+                "unsupported pixel format {:?}",
+                String::from_utf8_lossy(four_cc)
+            ))
+        }
+    };
+    let size = format.image_bytes(width, height);
+    let data = bytes
+        .get(data_offset..data_offset + size)
+        .ok_or_else(|| format!("expected {} bytes of pixels for {}x{}", size, width, height))?;
+    Ok(Dds {
+        width,
+        height,
+        format,
+        srgb,
+        data: data.to_vec(),
+    })
+}
+
+/// Expands a 5:6:5 color to 8 bits per channel.
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 0x1f;
+    let g = (color >> 5) & 0x3f;
+    let b = color & 0x1f;
+    [
+        ((r << 3) | (r >> 2)) as u8,
+        ((g << 2) | (g >> 4)) as u8,
+        ((b << 3) | (b >> 2)) as u8,
+    ]
+}
+
+fn lerp(a: u8, b: u8, numerator: u32, denominator: u32) -> u8 {
+    ((u32::from(a) * (denominator - numerator) + u32::from(b) * numerator) / denominator) as u8
+}
+
+/// The 16 pixels of a BC1 color block, row by row. BC3 color blocks always
+/// have four colors, BC1 ones only when the first endpoint is bigger.
+fn decode_color_block(block: &[u8], always_opaque: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let [r0, g0, b0] = rgb565(color0);
+    let [r1, g1, b1] = rgb565(color1);
+    let palette = if always_opaque || color0 > color1 {
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [
+                lerp(r0, r1, 1, 3),
+                lerp(g0, g1, 1, 3),
+                lerp(b0, b1, 1, 3),
+                255,
+            ],
+            [
+                lerp(r0, r1, 2, 3),
+                lerp(g0, g1, 2, 3),
+                lerp(b0, b1, 2, 3),
+                255,
+            ],
+        ]
+    } else {
+        [
+            [r0, g0, b0, 255],
+            [r1, g1, b1, 255],
+            [
+                lerp(r0, r1, 1, 2),
+                lerp(g0, g1, 1, 2),
+                lerp(b0, b1, 1, 2),
+                255,
+            ],
+            [0, 0, 0, 0],
+        ]
+    };
+    let indices = read_u32(block, 4);
+    let mut pixels = [[0; 4]; 16];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[((indices >> (2 * i)) & 0b11) as usize];
+    }
+    pixels
+}
+
+/// The 16 alphas of a BC3 alpha block, row by row.
+fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+    let (alpha0, alpha1) = (block[0], block[1]);
+    let mut palette = [alpha0, alpha1, 0, 0, 0, 0, 0, 255];
+    if alpha0 > alpha1 {
+        for i in 1..7 {
+            palette[i + 1] = lerp(alpha0, alpha1, i as u32, 7);
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = lerp(alpha0, alpha1, i as u32, 5);
+        }
+    }
+    // 16 3-bit indices in the remaining 6 bytes
+    let indices = block[2..8]
+        .iter()
+        .rev()
+        .fold(0u64, |bits, &byte| (bits << 8) | u64::from(byte));
+    let mut alphas = [0; 16];
+    for (i, alpha) in alphas.iter_mut().enumerate() {
+        *alpha = palette[((indices >> (3 * i)) & 0b111) as usize];
+    }
+    alphas
+}
+
+/// Decodes a `width` by `height` image to RGBA8, for devices that can't
+/// sample `format`. There's no decoder for BC7, which is a lot more
+/// involved than the others.
+pub(crate) fn decode_to_rgba8(
+    format: BlockFormat,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Result<Vec<u8>, String> {
+    if format == BlockFormat::Bc7 {
+        return Err("BC7 textures can't be decoded, and the device can't sample them".to_string());
+    }
+    let (width, height) = (width as usize, height as usize);
+    let blocks_across = width.div_ceil(4);
+    let mut pixels = vec![0; width * height * 4];
+    for (block_index, block) in data.chunks_exact(format.block_bytes()).enumerate() {
+        let block_x = block_index % blocks_across * 4;
+        let block_y = block_index / blocks_across * 4;
+        let decoded = match format {
+            BlockFormat::Bc1 => decode_color_block(block, false),
+            _ => {
+                let mut decoded = decode_color_block(&block[8..], true);
+                for (pixel, alpha) in decoded.iter_mut().zip(decode_alpha_block(block).iter()) {
+                    pixel[3] = *alpha;
+                }
+                decoded
+            }
+        };
+        for (i, pixel) in decoded.iter().enumerate() {
+            let (x, y) = (block_x + i % 4, block_y + i / 4);
+            // Blocks at the edges can hang over
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dds_header(width: u32, height: u32, four_cc: &[u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        bytes
+    }
+
+    #[test]
+    fn parses_the_top_level_of_a_dxt1_file() {
+        let mut bytes = dds_header(8, 4, b"DXT1");
+        // Two blocks, and a smaller mip level after them
+        bytes.extend_from_slice(&[1; 16]);
+        bytes.extend_from_slice(&[2; 8]);
+        let dds = parse_dds(&bytes).unwrap();
+        assert_eq!((dds.width, dds.height), (8, 4));
+        assert_eq!(dds.format, BlockFormat::Bc1);
+        assert_eq!(dds.data, vec![1; 16]);
+    }
+
+    #[test]
+    fn truncated_pixels_are_an_error() {
+        let mut bytes = dds_header(8, 8, b"DXT5");
+        bytes.extend_from_slice(&[0; 16]);
+        assert!(parse_dds(&bytes).is_err());
+    }
+
+    #[test]
+    fn decodes_a_solid_bc1_block() {
+        // Pure red at both ends, all indices 0
+        let red = 0xf800u16.to_le_bytes();
+        let block = [red[0], red[1], red[0], red[1], 0, 0, 0, 0];
+        let pixels = decode_to_rgba8(BlockFormat::Bc1, 4, 4, &block).unwrap();
+        for pixel in pixels.chunks(4) {
+            assert_eq!(pixel, [255, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn decodes_bc3_alpha() {
+        let mut block = [0; 16];
+        // Alpha endpoints 255 and 0, every pixel on index 1, so 0
+        block[0] = 255;
+        block[1] = 0;
+        block[2..8].copy_from_slice(&[0x49, 0x92, 0x24, 0x49, 0x92, 0x24]);
+        let pixels = decode_to_rgba8(BlockFormat::Bc3, 4, 4, &block).unwrap();
+        for pixel in pixels.chunks(4) {
+            assert_eq!(pixel[3], 0);
+        }
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        let pixels = decode_to_rgba8(BlockFormat::Bc1, 2, 3, &[0; 8]).unwrap();
+        assert_eq!(pixels.len(), 2 * 3 * 4);
+    }
+}
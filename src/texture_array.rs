@@ -0,0 +1,360 @@
+//! Textures for scene objects. Every object samples the same descriptor
+//! set and picks its texture with a push constant, so nothing has to be
+//! bound again between objects.
+//!
+//! With `VK_EXT_descriptor_indexing` each texture is an image of its own in
+//! a big, partially bound descriptor array. Without it they're all layers
+//! of one `2D_ARRAY` image, scaled to the same size.
+
+use std::os::raw::c_void;
+
+use ash::vk;
+
+use crate::resources::Image;
+
+/// Width and height of every layer. Textures are scaled to fit.
+pub(crate) const LAYER_SIZE: u32 = 256;
+/// How many textures `VulkanApp::add_texture` takes without descriptor
+/// indexing.
+pub(crate) const MAX_LAYERS: u32 = 16;
+/// The most textures bound with descriptor indexing, however many more the
+/// device allows.
+pub(crate) const MAX_BINDLESS_TEXTURES: u32 = 16384;
+pub(crate) const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Names a texture from `VulkanApp::add_texture`, which is its layer or its
+/// index in the descriptor array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(crate) u32);
+
+/// What textured.vert and bindless.frag read.
+#[repr(C)]
+pub(crate) struct TexturedPushConstants {
+    pub(crate) model: [[f32; 4]; 4],
+    /// The `TextureId`, or -1 for the vertex colors instead of a texture.
+    pub(crate) texture: i32,
+}
+
+impl TexturedPushConstants {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+pub(crate) enum Storage {
+    /// Layers of one image with `MAX_LAYERS` of them.
+    Layers(Box<Image>),
+    /// An image per texture, in a descriptor array with room for `capacity`.
+    Bindless { images: Vec<Image>, capacity: u32 },
+}
+
+pub(crate) struct TextureArray {
+    device: ash::Device,
+    pub(crate) storage: Storage,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    /// Set 1, with the whole array. Textures are only ever added, so all
+    /// frames share it.
+    pub(crate) descriptor_set: vk::DescriptorSet,
+    /// The scene's camera set at 0, the array at 1, and
+    /// `TexturedPushConstants`.
+    pub(crate) pipeline_layout: vk::PipelineLayout,
+    /// Which stages read `TexturedPushConstants`.
+    pub(crate) push_constant_stages: vk::ShaderStageFlags,
+    /// The width, height and pixels of each texture added, kept to upload
+    /// again if the device is lost.
+    pub(crate) textures: Vec<(u32, u32, Vec<u8>)>,
+}
+
+impl TextureArray {
+    /// Takes ownership of `storage`. A `Storage::Layers` image has to have
+    /// all layers in `SHADER_READ_ONLY_OPTIMAL` layout and a `TYPE_2D_ARRAY`
+    /// view, `Storage::Bindless` needs descriptor indexing enabled.
+    pub(crate) fn new(
+        device: &ash::Device,
+        storage: Storage,
+        scene_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_lod: 0.0,
+            ..Default::default()
+        };
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .expect("Failed to create texture array Sampler!")
+        };
+
+        let (image_count, bindless) = match &storage {
+            Storage::Layers(_) => (1, false),
+            Storage::Bindless { capacity, .. } => (*capacity, true),
+        };
+        // Separate image and sampler, like the skybox
+        let bindings = [
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: image_count,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        // Only the images that have been added are bound, and more are
+        // added while frames in flight use the set
+        let binding_flags = [
+            vk::DescriptorBindingFlagsEXT::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlagsEXT::UPDATE_AFTER_BIND,
+            vk::DescriptorBindingFlagsEXT::empty(),
+        ];
+        let binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfoEXT {
+            binding_count: binding_flags.len() as u32,
+            p_binding_flags: binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        let layout_info = vk::DescriptorSetLayoutCreateInfo {
+            p_next: if bindless {
+                &binding_flags_info as *const _ as *const c_void
+            } else {
+                std::ptr::null()
+            },
+            flags: if bindless {
+                vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL_EXT
+            } else {
+                vk::DescriptorSetLayoutCreateFlags::empty()
+            },
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create texture array Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: image_count,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            flags: if bindless {
+                vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND_EXT
+            } else {
+                vk::DescriptorPoolCreateFlags::empty()
+            },
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create texture array Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo {
+            descriptor_pool,
+            descriptor_set_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            ..Default::default()
+        };
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate texture array Descriptor Set!")[0]
+        };
+        let sampler_infos = [vk::DescriptorImageInfo {
+            sampler,
+            ..Default::default()
+        }];
+        let descriptor_writes = [vk::WriteDescriptorSet {
+            dst_set: descriptor_set,
+            dst_binding: 1,
+            dst_array_element: 0,
+            descriptor_count: sampler_infos.len() as u32,
+            descriptor_type: vk::DescriptorType::SAMPLER,
+            p_image_info: sampler_infos.as_ptr(),
+            ..Default::default()
+        }];
+        unsafe {
+            device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+
+        // bindless.frag reads the index itself, so it's the same across the
+        // draw as descriptor indexing without extra features wants
+        let push_constant_stages = if bindless {
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        } else {
+            vk::ShaderStageFlags::VERTEX
+        };
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: push_constant_stages,
+            offset: 0,
+            size: std::mem::size_of::<TexturedPushConstants>() as u32,
+        }];
+        let set_layouts = [scene_set_layout, descriptor_set_layout];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo {
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_info, None)
+                .expect("Failed to create texture array pipeline layout")
+        };
+
+        let texture_array = TextureArray {
+            device: device.clone(),
+            storage,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            push_constant_stages,
+            textures: vec![],
+        };
+        if let Storage::Layers(image) = &texture_array.storage {
+            texture_array.write_image(0, image.view);
+        }
+        texture_array
+    }
+
+    pub(crate) fn is_bindless(&self) -> bool {
+        matches!(self.storage, Storage::Bindless { .. })
+    }
+
+    /// How many textures fit.
+    pub(crate) fn capacity(&self) -> u32 {
+        match self.storage {
+            Storage::Layers(_) => MAX_LAYERS,
+            Storage::Bindless { capacity, .. } => capacity,
+        }
+    }
+
+    /// Binds `image` as texture `index` of a bindless array, which frames
+    /// in flight can go on using as it's only read from `index` on.
+    pub(crate) fn push_image(&mut self, index: u32, image: Image) {
+        self.write_image(index, image.view);
+        match &mut self.storage {
+            Storage::Bindless { images, .. } => images.push(image),
+            Storage::Layers(_) => panic!("Only bindless texture arrays take more images"),
+        }
+    }
+
+    fn write_image(&self, index: u32, view: vk::ImageView) {
+        let image_infos = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let descriptor_writes = [vk::WriteDescriptorSet {
+            dst_set: self.descriptor_set,
+            dst_binding: 0,
+            dst_array_element: index,
+            descriptor_count: image_infos.len() as u32,
+            descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+            p_image_info: image_infos.as_ptr(),
+            ..Default::default()
+        }];
+        unsafe {
+            self.device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .destroy_pipeline_layout(self.pipeline_layout, None);
+            // Frees the descriptor set along with it
+            self.device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+/// Checks `rgba` holds `width` by `height` RGBA8 pixels.
+pub(crate) fn texture_pixels(
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<image::RgbaImage, String> {
+    if width == 0 || height == 0 {
+        return Err(format!("A {}x{} texture is empty", width, height));
+    }
+    image::RgbaImage::from_raw(width, height, rgba.to_vec()).ok_or_else(|| {
+        format!(
+            "Expected {} bytes of pixels for a {}x{} texture, got {}",
+            4 * width as usize * height as usize,
+            width,
+            height,
+            rgba.len()
+        )
+    })
+}
+
+/// Scales `pixels` to a layer.
+pub(crate) fn fit_to_layer(pixels: image::RgbaImage) -> image::RgbaImage {
+    if pixels.dimensions() == (LAYER_SIZE, LAYER_SIZE) {
+        return pixels;
+    }
+    image::imageops::resize(
+        &pixels,
+        LAYER_SIZE,
+        LAYER_SIZE,
+        image::imageops::FilterType::Triangle,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textures_are_scaled_to_the_layer_size() {
+        let pixels = fit_to_layer(texture_pixels(2, 2, &[255; 16]).unwrap());
+        assert_eq!(pixels.dimensions(), (LAYER_SIZE, LAYER_SIZE));
+        assert!(pixels.iter().all(|&byte| byte == 255));
+    }
+
+    #[test]
+    fn too_few_pixels_are_an_error() {
+        assert!(texture_pixels(2, 2, &[0; 15]).is_err());
+        assert!(texture_pixels(0, 2, &[]).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+//! Renders a few frames through the whole pipeline and checks that the
+//! validation layer stayed quiet.
+//!
+//! This needs a Vulkan driver and an X server, so it's ignored by default.
+//! Without a GPU or display, lavapipe and Xvfb do the job:
+//!
+//! ```sh
+//! VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json \
+//!     xvfb-run cargo test --test smoke -- --ignored
+//! ```
+#![cfg(unix)]
+
+use glam::Mat4;
+use winit::event_loop::EventLoop;
+use winit::platform::unix::EventLoopExtUnix;
+
+use vulkan_tutorial_rs::{init_window, CliArgs, VulkanApp, CUBE_INDICES, CUBE_VERTICES};
+
+const FRAMES: usize = 10;
+
+#[test]
+#[ignore]
+fn renders_frames_without_validation_errors() {
+    // Surface creation goes through Xlib, so don't let winit pick Wayland
+    let event_loop = EventLoop::new_x11().expect("Smoke test needs an X server");
+    let cli_args = CliArgs::default();
+    let window = init_window(&event_loop, &cli_args);
+
+    let mut app = VulkanApp::new(&window, &cli_args).expect("Failed to set up Vulkan");
+    // Removed while frames that draw it are still in flight
+    let cube = app.add_object(&CUBE_VERTICES, &CUBE_INDICES, Mat4::IDENTITY);
+    let checkers = [[255u8; 4], [0, 0, 0, 255], [0, 0, 0, 255], [255; 4]].concat();
+    let texture = app
+        .add_texture(2, 2, &checkers)
+        .expect("Failed to add texture");
+    assert!(app.set_object_texture(cube, Some(texture)));
+    for frame in 0..FRAMES {
+        if frame == FRAMES / 2 {
+            assert!(app.remove_object(cube));
+        }
+        app.draw_frame().expect("Lost the device");
+    }
+    drop(app);
+
+    assert_eq!(
+        VulkanApp::validation_error_count(),
+        0,
+        "Validation errors were reported, see the output above"
+    );
+}